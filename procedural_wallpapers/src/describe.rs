@@ -0,0 +1,224 @@
+//! The `list` and `describe` commands, printing information about the available modes
+use serde_json::{json, Value};
+use std::io::Write;
+use wallpapers::algorithms::{Parameter, ParameterValue};
+use wallpapers::registry::{Registry, RegistryEntry};
+use wallpapers::{Error, Rng};
+
+/// Print all modes of the registry with a one-line description to the given output
+pub fn list<R: Rng>(
+    registry: &Registry<R>,
+    names_only: bool,
+    json: bool,
+    out: &mut impl Write,
+) -> Result<(), Error> {
+    if json {
+        let modes: Vec<Value> = registry
+            .entries()
+            .map(|entry| {
+                json!({
+                    "name": entry.name,
+                    "description": entry.description,
+                    "author": entry.author,
+                })
+            })
+            .collect();
+        writeln!(out, "{}", Value::Array(modes))?;
+    } else if names_only {
+        for name in registry.names() {
+            writeln!(out, "{}", name)?;
+        }
+    } else {
+        let width = registry.names().map(str::len).max().unwrap_or(0);
+        for entry in registry.entries() {
+            writeln!(
+                out,
+                "{:<width$}  {}",
+                entry.name,
+                entry.description,
+                width = width
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Print the parameters and supported image sizes of the given mode to the given output
+pub fn describe<R: Rng>(
+    registry: &Registry<R>,
+    mode: &str,
+    json: bool,
+    out: &mut impl Write,
+) -> Result<(), Error> {
+    let entry: &RegistryEntry<R> = registry
+        .get(mode)
+        .ok_or_else(|| Error::UnknownAlgorithm(mode.to_string()))?;
+    let algorithm = entry.create();
+    let (min_width, min_height) = algorithm.min_dimensions();
+    let tileable = algorithm.supports_tiling();
+    let transparent = algorithm.supports_transparency();
+    let parameters = entry.parameters();
+    if json {
+        let description = json!({
+            "name": entry.name,
+            "description": entry.description,
+            "author": entry.author,
+            "min_width": min_width,
+            "min_height": min_height,
+            "tileable": tileable,
+            "transparent": transparent,
+            "parameters": parameters.iter().map(parameter_to_json).collect::<Vec<Value>>(),
+        });
+        writeln!(out, "{}", description)?;
+        return Ok(());
+    }
+    writeln!(out, "{} - {}", entry.name, entry.description)?;
+    writeln!(out, "Author: {}", entry.author)?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "Supported image sizes: at least {}x{} pixels",
+        min_width, min_height
+    )?;
+    writeln!(out, "Tileable: {}", if tileable { "yes" } else { "no" })?;
+    writeln!(
+        out,
+        "Transparent background: {}",
+        if transparent { "yes" } else { "no" }
+    )?;
+    writeln!(out)?;
+    if parameters.is_empty() {
+        writeln!(out, "This mode has no tunable parameters.")?;
+        return Ok(());
+    }
+    writeln!(
+        out,
+        "Parameters (set with --param {}.NAME=VALUE):",
+        entry.name
+    )?;
+    for parameter in &parameters {
+        write!(
+            out,
+            "  {} ({}, default: {}",
+            parameter.name,
+            parameter.kind(),
+            parameter.default
+        )?;
+        if let Some((min, max)) = parameter.range {
+            write!(out, ", range: [{}, {}]", min, max)?;
+        }
+        if let Some(choices) = parameter.choices {
+            write!(out, ", one of: {}", choices.join(", "))?;
+        }
+        writeln!(out, ")")?;
+        writeln!(out, "      {}", parameter.description)?;
+    }
+    Ok(())
+}
+
+/// Convert a parameter value to JSON. Colors are represented as hex strings.
+fn value_to_json(value: &ParameterValue) -> Value {
+    match value {
+        ParameterValue::Integer(i) => json!(i),
+        ParameterValue::Float(x) => json!(x),
+        ParameterValue::Boolean(b) => json!(b),
+        ParameterValue::Color(_) | ParameterValue::Choice(_) => json!(value.to_string()),
+    }
+}
+
+fn parameter_to_json(parameter: &Parameter) -> Value {
+    json!({
+        "name": parameter.name,
+        "type": parameter.kind().to_string(),
+        "default": value_to_json(&parameter.default),
+        "min": parameter.range.map(|(min, _)| min),
+        "max": parameter.range.map(|(_, max)| max),
+        "choices": parameter.choices,
+        "description": parameter.description,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wallpapers::ChaCha8Rng;
+
+    fn output(print: impl FnOnce(&mut Vec<u8>) -> Result<(), Error>) -> String {
+        let mut out = vec![];
+        print(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn list_prints_all_modes() {
+        let registry: Registry<ChaCha8Rng> = Registry::builtin();
+        let names: Vec<&str> = registry.names().collect();
+        let text = output(|out| list(&registry, true, false, out));
+        assert_eq!(text.lines().collect::<Vec<_>>(), names);
+        let json: Value =
+            serde_json::from_str(&output(|out| list(&registry, false, true, out))).unwrap();
+        let modes = json.as_array().unwrap();
+        assert_eq!(modes.len(), names.len());
+        for (mode, name) in modes.iter().zip(&names) {
+            assert_eq!(mode["name"], *name);
+            assert!(mode["description"].is_string() && mode["author"].is_string());
+        }
+    }
+
+    #[test]
+    fn describe_prints_the_minimum_dimensions_and_parameters() {
+        let registry: Registry<ChaCha8Rng> = Registry::builtin();
+        let text = output(|out| describe(&registry, "lightning", false, out));
+        assert!(
+            text.contains("Supported image sizes: at least 1x4 pixels"),
+            "{}",
+            text
+        );
+        let json: Value =
+            serde_json::from_str(&output(|out| describe(&registry, "squares", true, out))).unwrap();
+        assert_eq!(json["name"], "squares");
+        assert_eq!(
+            (&json["min_width"], &json["min_height"]),
+            (&json!(1), &json!(1))
+        );
+        assert_eq!(json["tileable"], true);
+        let parameters = json["parameters"].as_array().unwrap();
+        assert_eq!(
+            parameters.len(),
+            registry.get("squares").unwrap().parameters().len()
+        );
+        for parameter in parameters {
+            let keys: Vec<&String> = parameter.as_object().unwrap().keys().collect();
+            assert_eq!(
+                keys,
+                [
+                    "choices",
+                    "default",
+                    "description",
+                    "max",
+                    "min",
+                    "name",
+                    "type"
+                ]
+            );
+        }
+        let traversal = parameters
+            .iter()
+            .find(|p| p["name"] == "traversal")
+            .unwrap();
+        assert_eq!(traversal["type"], "choice");
+        assert!(traversal["choices"]
+            .as_array()
+            .unwrap()
+            .contains(&json!("spiral")));
+    }
+
+    #[test]
+    fn describing_an_unknown_mode_fails() {
+        let registry: Registry<ChaCha8Rng> = Registry::builtin();
+        let mut out = vec![];
+        let result = describe(&registry, "nosuchmode", false, &mut out);
+        assert!(matches!(result, Err(Error::UnknownAlgorithm(name)) if name == "nosuchmode"));
+        assert!(out.is_empty());
+    }
+}
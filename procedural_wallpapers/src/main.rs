@@ -1,107 +1,350 @@
 //! Procedural Wallpapers in Rust - A command-line interface to generate wallpapers
 //!
+use clap::builder::PossibleValuesParser;
 use clap::ValueHint;
-use clap::{Parser, ValueEnum};
-use std::borrow::BorrowMut;
-use std::path::PathBuf;
-use wallpapers::algorithms::Algorithm;
-use wallpapers::algorithms::*;
+use clap::{Args as ClapArgs, Parser, Subcommand};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use wallpapers::algorithms::ParameterValue;
+use wallpapers::metadata;
+use wallpapers::monitors::Layout;
 use wallpapers::patterns::pattern::Patterns;
-use wallpapers::{ChaCha8Rng, ImageBuffer, RgbImage, Rng, SeedableRng};
+use wallpapers::recipe::Recipe;
+use wallpapers::registry::Registry;
+use wallpapers::seed::Seed;
+use wallpapers::{ChaCha8Rng, Error};
 
-#[derive(Debug, Copy, Clone, PartialEq, ValueEnum)]
-enum Mode {
-    #[clap(name = "clouds")]
-    Clouds,
-    #[clap(name = "flow")]
-    Flow,
-    #[clap(name = "islands")]
-    Islands,
-    #[clap(name = "lightning")]
-    Lightning,
-    #[clap(name = "nearestpoint")]
-    NearestPoint,
-    #[clap(name = "tangles")]
-    Tangles,
-    #[clap(name = "cellularone")]
-    CellularOne,
-    #[clap(name = "squares")]
-    Squares,
-    #[clap(name = "squareshor")]
-    SquaresHor,
-    #[clap(name = "squaresver")]
-    SquaresVer,
-    #[clap(name = "squaresdiag")]
-    SquaresDiag,
-    #[clap(name = "squares2")]
-    Squares2,
-    #[clap(name = "squares2h")]
-    Squares2H,
-    #[clap(name = "squares2v")]
-    Squares2V,
-    #[clap(name = "nearestgradient")]
-    NearestGradient,
-    #[clap(name = "pattern")]
-    Pattern,
+/// Get the names of all available modes
+fn mode_names() -> Vec<&'static str> {
+    Registry::<ChaCha8Rng>::builtin().names().collect()
 }
 
-impl Mode {
-    pub fn to_algorithm<R: Rng>(self) -> Box<dyn Algorithm<R>> {
-        match self {
-            Mode::Clouds => Box::new(Clouds {}),
-            Mode::Flow => Box::new(Flow::default()),
-            Mode::Islands => Box::new(Islands::default()),
-            Mode::Lightning => Box::new(Lightning::default()),
-            Mode::NearestPoint => Box::new(NearestPoint::default()),
-            Mode::Tangles => Box::new(Tangles::default()),
-            Mode::CellularOne => Box::new(CellularOne::default()),
-            Mode::Squares => Box::new(SquaresOneDirection::new_nodir()),
-            Mode::SquaresHor => Box::new(SquaresOneDirection::new_horiz()),
-            Mode::SquaresVer => Box::new(SquaresOneDirection::new_vert()),
-            Mode::SquaresDiag => Box::new(SquaresOneDirection::new_diag()),
-            Mode::Squares2 => Box::new(SquaresOneDirection::new_nodir_randomized()),
-            Mode::Squares2H => Box::new(SquaresOneDirection::new_horiz_randomized()),
-            Mode::Squares2V => Box::new(SquaresOneDirection::new_vert_randomized()),
-            Mode::NearestGradient => Box::new(NearestPoint::new_soft()),
-            Mode::Pattern => Box::new(Patterns::diamond()),
-        }
-    }
-}
+mod describe;
+mod recipefile;
 
 /// Generate wallpapers procedurally with the given algorithm
 #[derive(Parser, PartialEq, Debug)]
 #[clap(author, version, about, long_about = None, disable_help_flag = true)]
+#[clap(args_conflicts_with_subcommands = true)]
 struct Args {
+    #[clap(subcommand)]
+    command: Option<Command>,
+    #[clap(flatten)]
+    generate: Option<GenerateArgs>,
+    /// Open the command-line help
+    #[clap(long, action = clap::ArgAction::HelpLong)]
+    help: Option<bool>,
+}
+
+#[derive(Subcommand, PartialEq, Debug)]
+enum Command {
+    /// List all available modes with a short description
+    List {
+        /// Only print the mode names, one per line
+        #[clap(long)]
+        names_only: bool,
+        /// Print the list as JSON
+        #[clap(long, conflicts_with = "names_only")]
+        json: bool,
+    },
+    /// Show the tunable parameters and supported image sizes of a mode
+    Describe {
+        /// The mode to describe
+        #[clap(value_parser = PossibleValuesParser::new(mode_names()))]
+        mode: String,
+        /// Print the description as JSON
+        #[clap(long)]
+        json: bool,
+    },
+    /// Render a wallpaper from a recipe file (TOML, or JSON if the file name ends with .json)
+    Render {
+        /// The recipe file
+        #[clap(value_hint = ValueHint::FilePath)]
+        recipe: PathBuf,
+        /// The output file to save, overriding the output given in the recipe
+        #[clap(short, long, value_hint = ValueHint::FilePath)]
+        output: Option<PathBuf>,
+    },
+    /// Render a wallpaper again from the generation metadata embedded into it, optionally at a
+    /// different size
+    #[clap(disable_help_flag = true)]
+    Reproduce {
+        /// A wallpaper that was generated by this program (PNG, JPEG or WebP)
+        #[clap(value_hint = ValueHint::FilePath)]
+        image: PathBuf,
+        /// Desired width (pixels), defaults to the width of the original wallpaper
+        #[clap(short, long, value_parser)]
+        width: Option<u32>,
+        /// Desired height (pixels), defaults to the height of the original wallpaper
+        #[clap(short, long, value_parser)]
+        height: Option<u32>,
+        /// The output file to save, defaults to IMAGE-WIDTHxHEIGHT next to the original wallpaper
+        #[clap(short, long, value_hint = ValueHint::FilePath)]
+        output: Option<PathBuf>,
+        /// Open the command-line help
+        #[clap(long, action = clap::ArgAction::HelpLong)]
+        help: Option<bool>,
+    },
+}
+
+/// Arguments to generate a single wallpaper
+#[derive(ClapArgs, PartialEq, Debug)]
+struct GenerateArgs {
     /// Image generation mode
-    #[clap(short, long)]
-    mode: Mode,
+    #[clap(short, long, value_parser = PossibleValuesParser::new(mode_names()))]
+    mode: String,
     /// Desired width (pixels) of the generated image
     #[clap(short, long, value_parser, default_value_t = 1920)]
     width: u32,
     /// Desired height (pixels) of the generated image
     #[clap(short, long, value_parser, default_value_t = 1080)]
     height: u32,
-    /// Seed for the random number generator. If a seed of 0 is given, no seed is used
-    #[clap(short, long, value_parser, default_value_t = 0)]
-    seed: i32,
+    /// Seed for the random number generator, given as decimal number, as hex number (0x...) or
+    /// as arbitrary string. If no seed is given, a random seed is picked and printed
+    #[clap(short, long, value_parser, allow_hyphen_values = true)]
+    seed: Option<Seed>,
     /// The output file to save
     #[clap(short, long, value_hint = ValueHint::FilePath)]
     output: PathBuf,
-    /// Open the command-line help
-    #[clap(long, action = clap::ArgAction::HelpLong)]
-    help: Option<bool>,
+    /// Render one continuous wallpaper spanning the given monitors, e.g.
+    /// 2560x1440+0+0,1920x1080+2560+180. One image per monitor is saved, numbered after the output
+    /// file name
+    #[clap(long, value_name = "WxH+X+Y,...", conflicts_with_all = ["width", "height"])]
+    monitors: Option<Layout>,
+    /// The gap in pixels between adjacent monitors, to continue the wallpaper behind the monitor
+    /// frames
+    #[clap(long, value_parser, default_value_t = 0, requires = "monitors")]
+    bezel: u32,
+    /// Render a seamlessly tileable wallpaper, whose right edge continues at the left edge and
+    /// whose bottom edge continues at the top edge. Not all modes support this, see describe
+    #[clap(long)]
+    tileable: bool,
+    /// Render a transparent background and save the wallpaper with an alpha channel, e.g. as PNG.
+    /// Not all modes support this, see describe
+    #[clap(long)]
+    transparent: bool,
+    /// A pattern file that is drawn instead of the built-in pattern of the pattern mode, either
+    /// ASCII art with a legend or a small image with a transparent background
+    #[clap(long, value_hint = ValueHint::FilePath)]
+    pattern: Option<PathBuf>,
+    /// A pre-defined pattern that is drawn instead of the built-in pattern of the pattern mode
+    #[clap(long, value_parser = PossibleValuesParser::new(Patterns::NAMES), conflicts_with = "pattern")]
+    pattern_name: Option<String>,
+    /// Set an algorithm parameter, e.g. flow.num_particles=12000. Can be given multiple times
+    #[clap(long = "param", value_name = "MODE.NAME=VALUE")]
+    params: Vec<String>,
+    /// Save a recipe file (TOML, or JSON if the file name ends with .json) to render the
+    /// wallpaper again later
+    #[clap(long, value_hint = ValueHint::FilePath)]
+    save_recipe: Option<PathBuf>,
 }
 
-fn main() {
-    let args = Args::parse();
-    let mut img: RgbImage = ImageBuffer::new(args.width, args.height);
-    let mut algorithm: Box<dyn Algorithm<ChaCha8Rng>> = args.mode.to_algorithm::<ChaCha8Rng>();
-    let mut rng = if args.seed != 0 {
-        ChaCha8Rng::seed_from_u64(args.seed as u64)
-    } else {
-        ChaCha8Rng::from_entropy()
+/// Parse all parameters given as MODE.NAME=VALUE for the given mode
+fn parse_parameters(
+    mode_name: &str,
+    params: &[String],
+) -> Result<BTreeMap<String, ParameterValue>, Error> {
+    let mut parsed = BTreeMap::new();
+    for param in params {
+        let invalid = |reason: String| Error::InvalidParameter {
+            name: param.clone(),
+            reason,
+        };
+        let (key, value) = param
+            .split_once('=')
+            .ok_or_else(|| invalid("Expected MODE.NAME=VALUE".to_string()))?;
+        let (param_mode, name) = key
+            .split_once('.')
+            .ok_or_else(|| invalid("Expected MODE.NAME=VALUE".to_string()))?;
+        if param_mode != mode_name {
+            return Err(invalid(format!(
+                "The parameter is meant for mode {}, but the mode is {}",
+                param_mode, mode_name
+            )));
+        }
+        parsed.insert(name.to_string(), ParameterValue::parse(name, value)?);
+    }
+    Ok(parsed)
+}
+
+/// Create the recipe described by the given arguments
+fn recipe_from_args(args: &GenerateArgs) -> Result<Recipe, Error> {
+    let mut recipe = Recipe::new(&args.mode, args.width, args.height);
+    recipe.seed = args.seed;
+    recipe.output = Some(args.output.clone());
+    recipe.monitors = args.monitors.clone();
+    recipe.bezel = args.bezel;
+    recipe.tileable = args.tileable;
+    recipe.transparent = args.transparent;
+    recipe.pattern = args.pattern.clone();
+    recipe.pattern_name = args.pattern_name.clone();
+    recipe.params = parse_parameters(&args.mode, &args.params)?;
+    Ok(recipe)
+}
+
+/// Pick a random seed for the given recipe if it does not have one, and print it
+fn pick_seed(recipe: &mut Recipe) {
+    if recipe.seed.is_none() {
+        let seed = Seed::random();
+        println!("Seed: {}", seed);
+        recipe.seed = Some(seed);
+    }
+}
+
+/// Render the given recipe and save it to the given output file, or the output of the recipe
+fn render(
+    registry: &Registry<ChaCha8Rng>,
+    recipe: &Recipe,
+    output: Option<&Path>,
+) -> Result<(), Error> {
+    let output = output
+        .or(recipe.output.as_deref())
+        .ok_or_else(|| Error::InvalidParameter {
+            name: "output".to_string(),
+            reason: "No output file given".to_string(),
+        })?;
+    let format = recipe.format_of(output)?;
+    if !metadata::is_supported(format) {
+        eprintln!(
+            "Warning: {} files cannot store generation metadata, {} cannot be reproduced",
+            format!("{:?}", format).to_uppercase(),
+            output.display()
+        );
+    }
+    let img = recipe.render(registry)?;
+    match &recipe.monitors {
+        Some(layout) => {
+            for (i, monitor_img) in layout.split(&img, recipe.bezel)?.iter().enumerate() {
+                recipe.save(monitor_img, &numbered_path(output, i + 1))?;
+            }
+            Ok(())
+        },
+        None => recipe.save(&img, output),
+    }
+}
+
+/// Get the given path with a number appended to the file name, e.g. wallpaper-1.png
+fn numbered_path(path: &Path, number: usize) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{}-{}.{}", stem, number, extension.to_string_lossy()),
+        None => format!("{}-{}", stem, number),
     };
-    algorithm.build(&mut rng, img.borrow_mut()).unwrap();
-    img.save(args.output).unwrap();
-    println!("Hello, world! The mode is {:?}", args.mode);
+    path.with_file_name(name)
+}
+
+/// Render the wallpaper embedded into the given image again with the given size
+fn reproduce(
+    registry: &Registry<ChaCha8Rng>,
+    image: &Path,
+    width: Option<u32>,
+    height: Option<u32>,
+    output: Option<&Path>,
+) -> Result<(), Error> {
+    let mut recipe = Recipe::from_image(image)?;
+    if recipe.seed.is_none() {
+        eprintln!(
+            "Warning: {} was generated without a seed, the result will differ",
+            image.display()
+        );
+        pick_seed(&mut recipe);
+    }
+    if width.is_some() || height.is_some() {
+        // A new size replaces the monitor layout by a single image
+        (recipe.width, recipe.height) = recipe.canvas_size()?;
+        recipe.monitors = None;
+    }
+    recipe.width = width.unwrap_or(recipe.width);
+    recipe.height = height.unwrap_or(recipe.height);
+    let output = match output {
+        Some(output) => output.to_path_buf(),
+        None => {
+            let stem = image.file_stem().unwrap_or_default().to_string_lossy();
+            let (width, height) = recipe.canvas_size()?;
+            let mut name = format!("{}-{}x{}", stem, width, height);
+            if let Some(extension) = image.extension() {
+                name = format!("{}.{}", name, extension.to_string_lossy());
+            }
+            image.with_file_name(name)
+        },
+    };
+    render(registry, &recipe, Some(&output))
+}
+
+/// Run the command given on the command line
+fn run(args: &Args) -> Result<(), Error> {
+    let registry: Registry<ChaCha8Rng> = Registry::builtin();
+    match (&args.command, &args.generate) {
+        (Some(Command::List { names_only, json }), _) => {
+            describe::list(&registry, *names_only, *json, &mut std::io::stdout())
+        },
+        (Some(Command::Describe { mode, json }), _) => {
+            describe::describe(&registry, mode, *json, &mut std::io::stdout())
+        },
+        (Some(Command::Render { recipe, output }), _) => {
+            let mut recipe = recipefile::load(recipe)?;
+            pick_seed(&mut recipe);
+            render(&registry, &recipe, output.as_deref())
+        },
+        (
+            Some(Command::Reproduce {
+                image,
+                width,
+                height,
+                output,
+                ..
+            }),
+            _,
+        ) => reproduce(&registry, image, *width, *height, output.as_deref()),
+        (None, Some(generate_args)) => {
+            let mut recipe = recipe_from_args(generate_args)?;
+            pick_seed(&mut recipe);
+            if let Some(path) = &generate_args.save_recipe {
+                recipefile::save(&recipe, path)?;
+            }
+            render(&registry, &recipe, None)?;
+            println!("Hello, world! The mode is {}", generate_args.mode);
+            Ok(())
+        },
+        (None, None) => Err(Error::InvalidParameter {
+            name: "mode".to_string(),
+            reason: "No mode given, see --help".to_string(),
+        }),
+    }
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            ExitCode::FAILURE
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seeds_may_start_with_a_hyphen() {
+        for seed in ["-5", "-monday-standup", "123456"] {
+            let args = Args::try_parse_from([
+                "procedural_wallpapers",
+                "-m",
+                "clouds",
+                "-s",
+                seed,
+                "-o",
+                "clouds.png",
+            ])
+            .unwrap();
+            let generate = args.generate.unwrap();
+            assert_eq!(generate.seed, Some(seed.parse().unwrap()));
+            assert_eq!(generate.output, PathBuf::from("clouds.png"));
+        }
+    }
 }
@@ -0,0 +1,36 @@
+//! Reading and writing recipe files. Files ending in `.json` are JSON files, all other recipe
+//! files are TOML files.
+use std::io::ErrorKind;
+use std::path::Path;
+use wallpapers::recipe::Recipe;
+use wallpapers::Error;
+
+fn is_json(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("json"))
+}
+
+fn invalid_data(err: impl std::error::Error + Send + Sync + 'static) -> Error {
+    Error::Io(std::io::Error::new(ErrorKind::InvalidData, err))
+}
+
+/// Load a recipe from the given file
+pub fn load(path: &Path) -> Result<Recipe, Error> {
+    let content = std::fs::read_to_string(path)?;
+    if is_json(path) {
+        serde_json::from_str(&content).map_err(invalid_data)
+    } else {
+        toml::from_str(&content).map_err(invalid_data)
+    }
+}
+
+/// Save a recipe to the given file
+pub fn save(recipe: &Recipe, path: &Path) -> Result<(), Error> {
+    let content = if is_json(path) {
+        serde_json::to_string_pretty(recipe).map_err(invalid_data)?
+    } else {
+        toml::to_string_pretty(recipe).map_err(invalid_data)?
+    };
+    std::fs::write(path, content)?;
+    Ok(())
+}
@@ -1,4 +1,8 @@
-use crate::algorithms::Algorithm;
+use crate::algorithms::parameters::unknown_parameter;
+use crate::algorithms::{check_min_dimensions, Algorithm, Parameter, ParameterValue};
+use crate::seed::Streams;
+use crate::utils::scale::Scale;
+use crate::Error;
 use image::{Rgb, RgbImage};
 use rand::Rng;
 use std::cmp::{max, min};
@@ -9,16 +13,25 @@ use std::collections::HashSet;
 ///
 /// The algorithm works as follows:
 /// 1. Assign a certain number of initial pixels a random color, at a random position
-/// 2. Iteratively visit each unvisited pixel that has at least one neighbor.
+/// 2. Iteratively visit each unvisited pixel that has at least one diagonal neighbor.
 ///    There is a P% chance that a pixel is skipped in that step, to be processed later.
 ///    Each pixel is then assigned the average color of each adjacent visited pixel, before a
 ///    certain number of bytes B is subtracted from each processed pixel.
 /// 3. Iteratively repeat that step, until all pixels have been visited.
+///
+/// Diagonal neighbors only reach the pixels of one checkerboard color. If all initial pixels have
+/// the same checkerboard color, the pixels of the other color are colored from all adjacent
+/// pixels as soon as no more pixel has a diagonal neighbor.
 pub struct CellularOne {
     /// If true, all pixels have been visited once
     all_visited: bool,
     num_visited: usize,
+    /// The probability that a pixel is skipped in an iteration
     pixel_skip_probability: f64,
+    /// The image area in square virtual units per initial random point
+    pixels_per_point: u32,
+    /// If true, pixels are only colored from their diagonal neighbors
+    diagonal_only: bool,
 }
 
 impl Default for CellularOne {
@@ -27,16 +40,29 @@ impl Default for CellularOne {
             all_visited: false,
             num_visited: 0,
             pixel_skip_probability: 0.01,
+            pixels_per_point: 20000,
+            diagonal_only: true,
         }
     }
 }
 
-/// Get the new color of a pixel, if at least one adjacent pixel has a color
-fn get_new_color(x: u32, y: u32, img: &RgbImage, visited: &[Vec<bool>]) -> Option<[u8; 3]> {
+/// Get the new color of a pixel, if at least one diagonal (or, if not diagonal_only, adjacent)
+/// pixel has a color
+fn get_new_color(
+    (x, y): (u32, u32),
+    img: &RgbImage,
+    visited: &[Vec<bool>],
+    diagonal_only: bool,
+) -> Option<[u8; 3]> {
     let mut new_color: Vec<[u8; 3]> = vec![];
     for xx in max(0, (x as i32) - 1)..=min(img.width() as i32 - 1, (x as i32) + 1) {
         for yy in max(0, (y as i32) - 1)..=min(img.height() as i32 - 1, (y as i32) + 1) {
-            if visited[xx as usize][yy as usize] && xx != x as i32 && yy != y as i32 {
+            let neighbor = if diagonal_only {
+                xx != x as i32 && yy != y as i32
+            } else {
+                xx != x as i32 || yy != y as i32
+            };
+            if visited[xx as usize][yy as usize] && neighbor {
                 new_color.push(
                     img.get_pixel(xx.try_into().unwrap(), yy.try_into().unwrap())
                         .0,
@@ -69,18 +95,23 @@ impl CellularOne {
     /// Create the initial random population of points
     fn populate_points(
         &mut self,
-        rng: &mut impl Rng,
+        streams: &Streams,
         num_points: usize,
         img: &mut RgbImage,
         visited: &mut [Vec<bool>],
     ) {
+        let mut positions = streams.rng("points");
+        let mut palette = streams.rng("palette");
+        let scale = Scale::of(img);
         for _ in 0..num_points {
-            let x = rng.gen_range(0..img.width());
-            let y = rng.gen_range(0..img.height());
+            let (x, y) = scale.pixel_at(
+                positions.gen_range(0.0..scale.width()),
+                positions.gen_range(0.0..scale.height()),
+            );
             let color: [u8; 3] = [
-                rng.gen_range(0..128) + 128,
-                rng.gen_range(0..128) + 128,
-                rng.gen_range(0..128) + 128,
+                palette.gen_range(0..128) + 128,
+                palette.gen_range(0..128) + 128,
+                palette.gen_range(0..128) + 128,
             ];
             let pixel = img.get_pixel_mut(x, y);
             *pixel = Rgb(color);
@@ -93,13 +124,15 @@ impl CellularOne {
     fn iterate_once(&mut self, rng: &mut impl Rng, img: &mut RgbImage, visited: &mut [Vec<bool>]) {
         // Keep track of newly-visited pixels and apply them later
         let mut newly_visited: HashSet<(u32, u32)> = HashSet::new();
+        let mut has_neighbors = false;
         for x in 0..img.width() {
             for y in 0..img.height() {
                 // Skip visited pixels, they should be kept as-is
                 if visited[x as usize][y as usize] {
                     continue;
                 }
-                if let Some(new_color) = get_new_color(x, y, img, visited) {
+                if let Some(new_color) = get_new_color((x, y), img, visited, self.diagonal_only) {
+                    has_neighbors = true;
                     // Skip each pixel with a certain probability
                     if rng.gen_bool(self.pixel_skip_probability) {
                         continue;
@@ -119,6 +152,9 @@ impl CellularOne {
         }
         if self.num_visited >= (img.width() * img.height()) as usize {
             self.all_visited = true;
+        } else if !has_neighbors {
+            // The remaining pixels cannot be reached diagonally
+            self.diagonal_only = false;
         }
         if cfg!(debug_assertions) {
             println!("Visited {} pixels ({} more)", self.num_visited, num_new);
@@ -127,17 +163,23 @@ impl CellularOne {
 }
 
 impl<R: Rng> Algorithm<R> for CellularOne {
-    fn build(&mut self, rng: &mut R, img: &mut RgbImage) -> Result<(), String> {
+    fn build(&mut self, rng: &mut R, img: &mut RgbImage) -> Result<(), Error> {
+        check_min_dimensions::<R, _>(self, img, "CellularOne")?;
         let mut visited: Vec<Vec<bool>> =
             vec![vec![false; img.height() as usize]; img.width() as usize];
+        self.diagonal_only = true;
         // Build initial population
-        let num_points: usize = max(2, img.width() * img.height() / 20000)
-            .try_into()
-            .expect("There were too many pixels to handle!");
-        self.populate_points(rng, num_points, img, &mut visited);
+        // The initial points are placed in virtual units, the growth works on pixels
+        let num_points = max(
+            2,
+            (Scale::of(img).area() / self.pixels_per_point as f64) as usize,
+        );
+        let streams = Streams::from_rng(rng);
+        self.populate_points(&streams, num_points, img, &mut visited);
+        let mut growth = streams.rng("growth");
         let mut num_iter: usize = 0;
         while !self.all_visited {
-            self.iterate_once(rng, img, &mut visited);
+            self.iterate_once(&mut growth, img, &mut visited);
             num_iter += 1;
         }
         if cfg!(debug_assertions) {
@@ -145,4 +187,62 @@ impl<R: Rng> Algorithm<R> for CellularOne {
         }
         Ok(())
     }
+    fn parameters(&self) -> Vec<Parameter> {
+        vec![
+            Parameter::new(
+                "pixel_skip_probability",
+                "The probability that a pixel is skipped in an iteration",
+                ParameterValue::Float(self.pixel_skip_probability),
+            )
+            .with_range(0.0, 0.99),
+            Parameter::new(
+                "pixels_per_point",
+                "The image area per initial random point in pixels of a 1080p image",
+                ParameterValue::Integer(self.pixels_per_point as i64),
+            )
+            .with_range(1000.0, 100_000_000.0),
+        ]
+    }
+    fn set_parameter(&mut self, name: &str, value: &ParameterValue) -> Result<(), Error> {
+        match (name, value) {
+            ("pixel_skip_probability", ParameterValue::Float(v)) => {
+                self.pixel_skip_probability = *v
+            },
+            ("pixels_per_point", ParameterValue::Integer(v)) => self.pixels_per_point = *v as u32,
+            _ => return Err(unknown_parameter(name)),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ChaCha8Rng, SeedableRng};
+
+    #[test]
+    fn pixels_of_both_checkerboard_colors_are_visited() {
+        let mut algorithm = CellularOne {
+            pixel_skip_probability: 0.0,
+            num_visited: 1,
+            ..CellularOne::default()
+        };
+        let mut img = RgbImage::new(6, 6);
+        img.put_pixel(2, 2, Rgb([200, 100, 50]));
+        let mut visited = vec![vec![false; 6]; 6];
+        visited[2][2] = true;
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        // The color spreads diagonally first
+        algorithm.iterate_once(&mut rng, &mut img, &mut visited);
+        assert_eq!(algorithm.num_visited, 5);
+        assert!(visited[1][1] && visited[3][3] && !visited[2][3]);
+        for _ in 0..20 {
+            if algorithm.all_visited {
+                break;
+            }
+            algorithm.iterate_once(&mut rng, &mut img, &mut visited);
+        }
+        assert!(algorithm.all_visited);
+        assert!(visited.iter().flatten().all(|visited| *visited));
+    }
 }
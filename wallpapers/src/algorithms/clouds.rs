@@ -1,5 +1,9 @@
-use crate::algorithms::Algorithm;
+use crate::algorithms::parameters::unknown_parameter;
+use crate::algorithms::{check_min_dimensions, Algorithm, Parameter, ParameterValue};
+use crate::seed::Streams;
 use crate::utils::perlin::Perlin;
+use crate::utils::scale::Scale;
+use crate::Error;
 use image::RgbImage;
 use rand::Rng;
 
@@ -7,17 +11,62 @@ fn sigmoid(x: f64) -> f64 {
     ((2.0 * x - 0.5).tanh() * 2.0).tanh()
 }
 
-pub struct Clouds {}
+pub struct Clouds {
+    /// The number of noise layers with increasing frequency
+    octaves: u32,
+    /// If true, the clouds continue seamlessly at the opposite image border
+    tileable: bool,
+}
+
+impl Default for Clouds {
+    fn default() -> Self {
+        Clouds {
+            octaves: 7,
+            tileable: false,
+        }
+    }
+}
 
 impl<R: Rng> Algorithm<R> for Clouds {
-    fn build(&mut self, rng: &mut R, img: &mut RgbImage) -> Result<(), String> {
-        let mut perlin = Perlin::new(img.width() as usize, img.height() as usize);
-        perlin.regenerate_noise(rng);
-        let freq: f64 = 0.002 * rng.gen::<u8>() as f64 / u8::MAX as f64 + 0.003;
+    fn build(&mut self, rng: &mut R, img: &mut RgbImage) -> Result<(), Error> {
+        check_min_dimensions::<R, _>(self, img, "Clouds")?;
+        let streams = Streams::from_rng(rng);
+        let scale = Scale::of(img);
+        let freq: f64 = 0.002 * streams.rng("style").gen::<u8>() as f64 / u8::MAX as f64 + 0.003;
+        let mut perlin = Perlin::for_fractal(scale.width(), scale.height(), freq, self.octaves);
+        perlin.regenerate_noise(&mut streams.rng("noise"));
+        if self.tileable {
+            perlin.set_period(scale.width(), scale.height());
+        }
         for (x, y, pixel) in img.enumerate_pixels_mut() {
-            let val: f64 = 0.5 * sigmoid(perlin.fractal(x as f64, y as f64, freq, 7)?) + 0.5;
+            let (x, y) = (scale.to_units(x as f64), scale.to_units(y as f64));
+            let val: f64 = 0.5 * sigmoid(perlin.fractal(x, y, freq, self.octaves)?) + 0.5;
             *pixel = image::Rgb([(val * 230.0) as u8 + 25, (val * 255.0) as u8, 255]);
         }
         Ok(())
     }
+    fn min_dimensions(&self) -> (u32, u32) {
+        (2, 2)
+    }
+    fn supports_tiling(&self) -> bool {
+        true
+    }
+    fn set_tileable(&mut self, tileable: bool) {
+        self.tileable = tileable;
+    }
+    fn parameters(&self) -> Vec<Parameter> {
+        vec![Parameter::new(
+            "octaves",
+            "The number of noise layers with increasing frequency",
+            ParameterValue::Integer(self.octaves as i64),
+        )
+        .with_range(1.0, 8.0)]
+    }
+    fn set_parameter(&mut self, name: &str, value: &ParameterValue) -> Result<(), Error> {
+        match (name, value) {
+            ("octaves", ParameterValue::Integer(v)) => self.octaves = *v as u32,
+            _ => return Err(unknown_parameter(name)),
+        }
+        Ok(())
+    }
 }
@@ -1,19 +1,29 @@
-use crate::algorithms::Algorithm;
+use crate::algorithms::parameters::unknown_parameter;
+use crate::algorithms::{check_min_dimensions, opaque, Algorithm, Parameter, ParameterValue};
+use crate::seed::Streams;
 use crate::utils::perlin::Perlin;
-use image::{Rgb, RgbImage};
+use crate::utils::scale::Scale;
+use crate::Error;
+use image::{Rgb, RgbImage, Rgba, RgbaImage};
 use rand::Rng;
 use std::f64::consts::PI;
 
 /// Flow
 /// Based on the FLOW algorithm implementation by Attila Bagyoni, 2018
 /// https://github.com/bagyoni/procedural-wallpapers
-
 pub struct Flow {
+    /// The number of particles that are traced through the flow field
     num_particles: u32,
+    /// The maximum path length of each particle in virtual units
     path_len: u32,
     curvature: f64,
     frequency: f64,
     signum: bool,
+    /// If true, particles that leave the image enter it again at the opposite border
+    tileable: bool,
+    /// If true, the background is transparent in images with an alpha channel, and the opacity
+    /// of the lines follows the flow
+    transparent: bool,
 }
 
 impl Default for Flow {
@@ -24,6 +34,8 @@ impl Default for Flow {
             curvature: 0.0,
             frequency: 0.0,
             signum: true,
+            tileable: false,
+            transparent: false,
         }
     }
 }
@@ -36,54 +48,170 @@ impl Flow {
             false => -1,
         }
     }
+    /// Check whether the given position in virtual units lies within the image
+    fn is_inside(&self, x: f64, y: f64, scale: &Scale) -> bool {
+        x > 0.0 && x < scale.width() && y > 0.0 && y < scale.height()
+    }
+    /// Trace a particle in steps of one pixel. Positions and the path length are in virtual units.
     fn make_single_path(
         &self,
         rng: &mut impl Rng,
         perlin: &Perlin,
         flow: &mut [Vec<f64>],
-        img: &RgbImage,
-    ) -> Result<(), String> {
-        let mut x: f64 = rng.gen_range(0..img.width()) as f64;
-        let mut y: f64 = rng.gen_range(0..img.height()) as f64;
-        let mut i: u32 = 0;
-        while i < self.path_len
-            && x > 0.0
-            && x < img.width() as f64
-            && y > 0.0
-            && y < img.height() as f64
-        {
-            flow[x as usize][y as usize] += ((self.path_len - i) as f64) / self.path_len as f64;
+        scale: &Scale,
+    ) -> Result<(), Error> {
+        let mut x: f64 = rng.gen_range(0.0..scale.width());
+        let mut y: f64 = rng.gen_range(0.0..scale.height());
+        let step = scale.units_per_pixel();
+        let mut distance: f64 = 0.0;
+        while distance < self.path_len as f64 && (self.tileable || self.is_inside(x, y, scale)) {
+            let (px, py) = scale.pixel_at(x, y);
+            flow[px as usize][py as usize] +=
+                (self.path_len as f64 - distance) / self.path_len as f64;
             let angle: f64 =
                 2.0 * PI * (perlin.fractal(x, y, self.frequency, 6)? - 0.5) * self.curvature;
-            x += angle.cos();
-            y += angle.sin();
-            i += 1;
+            x += step * angle.cos();
+            y += step * angle.sin();
+            if self.tileable {
+                x = x.rem_euclid(scale.width());
+                y = y.rem_euclid(scale.height());
+            }
+            distance += step;
         }
         Ok(())
     }
-}
-
-impl<R: Rng> Algorithm<R> for Flow {
-    fn build(&mut self, rng: &mut R, img: &mut RgbImage) -> Result<(), String> {
+    /// Trace all particles through the flow field and get the flow through each pixel and the
+    /// maximum flow
+    fn trace(&mut self, rng: &mut impl Rng, img: &RgbImage) -> Result<(Vec<Vec<f64>>, f64), Error> {
         let mut flow = vec![vec![0.0; img.height() as usize]; img.width() as usize];
-        let mut perlin: Perlin = Perlin::new(img.width() as usize, img.height() as usize);
-        perlin.regenerate_noise(rng);
-        self.signum = rng.gen_bool(0.5);
-        self.curvature = 0.3 + 0.1 * ((rng.gen::<u8>() as f64) / 0xff as f64);
-        self.frequency = 0.003 + 0.003 * ((rng.gen::<u8>() as f64) / 0xff as f64);
+        let streams = Streams::from_rng(rng);
+        let scale = Scale::of(img);
+        let mut style = streams.rng("style");
+        self.signum = style.gen_bool(0.5);
+        self.curvature = 0.3 + 0.1 * ((style.gen::<u8>() as f64) / 0xff as f64);
+        self.frequency = 0.003 + 0.003 * ((style.gen::<u8>() as f64) / 0xff as f64);
+        let mut perlin = Perlin::for_fractal(scale.width(), scale.height(), self.frequency, 6);
+        perlin.regenerate_noise(&mut streams.rng("noise"));
+        if self.tileable {
+            perlin.set_period(scale.width(), scale.height());
+        }
+        let mut particles = streams.rng("particles");
         for _ in 0..self.num_particles {
-            self.make_single_path(rng, &perlin, &mut flow, img)?;
+            self.make_single_path(&mut particles, &perlin, &mut flow, &scale)?;
         }
         let max: f64 = flow
             .iter()
             .flatten()
             .fold(f64::NEG_INFINITY, |prev, curr| prev.max(*curr));
+        // Particles that start on the image border leave no flow. Without any flow, all pixels get
+        // the gray value and opacity of no flow, instead of dividing by zero.
+        Ok((flow, max.max(f64::MIN_POSITIVE)))
+    }
+    /// Get the gray value of a pixel with the given flow
+    fn gray_value(&self, flow: f64, max: f64) -> u8 {
+        // Wrap around like the original C++ implementation, instead of saturating at 255 like a
+        // float to u8 cast: A positive signum gives bright lines on a dark background, a negative
+        // signum gives dark lines on a bright background
+        (256.0 + (self.signum() as f64) * (55.0 + 200.0 * flow / max)) as i32 as u8
+    }
+}
+
+impl<R: Rng> Algorithm<R> for Flow {
+    fn build(&mut self, rng: &mut R, img: &mut RgbImage) -> Result<(), Error> {
+        check_min_dimensions::<R, _>(self, img, "Flow")?;
+        let (flow, max) = self.trace(rng, img)?;
         for (x, y, pixel) in img.enumerate_pixels_mut() {
-            let val: u8 = (256.0
-                + (self.signum() as f64) * (55.0 + 200.0 * flow[x as usize][y as usize] / max))
-                as u8;
+            let val = self.gray_value(flow[x as usize][y as usize], max);
             *pixel = Rgb([val, val, val]);
         }
         Ok(())
     }
+    fn build_rgba(&mut self, rng: &mut R, img: &mut RgbaImage) -> Result<(), Error> {
+        let mut rgb = RgbImage::new(img.width(), img.height());
+        if !self.transparent {
+            self.build(rng, &mut rgb)?;
+            *img = opaque(rgb);
+            return Ok(());
+        }
+        check_min_dimensions::<R, _>(self, &rgb, "Flow")?;
+        let (flow, max) = self.trace(rng, &rgb)?;
+        for (x, y, pixel) in img.enumerate_pixels_mut() {
+            let flow = flow[x as usize][y as usize];
+            let val = self.gray_value(flow, max);
+            *pixel = Rgba([val, val, val, (255.0 * flow / max).round() as u8]);
+        }
+        Ok(())
+    }
+    fn min_dimensions(&self) -> (u32, u32) {
+        (2, 2)
+    }
+    fn supports_tiling(&self) -> bool {
+        true
+    }
+    fn set_tileable(&mut self, tileable: bool) {
+        self.tileable = tileable;
+    }
+    fn supports_transparency(&self) -> bool {
+        true
+    }
+    fn set_transparent(&mut self, transparent: bool) {
+        self.transparent = transparent;
+    }
+    fn parameters(&self) -> Vec<Parameter> {
+        vec![
+            Parameter::new(
+                "num_particles",
+                "The number of particles that are traced through the flow field",
+                ParameterValue::Integer(self.num_particles as i64),
+            )
+            .with_range(1.0, 1_000_000.0),
+            Parameter::new(
+                "path_len",
+                "The maximum path length of each particle in units of a 1080p image",
+                ParameterValue::Integer(self.path_len as i64),
+            )
+            .with_range(1.0, 100_000.0),
+        ]
+    }
+    fn set_parameter(&mut self, name: &str, value: &ParameterValue) -> Result<(), Error> {
+        match (name, value) {
+            ("num_particles", ParameterValue::Integer(v)) => self.num_particles = *v as u32,
+            ("path_len", ParameterValue::Integer(v)) => self.path_len = *v as u32,
+            _ => return Err(unknown_parameter(name)),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ChaCha8Rng, SeedableRng};
+
+    #[test]
+    fn gray_values_wrap_around() {
+        let mut flow = Flow::default();
+        // Saturating the gray values would make the whole image white
+        assert_eq!(flow.gray_value(0.0, 10.0), 55);
+        assert_eq!(flow.gray_value(5.0, 10.0), 155);
+        assert_eq!(flow.gray_value(10.0, 10.0), 255);
+        flow.signum = false;
+        assert_eq!(flow.gray_value(0.0, 10.0), 201);
+        assert_eq!(flow.gray_value(10.0, 10.0), 1);
+    }
+
+    #[test]
+    fn images_without_flow_are_uniform() {
+        let mut flow = Flow {
+            num_particles: 0,
+            transparent: true,
+            ..Flow::default()
+        };
+        let mut img = RgbaImage::new(16, 9);
+        flow.build_rgba(&mut ChaCha8Rng::seed_from_u64(3), &mut img)
+            .unwrap();
+        // The signum is picked while building, the gray value of no flow is 55 or 201
+        let gray = flow.gray_value(0.0, 1.0);
+        assert!(img.pixels().all(|pixel| pixel.0 == [gray, gray, gray, 0]));
+    }
 }
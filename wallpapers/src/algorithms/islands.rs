@@ -1,5 +1,8 @@
-use crate::algorithms::Algorithm;
+use crate::algorithms::parameters::unknown_parameter;
+use crate::algorithms::{check_min_dimensions, Algorithm, Parameter, ParameterValue};
 use crate::utils::perlin::Perlin;
+use crate::utils::scale::{Scale, VIRTUAL_SIZE};
+use crate::Error;
 use image::{Rgb, RgbImage};
 use rand::Rng;
 
@@ -14,6 +17,8 @@ pub struct Islands {
     foreground_base_color: [u8; 3],
     dashed_grid_color: [u8; 3],
     grid_margins: usize,
+    /// If true, the islands and the grid continue seamlessly at the opposite image border
+    tileable: bool,
 }
 
 impl Default for Islands {
@@ -26,6 +31,7 @@ impl Default for Islands {
             foreground_base_color: [202, 168, 131],
             dashed_grid_color: [100, 96, 82],
             grid_margins: 10,
+            tileable: false,
         }
     }
 }
@@ -46,38 +52,48 @@ impl Islands {
             replacement_3
         }
     }
-    fn draw_horiz_dashed(&self, img: &mut RgbImage, y: usize) {
+    fn draw_horiz_dashed(&self, img: &mut RgbImage, scale: &Scale, y: u32) {
         for x in 0..img.width() {
-            if x % 20 < 10 {
-                let pixel = img.get_pixel_mut(x, y as u32);
+            if scale.to_units(x as f64) % 20.0 < 10.0 {
+                let pixel = img.get_pixel_mut(x, y);
                 *pixel = Rgb(self.dashed_grid_color);
             }
         }
     }
-    fn draw_vert_dashed(&self, img: &mut RgbImage, x: usize) {
+    fn draw_vert_dashed(&self, img: &mut RgbImage, scale: &Scale, x: u32) {
         for y in 0..img.height() {
-            if y % 20 < 10 {
-                let pixel = img.get_pixel_mut(x as u32, y);
+            if scale.to_units(y as f64) % 20.0 < 10.0 {
+                let pixel = img.get_pixel_mut(x, y);
                 *pixel = Rgb(self.dashed_grid_color);
             }
         }
     }
+    /// Get the distance between two grid lines along an image side of the given length in virtual
+    /// units. If the image is tileable, the distance is adjusted so that the grid repeats itself
+    /// after the given length.
+    fn grid_spacing(&self, grid_size: f64, length: f64) -> f64 {
+        if self.tileable {
+            length / (length / grid_size).round().max(1.0)
+        } else {
+            grid_size
+        }
+    }
 }
 
 impl<R: Rng> Algorithm<R> for Islands {
-    fn build(&mut self, rng: &mut R, img: &mut RgbImage) -> Result<(), String> {
-        // Six grid squares for the longer dimension of the image
-        let grid_size_in_px: usize = ((if img.width() > img.height() {
-            img.height()
-        } else {
-            img.width()
-        } as f64
-            - (2.0 * self.grid_margins as f64))
-            / 6.0) as usize;
-        let mut perlin = Perlin::new(img.width() as usize, img.height() as usize);
+    fn build(&mut self, rng: &mut R, img: &mut RgbImage) -> Result<(), Error> {
+        check_min_dimensions::<R, _>(self, img, "Islands")?;
+        let scale = Scale::of(img);
+        // Six grid squares for the shorter dimension of the image
+        let grid_size = (VIRTUAL_SIZE - 2.0 * self.grid_margins as f64) / 6.0;
+        let mut perlin = Perlin::for_fractal(scale.width(), scale.height(), 0.004, 8);
         perlin.regenerate_noise(rng);
+        if self.tileable {
+            perlin.set_period(scale.width(), scale.height());
+        }
         for (x, y, pixel) in img.enumerate_pixels_mut() {
-            let val: u8 = (185.0 + perlin.fractal(x as f64, y as f64, 0.004, 8)? * 70.0) as u8;
+            let (x, y) = (scale.to_units(x as f64), scale.to_units(y as f64));
+            let val: u8 = (185.0 + perlin.fractal(x, y, 0.004, 8)? * 70.0) as u8;
             *pixel = Rgb([
                 self.compute_threshold(
                     val,
@@ -99,11 +115,76 @@ impl<R: Rng> Algorithm<R> for Islands {
                 ),
             ]);
         }
-        for y in (self.grid_margins..(img.height() as usize)).step_by(grid_size_in_px) {
-            self.draw_horiz_dashed(img, y);
+        let mut y = self.grid_margins as f64;
+        while y < scale.height() {
+            self.draw_horiz_dashed(img, &scale, scale.pixel_at(0.0, y).1);
+            y += self.grid_spacing(grid_size, scale.height());
+        }
+        let mut x = self.grid_margins as f64;
+        while x < scale.width() {
+            self.draw_vert_dashed(img, &scale, scale.pixel_at(x, 0.0).0);
+            x += self.grid_spacing(grid_size, scale.width());
         }
-        for x in (self.grid_margins..(img.width() as usize)).step_by(grid_size_in_px) {
-            self.draw_vert_dashed(img, x);
+        Ok(())
+    }
+    fn supports_tiling(&self) -> bool {
+        true
+    }
+    fn set_tileable(&mut self, tileable: bool) {
+        self.tileable = tileable;
+    }
+    fn parameters(&self) -> Vec<Parameter> {
+        vec![
+            Parameter::new(
+                "threshold_val_1",
+                "Noise values above this threshold are drawn as islands",
+                ParameterValue::Integer(self.threshold_val_1 as i64),
+            )
+            .with_range(0.0, 255.0),
+            Parameter::new(
+                "threshold_val_2",
+                "Noise values above this threshold are drawn as island borders",
+                ParameterValue::Integer(self.threshold_val_2 as i64),
+            )
+            .with_range(0.0, 255.0),
+            Parameter::new(
+                "background_base_color",
+                "The base color of the water",
+                ParameterValue::Color(self.background_base_color),
+            ),
+            Parameter::new(
+                "border_color",
+                "The color of the island borders",
+                ParameterValue::Color(self.border_color),
+            ),
+            Parameter::new(
+                "foreground_base_color",
+                "The base color of the islands",
+                ParameterValue::Color(self.foreground_base_color),
+            ),
+            Parameter::new(
+                "dashed_grid_color",
+                "The color of the dashed grid lines",
+                ParameterValue::Color(self.dashed_grid_color),
+            ),
+            Parameter::new(
+                "grid_margins",
+                "The distance of the first grid line to the image border in units of a 1080p image",
+                ParameterValue::Integer(self.grid_margins as i64),
+            )
+            .with_range(0.0, 500.0),
+        ]
+    }
+    fn set_parameter(&mut self, name: &str, value: &ParameterValue) -> Result<(), Error> {
+        match (name, value) {
+            ("threshold_val_1", ParameterValue::Integer(v)) => self.threshold_val_1 = *v as u8,
+            ("threshold_val_2", ParameterValue::Integer(v)) => self.threshold_val_2 = *v as u8,
+            ("background_base_color", ParameterValue::Color(c)) => self.background_base_color = *c,
+            ("border_color", ParameterValue::Color(c)) => self.border_color = *c,
+            ("foreground_base_color", ParameterValue::Color(c)) => self.foreground_base_color = *c,
+            ("dashed_grid_color", ParameterValue::Color(c)) => self.dashed_grid_color = *c,
+            ("grid_margins", ParameterValue::Integer(v)) => self.grid_margins = *v as usize,
+            _ => return Err(unknown_parameter(name)),
         }
         Ok(())
     }
@@ -1,23 +1,35 @@
-use crate::algorithms::Algorithm;
-use image::{Rgb, RgbImage};
+use crate::algorithms::parameters::unknown_parameter;
+use crate::algorithms::{check_min_dimensions, opaque, Algorithm, Parameter, ParameterValue};
+use crate::seed::Streams;
+use crate::Error;
+use image::{Rgb, RgbImage, Rgba, RgbaImage};
 use rand::Rng;
 use std::cmp::max;
 
 /// Lightning
 /// Based on the LIGHTNING algorithm implementation by Attila Bagyoni, 2018
 /// https://github.com/bagyoni/procedural-wallpapers
+///
+/// The particles move in steps of one pixel, so unlike most other algorithms, Lightning does not
+/// give the same picture at different resolutions.
 pub struct Lightning {
+    /// The number of particles per pixel column of the image
+    particles_per_column: u32,
     step: u32,
     distribution: u8,
     fg_color: [u8; 3],
+    /// If true, the background is transparent in images with an alpha channel
+    transparent: bool,
 }
 
 impl Default for Lightning {
     fn default() -> Self {
         Lightning {
+            particles_per_column: 10,
             step: 0,
             distribution: 0,
             fg_color: [255, 255, 255],
+            transparent: false,
         }
     }
 }
@@ -27,45 +39,42 @@ impl Lightning {
         self.distribution = rng.gen_range(0..=3);
     }
 
-    fn place_charge(&self, rng: &mut impl Rng, img: &RgbImage) -> (u32, u32) {
+    fn place_charge(&self, rng: &mut impl Rng, img: &RgbImage) -> Result<(u32, u32), Error> {
         let ran1 = rng.gen_range(2..img.height() - 1);
         let ran2 = rng.gen_range(1..=img.width());
         match self.distribution {
-            0 => (
+            0 => Ok((
                 rng.gen_range(0..img.width()),
                 img.height() - rng.gen_range(0..=ran1) - 2,
-            ),
-            1 => (rng.gen_range(0..img.width()), rng.gen_range(0..=ran1)),
-            2 => (
+            )),
+            1 => Ok((rng.gen_range(0..img.width()), rng.gen_range(0..=ran1))),
+            2 => Ok((
                 img.width() - rng.gen_range(0..ran2) - 1,
                 rng.gen_range(0..(img.height() - 1)),
-            ),
-            3 => (rng.gen_range(0..ran2), rng.gen_range(0..(img.height() - 1))),
+            )),
+            3 => Ok((rng.gen_range(0..ran2), rng.gen_range(0..(img.height() - 1)))),
 
-            _ => panic!("Illegal distribution: {}", self.distribution),
+            _ => Err(Error::InvalidParameter {
+                name: "distribution".to_string(),
+                reason: format!("Illegal distribution: {}", self.distribution),
+            }),
         }
     }
-    fn next_step(&mut self, rng: &mut impl Rng, img: &mut RgbImage, particles: &mut [(u32, u32)]) {
-        let (x, y) = self.place_charge(rng, img);
-        // Get the index of the nearest particle
-        let (nearest, _) = (0..self.step)
-            .map(|i| {
-                (
-                    Some(i),
-                    ((particles[i as usize].0 as i32 - x as i32).pow(2)
-                        + (particles[i as usize].1 as i32 - y as i32).pow(2))
-                        as u32,
-                )
+    fn next_step(
+        &mut self,
+        rng: &mut impl Rng,
+        img: &mut RgbImage,
+        particles: &mut [(u32, u32)],
+    ) -> Result<(), Error> {
+        let (x, y) = self.place_charge(rng, img)?;
+        // Get the index of the nearest particle. The first particle is always placed before
+        // the first step, so there is always a nearest particle.
+        let nearest = (0..self.step as usize)
+            .min_by_key(|&i| {
+                (particles[i].0 as i64 - x as i64).pow(2)
+                    + (particles[i].1 as i64 - y as i64).pow(2)
             })
-            .fold((None, img.width().pow(2) + img.height().pow(2)), |i, j| {
-                if j.1 < i.1 {
-                    (j.0, j.1)
-                } else {
-                    (i.0, i.1)
-                }
-            });
-        // Unwrap the index
-        let nearest = nearest.expect("No nearest was found!") as usize;
+            .unwrap_or(0);
         let mut dx: i32 = if particles[nearest].0 < x { 1 } else { -1 };
         let mut dy: i32 = if particles[nearest].1 < y { 1 } else { -1 };
         if dx * (x as i32 - particles[nearest].0 as i32)
@@ -92,28 +101,73 @@ impl Lightning {
             particles[self.step as usize].1 + 1,
         ) = Rgb(self.fg_color);
         self.step += 1;
+        Ok(())
     }
 }
 
 impl<R: Rng> Algorithm<R> for Lightning {
-    fn build(&mut self, rng: &mut R, img: &mut RgbImage) -> Result<(), String> {
-        let pnum = img.width() * 10;
+    fn build(&mut self, rng: &mut R, img: &mut RgbImage) -> Result<(), Error> {
+        check_min_dimensions::<R, _>(self, img, "Lightning")?;
+        let pnum = img.width() * self.particles_per_column;
         let mut particles: Vec<(u32, u32)> = vec![(0, 0); pnum as usize];
-        self.generate_distribution(rng);
-        particles[0] = (rng.gen_range(0..(img.width() as usize)) as u32, 0);
-        let red: u8 = rng.gen_range(0..120);
-        let green: u8 = rng.gen_range(0..120);
+        let streams = Streams::from_rng(rng);
+        let mut palette = streams.rng("palette");
+        let red: u8 = palette.gen_range(0..120);
+        let green: u8 = palette.gen_range(0..120);
+        let mut discharge = streams.rng("discharge");
+        self.generate_distribution(&mut discharge);
+        particles[0] = (discharge.gen_range(0..(img.width() as usize)) as u32, 0);
         self.step = 1;
         while self.step < pnum {
-            if (self.step % 2000) == 0 {
-                self.generate_distribution(rng);
+            if self.step.is_multiple_of(2000) {
+                self.generate_distribution(&mut discharge);
             }
             self.fg_color = [
                 (red as u32 + (200 - red) as u32 * (pnum - self.step) / pnum) as u8,
                 (green as u32 + (200 - green) as u32 * (pnum - self.step) / pnum) as u8,
                 255,
             ];
-            self.next_step(rng, img, &mut particles);
+            self.next_step(&mut discharge, img, &mut particles)?;
+        }
+        Ok(())
+    }
+    fn build_rgba(&mut self, rng: &mut R, img: &mut RgbaImage) -> Result<(), Error> {
+        let mut rgb = RgbImage::new(img.width(), img.height());
+        self.build(rng, &mut rgb)?;
+        if !self.transparent {
+            *img = opaque(rgb);
+            return Ok(());
+        }
+        // The bolts always have a blue channel of 255, everything else is background
+        for (x, y, pixel) in img.enumerate_pixels_mut() {
+            let [r, g, b] = rgb.get_pixel(x, y).0;
+            *pixel = Rgba([r, g, b, if b > 0 { u8::MAX } else { 0 }]);
+        }
+        Ok(())
+    }
+    fn min_dimensions(&self) -> (u32, u32) {
+        (1, 4)
+    }
+    fn supports_transparency(&self) -> bool {
+        true
+    }
+    fn set_transparent(&mut self, transparent: bool) {
+        self.transparent = transparent;
+    }
+    fn parameters(&self) -> Vec<Parameter> {
+        vec![Parameter::new(
+            "particles_per_column",
+            "The number of particles per pixel column of the image",
+            ParameterValue::Integer(self.particles_per_column as i64),
+        )
+        .with_range(1.0, 100.0)]
+    }
+    fn set_parameter(&mut self, name: &str, value: &ParameterValue) -> Result<(), Error> {
+        match (name, value) {
+            ("particles_per_column", ParameterValue::Integer(v)) => {
+                self.particles_per_column = *v as u32
+            },
+            _ => return Err(unknown_parameter(name)),
         }
         Ok(())
     }
@@ -1,4 +1,7 @@
-use image::RgbImage;
+//! This module contains all the image generation algorithms.
+use crate::patterns::pattern::Pattern;
+use crate::Error;
+use image::{DynamicImage, RgbImage, RgbaImage};
 use rand::Rng;
 
 mod cellularone;
@@ -6,7 +9,9 @@ mod clouds;
 mod flow;
 mod islands;
 mod lightning;
+mod mosaic;
 mod nearestpoint;
+mod parameters;
 mod randompatterns;
 mod squaresonedirection;
 mod tangles;
@@ -16,14 +21,96 @@ pub use clouds::Clouds;
 pub use flow::Flow;
 pub use islands::Islands;
 pub use lightning::Lightning;
+pub use mosaic::Mosaic;
 pub use nearestpoint::NearestPoint;
+pub(crate) use parameters::{parse_color, unknown_parameter};
+pub use parameters::{Parameter, ParameterKind, ParameterValue};
 pub use randompatterns::RandomPatterns;
 pub use squaresonedirection::SquaresOneDirection;
 pub use tangles::Tangles;
 
-/// This module contains all the image generation algorithms.
-
 pub trait Algorithm<R: Rng> {
     /// Build an image using this algorithm
-    fn build(&mut self, rng: &mut R, img: &mut RgbImage) -> Result<(), String>;
+    fn build(&mut self, rng: &mut R, img: &mut RgbImage) -> Result<(), Error>;
+    /// Build an image with an alpha channel using this algorithm.
+    /// By default, the image is built with [`Algorithm::build`] and is fully opaque.
+    fn build_rgba(&mut self, rng: &mut R, img: &mut RgbaImage) -> Result<(), Error> {
+        let mut rgb = RgbImage::new(img.width(), img.height());
+        self.build(rng, &mut rgb)?;
+        *img = opaque(rgb);
+        Ok(())
+    }
+    /// Get the minimum image width and height in pixels that this algorithm supports
+    fn min_dimensions(&self) -> (u32, u32) {
+        (1, 1)
+    }
+    /// Get all parameters that can be used to tune this algorithm
+    fn parameters(&self) -> Vec<Parameter> {
+        Vec::new()
+    }
+    /// Set the parameter with the given name.
+    /// The value has already been checked against the type and range of the parameter.
+    fn set_parameter(&mut self, name: &str, _value: &ParameterValue) -> Result<(), Error> {
+        Err(parameters::unknown_parameter(name))
+    }
+    /// Check whether this algorithm can render seamlessly tileable images
+    fn supports_tiling(&self) -> bool {
+        false
+    }
+    /// Render seamlessly tileable images, whose right edge continues at the left edge and whose
+    /// bottom edge continues at the top edge.
+    /// This is only called if [`Algorithm::supports_tiling`] returns true.
+    fn set_tileable(&mut self, _tileable: bool) {}
+    /// Check whether this algorithm draws patterns that can be replaced with
+    /// [`Algorithm::set_pattern`]
+    fn supports_patterns(&self) -> bool {
+        false
+    }
+    /// Draw the given pattern instead of the built-in pattern of this algorithm.
+    /// This is only called if [`Algorithm::supports_patterns`] returns true.
+    fn set_pattern(&mut self, _pattern: Pattern) {}
+    /// Check whether this algorithm can render a transparent background
+    fn supports_transparency(&self) -> bool {
+        false
+    }
+    /// Render a transparent background in [`Algorithm::build_rgba`], so that the image can be
+    /// used as an overlay.
+    /// This is only called if [`Algorithm::supports_transparency`] returns true.
+    fn set_transparent(&mut self, _transparent: bool) {}
+    /// Check the given value against the parameter with the given name and set it
+    fn configure(&mut self, name: &str, value: ParameterValue) -> Result<(), Error> {
+        let parameter = self
+            .parameters()
+            .into_iter()
+            .find(|parameter| parameter.name == name)
+            .ok_or_else(|| parameters::unknown_parameter(name))?;
+        let value = parameter.check(value)?;
+        self.set_parameter(name, &value)
+    }
+}
+
+/// Convert the given image into a fully opaque image with an alpha channel
+pub(crate) fn opaque(img: RgbImage) -> RgbaImage {
+    DynamicImage::ImageRgb8(img).to_rgba8()
+}
+
+/// Return an error if the given image is smaller than the minimum dimensions of the algorithm
+pub(crate) fn check_min_dimensions<R: Rng, A: Algorithm<R> + ?Sized>(
+    algorithm: &A,
+    img: &RgbImage,
+    algorithm_name: &str,
+) -> Result<(), Error> {
+    let (min_width, min_height) = algorithm.min_dimensions();
+    if img.width() < min_width || img.height() < min_height {
+        Err(Error::InvalidDimensions {
+            width: img.width(),
+            height: img.height(),
+            reason: format!(
+                "{} needs an image of at least {}x{} pixels",
+                algorithm_name, min_width, min_height
+            ),
+        })
+    } else {
+        Ok(())
+    }
 }
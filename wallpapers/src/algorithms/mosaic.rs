@@ -0,0 +1,109 @@
+use crate::algorithms::{check_min_dimensions, Algorithm, Parameter, ParameterValue};
+use crate::layers::cells::{CellGrid, CellLayer, CellShape, Hexagons, Rhombi, Triangles};
+use crate::layers::Layer;
+use crate::propagation::{Propagation, Traversal};
+use crate::seed::Streams;
+use crate::utils::scale::Scale;
+use crate::Error;
+use image::RgbImage;
+use rand::Rng;
+
+/// The Squares algorithm on a grid of other cells, e.g. hexagons.
+/// By default, the cells are colored row by row, each with the average color of the cells
+/// touching it that have already been colored, with a random variation in a random RGB channel.
+/// The coloring is done by a [`Propagation`], so the order, neighborhood and mutation of the
+/// colors can be changed, e.g. to vary the hue and lightness in the oklch color space instead.
+pub struct Mosaic<Shape: CellShape> {
+    cells: CellLayer<Shape, [u8; 3]>,
+    /// Create the shape of the cells from the cell size in pixels
+    shape: fn(f64) -> Shape,
+    propagation: Propagation,
+    additional_random_points: usize,
+    /// The width of each cell in virtual units
+    cell_size: f64,
+}
+
+impl<Shape: CellShape> Mosaic<Shape> {
+    fn new(shape: fn(f64) -> Shape) -> Self {
+        Mosaic {
+            cells: CellLayer::new(shape(1.0), 0, 0),
+            shape,
+            propagation: Propagation {
+                traversal: Traversal::Rows,
+                ..Propagation::default()
+            },
+            additional_random_points: 0,
+            cell_size: 40.0,
+        }
+    }
+}
+
+impl Mosaic<Hexagons> {
+    pub fn new_hexagons() -> Self {
+        Self::new(|size| Hexagons { size })
+    }
+}
+
+impl Mosaic<Triangles> {
+    pub fn new_triangles() -> Self {
+        Self::new(|size| Triangles { size })
+    }
+}
+
+impl Mosaic<Rhombi> {
+    /// Rhombi with angles of 60 and 120 degrees, lying on their long diagonal
+    pub fn new_rhombi() -> Self {
+        Self::new(|size| Rhombi {
+            width: size,
+            height: size / 3.0_f64.sqrt(),
+        })
+    }
+}
+
+impl<R: Rng, Shape: CellShape> Algorithm<R> for Mosaic<Shape> {
+    fn build(&mut self, rng: &mut R, img: &mut RgbImage) -> Result<(), Error> {
+        check_min_dimensions::<R, _>(self, img, "Mosaic")?;
+        // The cells are sized in virtual units, so that the number of cells only depends on the
+        // aspect ratio
+        let scale = Scale::of(img);
+        let cell_size = scale.to_pixels(self.cell_size).max(1.0);
+        self.cells.set_shape((self.shape)(cell_size));
+        self.cells
+            .adjust_cell_count_to_image_dimensions(img.width() as usize, img.height() as usize);
+        // n points per 1000x1000 virtual units
+        let num_points =
+            (self.additional_random_points as f64 * scale.area() / (1000.0 * 1000.0)) as usize;
+        self.propagation
+            .run(&mut self.cells, &Streams::from_rng(rng), num_points)?;
+        self.cells.draw(img)?;
+        Ok(())
+    }
+    fn parameters(&self) -> Vec<Parameter> {
+        let mut parameters = self.propagation.parameters();
+        parameters.extend([
+            Parameter::new(
+                "additional_random_points",
+                "The number of randomly colored cells per 1000x1000 pixels of a 1080p image",
+                ParameterValue::Integer(self.additional_random_points as i64),
+            )
+            .with_range(0.0, 100_000.0),
+            Parameter::new(
+                "cell_size",
+                "The width of each cell in pixels of a 1080p image",
+                ParameterValue::Float(self.cell_size),
+            )
+            .with_range(1.0, 10_000.0),
+        ]);
+        parameters
+    }
+    fn set_parameter(&mut self, name: &str, value: &ParameterValue) -> Result<(), Error> {
+        match (name, value) {
+            ("additional_random_points", ParameterValue::Integer(v)) => {
+                self.additional_random_points = *v as usize
+            },
+            ("cell_size", ParameterValue::Float(v)) => self.cell_size = *v,
+            _ => self.propagation.set_parameter(name, value)?,
+        }
+        Ok(())
+    }
+}
@@ -1,43 +1,52 @@
-use crate::algorithms::Algorithm;
+use crate::algorithms::parameters::unknown_parameter;
+use crate::algorithms::{check_min_dimensions, Algorithm, Parameter, ParameterValue};
+use crate::seed::Streams;
+use crate::utils::scale::Scale;
+use crate::Error;
 use image::{Rgb, RgbImage};
 use rand::Rng;
 use std::cmp::max;
-use std::collections::HashSet;
 
 enum PointDrawingMode {
     Hard,
     Smooth { brightness: f64 },
 }
 
-#[derive(Eq, PartialEq, Hash)]
+#[derive(PartialEq)]
 struct Point {
-    /// X Coordinate
-    x: u32,
-    /// Y Coordinate
-    y: u32,
+    /// X Coordinate in virtual units
+    x: f64,
+    /// Y Coordinate in virtual units
+    y: f64,
     /// The color of the point
     color: [u8; 3],
 }
 
 impl Point {
-    /// Get the distance of this point to the other point
-    fn distance_to(&self, x: u32, y: u32) -> f64 {
-        (((x as i32 - self.x as i32).pow(2) + (y as i32 - self.y as i32).pow(2)) as f64).sqrt()
+    /// Get the distance of this point to the given coordinate in virtual units.
+    /// If a period is given, the distance wraps around after the given width and height.
+    fn distance_to(&self, x: f64, y: f64, period: Option<(f64, f64)>) -> f64 {
+        let (mut dx, mut dy) = ((x - self.x).abs(), (y - self.y).abs());
+        if let Some((width, height)) = period {
+            dx = dx.min(width - dx);
+            dy = dy.min(height - dy);
+        }
+        (dx.powi(2) + dy.powi(2)).sqrt()
     }
-    /// Get the color that a pixel should get if drawn in connection to this point
-    fn color_at(&self, x: u32, y: u32) -> [u8; 3] {
+    /// Get the color that the given pixel should get if drawn in connection to this point
+    fn color_at(&self, scale: &Scale, x: u32, y: u32) -> [u8; 3] {
         // If debugging build is used, draw red dots where the actual points are placed
-        if cfg!(debug_assertions) && self.x == x && self.y == y {
+        if cfg!(debug_assertions) && scale.pixel_at(self.x, self.y) == (x, y) {
             [255, 0, 0]
         } else {
             self.color
         }
     }
-    /// Get a default black point at u32::MIN
+    /// Get a default black point at the origin
     fn black() -> Self {
         Point {
-            x: u32::MIN,
-            y: u32::MIN,
+            x: 0.0,
+            y: 0.0,
             color: [0, 0, 0],
         }
     }
@@ -45,15 +54,21 @@ impl Point {
 
 /// Nearest Points Algorithm Implementation by Lukas Kirschner, 2021
 pub struct NearestPoint {
-    points: HashSet<Point>,
+    points: Vec<Point>,
     mode: PointDrawingMode,
+    /// The image area in square virtual units per point
+    pixels_per_point: u32,
+    /// If true, distances wrap around at the image borders, so that the image can be tiled
+    tileable: bool,
 }
 
 impl Default for NearestPoint {
     fn default() -> Self {
         NearestPoint {
-            points: HashSet::new(),
+            points: vec![],
             mode: PointDrawingMode::Hard,
+            pixels_per_point: 20000,
+            tileable: false,
         }
     }
 }
@@ -61,34 +76,47 @@ impl Default for NearestPoint {
 impl NearestPoint {
     pub fn new_soft() -> Self {
         NearestPoint {
-            points: HashSet::new(),
+            points: vec![],
             mode: PointDrawingMode::Smooth { brightness: 1.25 },
+            pixels_per_point: 20000,
+            tileable: false,
         }
     }
 }
 
 impl NearestPoint {
-    fn populate_points(&mut self, rng: &mut impl Rng, num_points: usize, img: &RgbImage) {
+    fn populate_points(&mut self, streams: &Streams, num_points: usize, scale: &Scale) {
+        let mut positions = streams.rng("points");
+        let mut palette = streams.rng("palette");
+        self.points.clear();
         for _ in 0..num_points {
-            self.points.insert(Point {
-                x: rng.gen_range(0..img.width()),
-                y: rng.gen_range(0..img.height()),
+            self.points.push(Point {
+                x: positions.gen_range(0.0..scale.width()),
+                y: positions.gen_range(0.0..scale.height()),
                 color: [
-                    rng.gen_range(0..128) + 128,
-                    rng.gen_range(0..128) + 128,
-                    rng.gen_range(0..128) + 128,
+                    palette.gen_range(0..128) + 128,
+                    palette.gen_range(0..128) + 128,
+                    palette.gen_range(0..128) + 128,
                 ],
             });
         }
     }
-    fn color_image(&self, img: &mut RgbImage) {
-        for (x, y, pixel) in img.enumerate_pixels_mut() {
+    fn color_image(&self, img: &mut RgbImage, scale: &Scale) {
+        let period = self.tileable.then(|| (scale.width(), scale.height()));
+        for (px, py, pixel) in img.enumerate_pixels_mut() {
+            // Sample the center of each pixel
+            let x = scale.to_units(px as f64 + 0.5);
+            let y = scale.to_units(py as f64 + 0.5);
             match self.mode {
                 PointDrawingMode::Hard => {
                     let nearest_point = self.points.iter().min_by(|first, second| {
-                        first.distance_to(x, y).total_cmp(&second.distance_to(x, y))
+                        first
+                            .distance_to(x, y, period)
+                            .total_cmp(&second.distance_to(x, y, period))
                     });
-                    *pixel = Rgb(nearest_point.unwrap_or(&Point::black()).color_at(x, y));
+                    *pixel = Rgb(nearest_point
+                        .unwrap_or(&Point::black())
+                        .color_at(scale, px, py));
                 },
                 PointDrawingMode::Smooth { brightness } => {
                     let new_color: [f64; 3] = self
@@ -97,11 +125,14 @@ impl NearestPoint {
                         .map(|point| {
                             [
                                 point.color[0] as f64
-                                    / (point.distance_to(x, y) + 1.0).powf(1.0 / brightness),
+                                    / (point.distance_to(x, y, period) + 1.0)
+                                        .powf(1.0 / brightness),
                                 point.color[1] as f64
-                                    / (point.distance_to(x, y) + 1.0).powf(1.0 / brightness),
+                                    / (point.distance_to(x, y, period) + 1.0)
+                                        .powf(1.0 / brightness),
                                 point.color[2] as f64
-                                    / (point.distance_to(x, y) + 1.0).powf(1.0 / brightness),
+                                    / (point.distance_to(x, y, period) + 1.0)
+                                        .powf(1.0 / brightness),
                             ]
                         })
                         .fold([0.0, 0.0, 0.0], |a, b| {
@@ -120,19 +151,49 @@ impl NearestPoint {
 }
 
 impl<R: Rng> Algorithm<R> for NearestPoint {
-    fn build(&mut self, rng: &mut R, img: &mut RgbImage) -> Result<(), String> {
-        let num_points = max(2, img.width() * img.height() / 20000);
-        self.populate_points(
-            rng,
-            num_points.try_into().unwrap_or_else(|_| {
-                panic!(
-                    "There were too many points for this algorithm to handle: {}",
-                    num_points
+    fn build(&mut self, rng: &mut R, img: &mut RgbImage) -> Result<(), Error> {
+        check_min_dimensions::<R, _>(self, img, "NearestPoint")?;
+        let scale = Scale::of(img);
+        let num_points = max(2, (scale.area() / self.pixels_per_point as f64) as usize);
+        self.populate_points(&Streams::from_rng(rng), num_points, &scale);
+        self.color_image(img, &scale);
+        Ok(())
+    }
+    fn supports_tiling(&self) -> bool {
+        true
+    }
+    fn set_tileable(&mut self, tileable: bool) {
+        self.tileable = tileable;
+    }
+    fn parameters(&self) -> Vec<Parameter> {
+        let mut parameters = vec![Parameter::new(
+            "pixels_per_point",
+            "The image area per point in pixels of a 1080p image, at least two points are placed",
+            ParameterValue::Integer(self.pixels_per_point as i64),
+        )
+        .with_range(1000.0, 100_000_000.0)];
+        if let PointDrawingMode::Smooth { brightness } = self.mode {
+            parameters.push(
+                Parameter::new(
+                    "brightness",
+                    "The brightness of the gradients between the points",
+                    ParameterValue::Float(brightness),
                 )
-            }),
-            img,
-        );
-        self.color_image(img);
+                .with_range(0.1, 10.0),
+            );
+        }
+        parameters
+    }
+    fn set_parameter(&mut self, name: &str, value: &ParameterValue) -> Result<(), Error> {
+        match (name, value, &mut self.mode) {
+            ("pixels_per_point", ParameterValue::Integer(v), _) => {
+                self.pixels_per_point = *v as u32
+            },
+            ("brightness", ParameterValue::Float(v), PointDrawingMode::Smooth { brightness }) => {
+                *brightness = *v
+            },
+            _ => return Err(unknown_parameter(name)),
+        }
         Ok(())
     }
 }
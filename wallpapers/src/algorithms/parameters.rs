@@ -0,0 +1,296 @@
+use crate::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt::{Display, Formatter};
+
+/// The type of an algorithm parameter
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ParameterKind {
+    /// A whole number
+    Integer,
+    /// A floating-point number
+    Float,
+    /// A flag that can be either true or false
+    Boolean,
+    /// An RGB color
+    Color,
+    /// One of a fixed set of names
+    Choice,
+}
+
+impl Display for ParameterKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParameterKind::Integer => write!(f, "integer"),
+            ParameterKind::Float => write!(f, "float"),
+            ParameterKind::Boolean => write!(f, "boolean"),
+            ParameterKind::Color => write!(f, "color"),
+            ParameterKind::Choice => write!(f, "choice"),
+        }
+    }
+}
+
+/// The value of an algorithm parameter
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParameterValue {
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    Color([u8; 3]),
+    Choice(String),
+}
+
+impl ParameterValue {
+    /// Get the type of this value
+    pub fn kind(&self) -> ParameterKind {
+        match self {
+            ParameterValue::Integer(_) => ParameterKind::Integer,
+            ParameterValue::Float(_) => ParameterKind::Float,
+            ParameterValue::Boolean(_) => ParameterKind::Boolean,
+            ParameterValue::Color(_) => ParameterKind::Color,
+            ParameterValue::Choice(_) => ParameterKind::Choice,
+        }
+    }
+    /// Parse a value from a string, guessing its type.
+    /// Colors can be given as hex code (`#ff8000`) or as comma-separated list (`255,128,0`).
+    /// Single words are parsed as choice.
+    pub fn parse(name: &str, value: &str) -> Result<Self, Error> {
+        let value = value.trim();
+        if let Ok(b) = value.parse::<bool>() {
+            Ok(ParameterValue::Boolean(b))
+        } else if let Ok(i) = value.parse::<i64>() {
+            Ok(ParameterValue::Integer(i))
+        } else if let Ok(f) = value.parse::<f64>() {
+            Ok(ParameterValue::Float(f))
+        } else if let Some(color) = parse_color(value) {
+            Ok(ParameterValue::Color(color))
+        } else if is_choice(value) {
+            Ok(ParameterValue::Choice(value.to_string()))
+        } else {
+            Err(Error::InvalidParameter {
+                name: name.to_string(),
+                reason: format!("Could not parse value \"{}\"", value),
+            })
+        }
+    }
+}
+
+impl Display for ParameterValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParameterValue::Integer(i) => write!(f, "{}", i),
+            ParameterValue::Float(x) => write!(f, "{}", x),
+            ParameterValue::Boolean(b) => write!(f, "{}", b),
+            ParameterValue::Color([r, g, b]) => write!(f, "#{:02x}{:02x}{:02x}", r, g, b),
+            ParameterValue::Choice(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+impl Serialize for ParameterValue {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            ParameterValue::Integer(i) => serializer.serialize_i64(*i),
+            ParameterValue::Float(x) => serializer.serialize_f64(*x),
+            ParameterValue::Boolean(b) => serializer.serialize_bool(*b),
+            ParameterValue::Color(_) | ParameterValue::Choice(_) => {
+                serializer.serialize_str(&self.to_string())
+            },
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ParameterValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        /// All representations of parameter values in serialized data
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Integer(i64),
+            Float(f64),
+            Boolean(bool),
+            Channels([u8; 3]),
+            Text(String),
+        }
+        Ok(match Repr::deserialize(deserializer)? {
+            Repr::Integer(i) => ParameterValue::Integer(i),
+            Repr::Float(x) => ParameterValue::Float(x),
+            Repr::Boolean(b) => ParameterValue::Boolean(b),
+            Repr::Channels(color) => ParameterValue::Color(color),
+            Repr::Text(text) => match parse_color(&text) {
+                Some(color) => ParameterValue::Color(color),
+                None if is_choice(&text) => ParameterValue::Choice(text),
+                None => {
+                    return Err(serde::de::Error::custom(format!(
+                        "Invalid color or choice \"{}\"",
+                        text
+                    )))
+                },
+            },
+        })
+    }
+}
+
+/// Parse a color given as hex code (`#ff8000`) or as comma-separated list (`255,128,0`)
+pub(crate) fn parse_color(value: &str) -> Option<[u8; 3]> {
+    if let Some(hex) = value.strip_prefix('#') {
+        if hex.len() != 6 || !hex.is_ascii() {
+            return None;
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).ok();
+        Some([channel(0)?, channel(1)?, channel(2)?])
+    } else {
+        let channels: Vec<u8> = value
+            .split(',')
+            .map(|c| c.trim().parse::<u8>())
+            .collect::<Result<_, _>>()
+            .ok()?;
+        channels.try_into().ok()
+    }
+}
+
+/// Check whether the given value is a single word that can name a choice
+fn is_choice(value: &str) -> bool {
+    !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// The description of a tunable parameter of an algorithm
+#[derive(Debug, Clone)]
+pub struct Parameter {
+    /// The name that is used to set the parameter
+    pub name: &'static str,
+    /// A short human-readable description
+    pub description: &'static str,
+    /// The value that is used if the parameter is not set explicitly
+    pub default: ParameterValue,
+    /// The inclusive range of valid values, if this is a numeric parameter
+    pub range: Option<(f64, f64)>,
+    /// The valid values, if this is a choice parameter
+    pub choices: Option<&'static [&'static str]>,
+}
+
+impl Parameter {
+    pub fn new(name: &'static str, description: &'static str, default: ParameterValue) -> Self {
+        Parameter {
+            name,
+            description,
+            default,
+            range: None,
+            choices: None,
+        }
+    }
+    /// Restrict the valid values of this choice parameter to the given names
+    pub fn with_choices(mut self, choices: &'static [&'static str]) -> Self {
+        self.choices = Some(choices);
+        self
+    }
+    /// Restrict the valid values of this parameter to the given inclusive range
+    pub fn with_range(mut self, min: f64, max: f64) -> Self {
+        self.range = Some((min, max));
+        self
+    }
+    /// Get the type of this parameter
+    pub fn kind(&self) -> ParameterKind {
+        self.default.kind()
+    }
+    /// Check the given value against the type and range of this parameter.
+    /// Integers are converted to floats for float parameters.
+    pub fn check(&self, value: ParameterValue) -> Result<ParameterValue, Error> {
+        let value = match (self.kind(), value) {
+            (ParameterKind::Float, ParameterValue::Integer(i)) => ParameterValue::Float(i as f64),
+            (_, value) => value,
+        };
+        if value.kind() != self.kind() {
+            return Err(Error::InvalidParameter {
+                name: self.name.to_string(),
+                reason: format!("Expected a value of type {}, got {}", self.kind(), value),
+            });
+        }
+        let numeric = match value {
+            ParameterValue::Integer(i) => Some(i as f64),
+            ParameterValue::Float(x) => Some(x),
+            _ => None,
+        };
+        if let (Some(x), Some((min, max))) = (numeric, self.range) {
+            if !(min..=max).contains(&x) {
+                return Err(Error::InvalidParameter {
+                    name: self.name.to_string(),
+                    reason: format!("The value {} is not in the range [{},{}]", value, min, max),
+                });
+            }
+        }
+        if let (ParameterValue::Choice(name), Some(choices)) = (&value, self.choices) {
+            if !choices.contains(&name.as_str()) {
+                return Err(Error::InvalidParameter {
+                    name: self.name.to_string(),
+                    reason: format!("Expected one of {}, got {}", choices.join(", "), name),
+                });
+            }
+        }
+        Ok(value)
+    }
+}
+
+/// Get the error that is returned if an algorithm does not have a parameter with the given name
+pub(crate) fn unknown_parameter(name: &str) -> Error {
+    Error::InvalidParameter {
+        name: name.to_string(),
+        reason: "Unknown parameter".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_guesses_the_type() {
+        assert_eq!(
+            ParameterValue::parse("p", "true").unwrap(),
+            ParameterValue::Boolean(true)
+        );
+        assert_eq!(
+            ParameterValue::parse("p", "-12").unwrap(),
+            ParameterValue::Integer(-12)
+        );
+        assert_eq!(
+            ParameterValue::parse("p", "0.5").unwrap(),
+            ParameterValue::Float(0.5)
+        );
+        assert_eq!(
+            ParameterValue::parse("p", "#ff8000").unwrap(),
+            ParameterValue::Color([255, 128, 0])
+        );
+        assert_eq!(
+            ParameterValue::parse("p", "1, 2, 3").unwrap(),
+            ParameterValue::Color([1, 2, 3])
+        );
+        assert_eq!(
+            ParameterValue::parse("p", "half-drop").unwrap(),
+            ParameterValue::Choice("half-drop".to_string())
+        );
+        assert!(ParameterValue::parse("p", "#ff80").is_err());
+        assert!(ParameterValue::parse("p", "1,2,300").is_err());
+    }
+
+    #[test]
+    fn check_validates_type_and_range() {
+        let parameter = Parameter::new("p", "", ParameterValue::Float(0.5)).with_range(0.0, 1.0);
+        assert_eq!(
+            parameter.check(ParameterValue::Integer(1)).unwrap(),
+            ParameterValue::Float(1.0)
+        );
+        assert!(parameter.check(ParameterValue::Float(1.5)).is_err());
+        assert!(parameter.check(ParameterValue::Boolean(true)).is_err());
+        let choice = Parameter::new("c", "", ParameterValue::Choice("a".to_string()))
+            .with_choices(&["a", "b"]);
+        assert!(choice
+            .check(ParameterValue::Choice("b".to_string()))
+            .is_ok());
+        assert!(choice
+            .check(ParameterValue::Choice("c".to_string()))
+            .is_err());
+    }
+}
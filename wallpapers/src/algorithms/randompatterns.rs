@@ -1,2 +1,327 @@
+use crate::algorithms::parameters::unknown_parameter;
+use crate::algorithms::{Algorithm, Parameter, ParameterValue};
+use crate::patterns::pattern::{create_noise, noise_shade, Pattern, Patterns};
+use crate::patterns::PatternColor;
+use crate::seed::Streams;
+use crate::utils::scale::Scale;
+use crate::Error;
+use image::{Rgb, RgbImage};
+use rand::Rng;
+use std::f64::consts::PI;
+
+/// How often a stamp is placed at a new random position if it overlaps too much with the stamps
+/// that were drawn before, before it is skipped
+const PLACEMENT_ATTEMPTS: usize = 20;
+
 /// A random pattern drawer - A given pattern is repeatedly drawn on the image
-pub struct RandomPatterns {}
+///
+/// Each stamp of a pattern gets a random position, size, rotation and color.
+/// Positions and sizes are given in virtual units.
+pub struct RandomPatterns {
+    /// The patterns that are stamped onto the image, each stamp picks one of them at random
+    patterns: Vec<Pattern>,
+    background_color: [u8; 3],
+    /// The number of stamps per 1000x1000 virtual units
+    density: f64,
+    /// The minimum size of a single pattern pixel in virtual units
+    min_scale: f64,
+    /// The maximum size of a single pattern pixel in virtual units
+    max_scale: f64,
+    /// If true, each stamp is rotated by a random angle
+    rotate: bool,
+    /// If true, each stamp is drawn in a random color instead of the colors of the pattern
+    recolor: bool,
+    /// The maximum share of the pixels of a stamp that may cover earlier stamps
+    max_overlap: f64,
+    /// If true, stamps that cross an image border continue at the opposite border
+    tileable: bool,
+}
+
+impl Default for RandomPatterns {
+    fn default() -> Self {
+        RandomPatterns::new(vec![Patterns::diamond()])
+    }
+}
+
+/// A single pattern drawn at a certain position, size and rotation
+struct Stamp {
+    /// The index of the pattern
+    pattern: usize,
+    /// The center of the stamp in pixels
+    center: (f64, f64),
+    /// The size of a single pattern pixel in pixels
+    scale: f64,
+    /// The rotation in radians
+    angle: f64,
+    /// The color that replaces the colors of the pattern, if any
+    color: Option<[u8; 3]>,
+}
+
+impl RandomPatterns {
+    /// Create a new random pattern drawer that stamps the given patterns
+    pub fn new(patterns: Vec<Pattern>) -> Self {
+        RandomPatterns {
+            patterns,
+            background_color: [30, 30, 30],
+            density: 40.0,
+            min_scale: 4.0,
+            max_scale: 12.0,
+            rotate: true,
+            recolor: true,
+            max_overlap: 1.0,
+            tileable: false,
+        }
+    }
+    /// Create a stamp of a random pattern with a random position, size, rotation and color
+    fn random_stamp(&self, rng: &mut impl Rng, palette: &mut impl Rng, scale: &Scale) -> Stamp {
+        Stamp {
+            pattern: rng.gen_range(0..self.patterns.len()),
+            center: (
+                scale.to_pixels(rng.gen_range(0.0..scale.width())),
+                scale.to_pixels(rng.gen_range(0.0..scale.height())),
+            ),
+            scale: scale.to_pixels(rng.gen_range(self.min_scale..=self.max_scale)),
+            angle: if self.rotate {
+                rng.gen_range(0.0..2.0 * PI)
+            } else {
+                0.0
+            },
+            color: self.recolor.then(|| {
+                [
+                    palette.gen_range(64..=255),
+                    palette.gen_range(64..=255),
+                    palette.gen_range(64..=255),
+                ]
+            }),
+        }
+    }
+    /// Get all image pixels that are covered by the given stamp, with their pattern colors.
+    /// Transparent pattern pixels are skipped.
+    fn rasterize(&self, stamp: &Stamp, img: &RgbImage) -> Vec<(u32, u32, PatternColor)> {
+        let pattern = &self.patterns[stamp.pattern];
+        let (width, height) = (pattern.width() as f64, pattern.height() as f64);
+        let radius = 0.5 * stamp.scale * (width * width + height * height).sqrt();
+        let (sin, cos) = stamp.angle.sin_cos();
+        let (cx, cy) = stamp.center;
+        let mut pixels = vec![];
+        for py in (cy - radius).floor() as i64..=(cy + radius).ceil() as i64 {
+            for px in (cx - radius).floor() as i64..=(cx + radius).ceil() as i64 {
+                // Rotate the pixel center back into the coordinates of the pattern
+                let (dx, dy) = (px as f64 + 0.5 - cx, py as f64 + 0.5 - cy);
+                let u = (dx * cos + dy * sin) / stamp.scale + width / 2.0;
+                let v = (dy * cos - dx * sin) / stamp.scale + height / 2.0;
+                if u < 0.0 || v < 0.0 || u >= width || v >= height {
+                    continue;
+                }
+                let color = pattern.color_at(u as usize, v as usize);
+                if *color == PatternColor::None {
+                    continue;
+                }
+                let color = match stamp.color {
+                    Some(new_color) => color.recolored(new_color),
+                    None => *color,
+                };
+                let (x, y) = if self.tileable {
+                    (
+                        px.rem_euclid(img.width() as i64),
+                        py.rem_euclid(img.height() as i64),
+                    )
+                } else {
+                    (px, py)
+                };
+                if x >= 0 && y >= 0 && x < img.width() as i64 && y < img.height() as i64 {
+                    pixels.push((x as u32, y as u32, color));
+                }
+            }
+        }
+        pixels
+    }
+}
+
+impl<R: Rng> Algorithm<R> for RandomPatterns {
+    fn build(&mut self, rng: &mut R, img: &mut RgbImage) -> Result<(), Error> {
+        if self.patterns.is_empty() {
+            return Err(Error::InvalidPattern(
+                "RandomPatterns needs at least one pattern".to_string(),
+            ));
+        }
+        if self.min_scale > self.max_scale {
+            return Err(Error::InvalidParameter {
+                name: "min_scale".to_string(),
+                reason: format!(
+                    "The value {} is greater than max_scale {}",
+                    self.min_scale, self.max_scale
+                ),
+            });
+        }
+        for (_, _, pixel) in img.enumerate_pixels_mut() {
+            *pixel = Rgb(self.background_color);
+        }
+        let streams = Streams::from_rng(rng);
+        let mut stamps = streams.rng("stamps");
+        let mut palette = streams.rng("palette");
+        let mut colors = streams.rng("colors");
+        let noise = create_noise(
+            &mut streams.rng("noise"),
+            img.width() as f64,
+            img.height() as f64,
+            self.tileable,
+        );
+        let scale = Scale::of(img);
+        let num_stamps = (self.density * scale.area() / (1000.0 * 1000.0)).round() as usize;
+        let mut covered = vec![vec![false; img.height() as usize]; img.width() as usize];
+        for _ in 0..num_stamps {
+            for _ in 0..PLACEMENT_ATTEMPTS {
+                let stamp = self.random_stamp(&mut stamps, &mut palette, &scale);
+                let pixels = self.rasterize(&stamp, img);
+                let overlapping = pixels
+                    .iter()
+                    .filter(|(x, y, _)| covered[*x as usize][*y as usize])
+                    .count();
+                if overlapping as f64 > self.max_overlap * pixels.len() as f64 {
+                    continue;
+                }
+                for (x, y, color) in pixels {
+                    let pixel = img.get_pixel_mut(x, y);
+                    let new_color = color.blend(pixel.0, &mut colors, |_| {
+                        noise_shade(&noise, x as f64, y as f64)
+                    })?;
+                    *pixel = Rgb(new_color);
+                    covered[x as usize][y as usize] = true;
+                }
+                break;
+            }
+        }
+        Ok(())
+    }
+    fn supports_tiling(&self) -> bool {
+        true
+    }
+    fn set_tileable(&mut self, tileable: bool) {
+        self.tileable = tileable;
+    }
+    fn supports_patterns(&self) -> bool {
+        true
+    }
+    fn set_pattern(&mut self, pattern: Pattern) {
+        self.patterns = vec![pattern];
+    }
+    fn parameters(&self) -> Vec<Parameter> {
+        vec![
+            Parameter::new(
+                "background_color",
+                "The color of the image background",
+                ParameterValue::Color(self.background_color),
+            ),
+            Parameter::new(
+                "density",
+                "The number of stamped patterns per 1000x1000 pixels of a 1080p image",
+                ParameterValue::Float(self.density),
+            )
+            .with_range(0.0, 10_000.0),
+            Parameter::new(
+                "min_scale",
+                "The minimum size of a pattern pixel in pixels of a 1080p image",
+                ParameterValue::Float(self.min_scale),
+            )
+            .with_range(0.1, 1000.0),
+            Parameter::new(
+                "max_scale",
+                "The maximum size of a pattern pixel in pixels of a 1080p image",
+                ParameterValue::Float(self.max_scale),
+            )
+            .with_range(0.1, 1000.0),
+            Parameter::new(
+                "rotate",
+                "Rotate each stamped pattern by a random angle",
+                ParameterValue::Boolean(self.rotate),
+            ),
+            Parameter::new(
+                "recolor",
+                "Draw each stamped pattern in a random color instead of the pattern colors",
+                ParameterValue::Boolean(self.recolor),
+            ),
+            Parameter::new(
+                "max_overlap",
+                "The maximum share of a stamped pattern that may cover earlier patterns, 0 \
+                 prevents any overlap",
+                ParameterValue::Float(self.max_overlap),
+            )
+            .with_range(0.0, 1.0),
+        ]
+    }
+    fn set_parameter(&mut self, name: &str, value: &ParameterValue) -> Result<(), Error> {
+        match (name, value) {
+            ("background_color", ParameterValue::Color(c)) => self.background_color = *c,
+            ("density", ParameterValue::Float(v)) => self.density = *v,
+            ("min_scale", ParameterValue::Float(v)) => self.min_scale = *v,
+            ("max_scale", ParameterValue::Float(v)) => self.max_scale = *v,
+            ("rotate", ParameterValue::Boolean(b)) => self.rotate = *b,
+            ("recolor", ParameterValue::Boolean(b)) => self.recolor = *b,
+            ("max_overlap", ParameterValue::Float(v)) => self.max_overlap = *v,
+            _ => return Err(unknown_parameter(name)),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ChaCha8Rng, SeedableRng};
+
+    const WHITE: PatternColor = PatternColor::Solid {
+        color: [255, 255, 255],
+    };
+
+    #[test]
+    fn rotated_stamps_cover_the_rotated_pattern() {
+        let bar = Pattern::from_rows(vec![vec![WHITE; 3]]).unwrap();
+        let algorithm = RandomPatterns::new(vec![bar]);
+        let stamp = Stamp {
+            pattern: 0,
+            center: (50.0, 50.0),
+            scale: 10.0,
+            angle: PI / 2.0,
+            color: None,
+        };
+        let pixels = algorithm.rasterize(&stamp, &RgbImage::new(100, 100));
+        // The horizontal bar of 30x10 pixels becomes a vertical bar of 10x30 pixels
+        assert_eq!(pixels.len(), 300);
+        assert!(pixels
+            .iter()
+            .all(|(x, y, _)| (45..55).contains(x) && (35..65).contains(y)));
+    }
+
+    #[test]
+    fn stamps_are_drawn_in_random_colors() {
+        let mut algorithm = RandomPatterns::default();
+        Algorithm::<ChaCha8Rng>::configure(
+            &mut algorithm,
+            "background_color",
+            ParameterValue::Color([0, 0, 0]),
+        )
+        .unwrap();
+        let mut img = RgbImage::new(192, 108);
+        algorithm
+            .build(&mut ChaCha8Rng::seed_from_u64(5), &mut img)
+            .unwrap();
+        let mut colors: Vec<[u8; 3]> = img.pixels().map(|pixel| pixel.0).collect();
+        colors.sort_unstable();
+        colors.dedup();
+        assert!(colors.len() > 3);
+    }
+
+    #[test]
+    fn inverted_scale_ranges_are_rejected() {
+        let mut algorithm = RandomPatterns::default();
+        // Recipes set max_scale before min_scale
+        for (name, value) in [("max_scale", 15.0), ("min_scale", 20.0)] {
+            Algorithm::<ChaCha8Rng>::configure(&mut algorithm, name, ParameterValue::Float(value))
+                .unwrap();
+        }
+        let mut img = RgbImage::new(64, 36);
+        let result = algorithm.build(&mut ChaCha8Rng::seed_from_u64(5), &mut img);
+        assert!(matches!(result, Err(Error::InvalidParameter { name, .. }) if name == "min_scale"));
+    }
+}
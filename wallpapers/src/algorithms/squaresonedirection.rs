@@ -1,238 +1,288 @@
-use crate::algorithms::Algorithm;
+use crate::algorithms::{check_min_dimensions, Algorithm, Parameter, ParameterValue};
 use crate::layers::squareslayer::SquaresLayer;
 use crate::layers::Layer;
+use crate::propagation::{Propagation, Weighting};
+use crate::seed::Streams;
+use crate::utils::scale::Scale;
+use crate::Error;
 use image::RgbImage;
 use rand::Rng;
 use std::cmp::{max, min};
 
-enum Direction {
-    Horizontal,
-    Vertical,
-    Diagonal,
-    None,
-}
-
-/// A simple algorithm that starts with a random colored square in the upper-right corner.
-/// It then iterates from left-to-right and bottom-down over each square
-/// and assigns the average color of each surrounding square with a random variation in a
-///random RGB channel to each square, until the bottom-right is reached.
-/// The surrounding square with the direction that this Struct is designed to prioritize
-/// is weighted N times as high as the surrounding squares.
+/// A simple algorithm that starts with a random colored square in the upper-left corner.
+/// It then iterates column by column over each square and assigns the average color of each
+/// surrounding square with a random variation to each square, until the bottom-right is reached.
+/// By default, the variation is applied to a random RGB channel. The `mutation` parameter varies
+/// all channels, the brightness, or the hue, chroma and lightness in oklch or hsv instead.
+/// The surrounding squares in the direction that this Struct is designed to prioritize
+/// are weighted N times as high as the other surrounding squares.
+/// The coloring is done by a [`Propagation`], whose traversal, neighborhood, weighting and
+/// mutation can be changed, so that the presets are only starting points.
 pub struct SquaresOneDirection {
     squares: SquaresLayer<[u8; 3]>,
-    variation_amount: u8,
-    visited_squares: Vec<Vec<bool>>,
-    direction: Direction,
-    weight: usize,
+    propagation: Propagation,
     additional_random_points: usize,
+    /// The width and height of each square in virtual units
+    square_size: f64,
+    /// The offset of the squares to the right and down in virtual units
+    offset: (f64, f64),
+    /// If true, the squares are centered on the image
+    centered: bool,
+    /// The gap between two squares in virtual units
+    grout: f64,
+    /// The color of the gap between two squares
+    grout_color: [u8; 3],
+    /// If true, the averaging wraps around at the image borders, so that the image can be tiled
+    tileable: bool,
+}
+
+/// Get the divisor of the given number that is closest to the given target
+fn nearest_divisor(number: u32, target: u32) -> u32 {
+    (1..=number)
+        .filter(|divisor| number.is_multiple_of(*divisor))
+        .min_by_key(|divisor| divisor.abs_diff(target))
+        .unwrap_or(1)
 }
 
 impl SquaresOneDirection {
-    pub fn new_horiz() -> Self {
+    fn new(
+        weighting: Weighting,
+        weight: usize,
+        variation_amount: u8,
+        additional_random_points: usize,
+    ) -> Self {
         SquaresOneDirection {
             squares: SquaresLayer::new(0, 0, 10, 10),
-            variation_amount: 25,
-            visited_squares: vec![],
-            direction: Direction::Horizontal,
-            weight: 6,
-            additional_random_points: 0,
+            propagation: Propagation {
+                weighting,
+                weight,
+                variation_amount,
+                ..Propagation::default()
+            },
+            additional_random_points,
+            square_size: 10.0,
+            offset: (0.0, 0.0),
+            centered: false,
+            grout: 0.0,
+            grout_color: [0, 0, 0],
+            tileable: false,
         }
     }
+    pub fn new_horiz() -> Self {
+        Self::new(Weighting::Horizontal, 6, 25, 0)
+    }
     pub fn new_vert() -> Self {
-        SquaresOneDirection {
-            squares: SquaresLayer::new(0, 0, 10, 10),
-            variation_amount: 25,
-            visited_squares: vec![],
-            direction: Direction::Vertical,
-            weight: 4,
-            additional_random_points: 0,
-        }
+        Self::new(Weighting::Vertical, 4, 25, 0)
     }
     pub fn new_diag() -> Self {
-        SquaresOneDirection {
-            squares: SquaresLayer::new(0, 0, 10, 10),
-            variation_amount: 25,
-            visited_squares: vec![],
-            direction: Direction::Diagonal,
-            weight: 4,
-            additional_random_points: 0,
-        }
+        Self::new(Weighting::Diagonal, 4, 25, 0)
     }
     pub fn new_nodir() -> Self {
-        SquaresOneDirection {
-            squares: SquaresLayer::new(0, 0, 10, 10),
-            variation_amount: 20,
-            visited_squares: vec![],
-            direction: Direction::None,
-            weight: 0,
-            additional_random_points: 0,
-        }
+        Self::new(Weighting::Uniform, 0, 20, 0)
     }
     pub fn new_nodir_randomized() -> Self {
-        SquaresOneDirection {
-            squares: SquaresLayer::new(0, 0, 10, 10),
-            variation_amount: 20,
-            visited_squares: vec![],
-            direction: Direction::None,
-            weight: 0,
-            additional_random_points: 32,
-        }
+        Self::new(Weighting::Uniform, 0, 20, 32)
     }
     pub fn new_horiz_randomized() -> Self {
-        SquaresOneDirection {
-            squares: SquaresLayer::new(0, 0, 10, 10),
-            variation_amount: 25,
-            visited_squares: vec![],
-            direction: Direction::Horizontal,
-            weight: 20,
-            additional_random_points: 48,
-        }
+        Self::new(Weighting::Horizontal, 20, 25, 48)
     }
     pub fn new_vert_randomized() -> Self {
-        SquaresOneDirection {
-            squares: SquaresLayer::new(0, 0, 10, 10),
-            variation_amount: 25,
-            visited_squares: vec![],
-            direction: Direction::Vertical,
-            weight: 8,
-            additional_random_points: 48,
-        }
+        Self::new(Weighting::Vertical, 8, 25, 48)
     }
 }
 
-impl SquaresOneDirection {
-    /// Create the initial additional random population of points
-    fn populate_points(&mut self, rng: &mut impl Rng) {
-        for _ in 0..self.additional_random_points {
-            let x = rng.gen_range(0..self.squares.squares_h());
-            let y = rng.gen_range(0..self.squares.squares_v());
-            let color: [u8; 3] = [
-                rng.gen_range(0..255),
-                rng.gen_range(0..255),
-                rng.gen_range(0..255),
-            ];
-            self.squares.set_color_at(x, y, color);
-            self.visited_squares[x][y] = true;
-        }
-    }
-    /// Gets the square color at the given location, or returns None, if the square has not yet been visited
-    fn get_square_color(&self, square_x: usize, square_y: usize) -> Option<&[u8; 3]> {
-        if self.visited_squares[square_x][square_y] {
-            Some(self.squares.get_color_at(square_x, square_y))
-        } else {
-            None
-        }
-    }
-    /// Gets the average color of the given square and all squares surrounding it, i.e. 9 squares.
-    /// All unvisited squares are skipped in the calculation.
-    /// If none of the 9 squares has been visited, return None
-    fn get_average_color_of_squares(&self, square_x: usize, square_y: usize) -> Option<[u8; 3]> {
-        // We need a vector here because elements may occur more than once
-        let mut colors: Vec<&[u8; 3]> = Vec::new();
-        for xx in max(0, square_x as i32 - 1)
-            ..=min(self.squares.squares_h() as i32 - 1, square_x as i32 + 1)
-        {
-            for yy in max(0, square_y as i32 - 1)
-                ..=min(self.squares.squares_v() as i32 - 1, square_y as i32 + 1)
-            {
-                if let Some(color) = self.get_square_color(xx as usize, yy as usize) {
-                    match self.direction {
-                        Direction::Horizontal => {
-                            if yy as usize == square_y && xx as usize != square_x {
-                                for _ in 0..self.weight {
-                                    colors.push(color);
-                                }
-                            } else {
-                                colors.push(color);
-                            }
-                        },
-                        Direction::Vertical => {
-                            if xx as usize == square_x && yy as usize != square_y {
-                                for _ in 0..self.weight {
-                                    colors.push(color);
-                                }
-                            } else {
-                                colors.push(color);
-                            }
-                        },
-                        Direction::Diagonal => {
-                            if xx as usize != square_x && yy as usize != square_y {
-                                for _ in 0..self.weight {
-                                    colors.push(color);
-                                }
-                            } else {
-                                colors.push(color);
-                            }
-                        },
-                        Direction::None => {
-                            colors.push(color);
-                        },
-                    }
-                }
+impl<R: Rng> Algorithm<R> for SquaresOneDirection {
+    fn build(&mut self, rng: &mut R, img: &mut RgbImage) -> Result<(), Error> {
+        check_min_dimensions::<R, _>(self, img, "Squares")?;
+        // The squares are sized in virtual units, so that the number of squares only depends on
+        // the aspect ratio and the size of each square in pixels depends on the resolution
+        let scale = Scale::of(img);
+        let to_pixels = |units: f64| scale.to_pixels(units).round().max(0.0) as usize;
+        let grout = to_pixels(self.grout);
+        let square_size = max(1, to_pixels(self.square_size));
+        let (squaresize_h, squaresize_v, grout) = if self.tileable {
+            if self.centered || self.offset != (0.0, 0.0) {
+                return Err(Error::InvalidParameter {
+                    name: "offset".to_string(),
+                    reason: "Centered or shifted squares cannot be tiled".to_string(),
+                });
             }
-        }
-        if !colors.is_empty() {
-            Some([
-                (colors.iter().map(|color| color[0] as u32).sum::<u32>() / colors.len() as u32)
-                    as u8,
-                (colors.iter().map(|color| color[1] as u32).sum::<u32>() / colors.len() as u32)
-                    as u8,
-                (colors.iter().map(|color| color[2] as u32).sum::<u32>() / colors.len() as u32)
-                    as u8,
-            ])
+            // The squares need to fill the image completely to continue at the opposite border
+            let pitch = self.square_size + self.grout;
+            let squares_h = ((scale.width() / pitch) as u32).clamp(1, img.width());
+            let squares_v = ((scale.height() / pitch) as u32).clamp(1, img.height());
+            let pitch_h = (img.width() / nearest_divisor(img.width(), squares_h)) as usize;
+            let pitch_v = (img.height() / nearest_divisor(img.height(), squares_v)) as usize;
+            let grout = min(grout, min(pitch_h, pitch_v) - 1);
+            (pitch_h - grout, pitch_v - grout, grout)
         } else {
-            None
-        }
+            (square_size, square_size, grout)
+        };
+        self.squares.set_squaresize_h(squaresize_h);
+        self.squares.set_squaresize_v(squaresize_v);
+        self.squares.set_grout(grout, Some(self.grout_color));
+        self.squares.set_centered(self.centered);
+        self.squares.set_offset(
+            scale.to_pixels(self.offset.0).round() as i64,
+            scale.to_pixels(self.offset.1).round() as i64,
+        );
+        self.squares
+            .adjust_square_count_to_image_dimensions(img.width() as usize, img.height() as usize);
+        // n points per 1000x1000 virtual units
+        let num_points =
+            (self.additional_random_points as f64 * scale.area() / (1000.0 * 1000.0)) as usize;
+        let propagation = Propagation {
+            wrap: self.tileable,
+            ..self.propagation.clone()
+        };
+        propagation.run(&mut self.squares, &Streams::from_rng(rng), num_points)?;
+        self.squares.draw(img)?;
+        Ok(())
     }
-
-    fn color_square_average(&mut self, rng: &mut impl Rng, square_x: usize, square_y: usize) {
-        if let Some(average_color) = self.get_average_color_of_squares(square_x, square_y) {
-            let color_offset: i32 =
-                rng.gen_range(-(self.variation_amount as i32)..=(self.variation_amount as i32));
-            let mut variant_color: [u8; 3] = average_color;
-            let channel_id: usize = rng.gen_range(0..3);
-            if color_offset > 0 {
-                variant_color[channel_id] =
-                    min(255, average_color[channel_id] as i32 + color_offset) as u8;
-            } else {
-                variant_color[channel_id] =
-                    max(0, average_color[channel_id] as i32 + color_offset) as u8;
-            }
-            self.squares.set_color_at(square_x, square_y, variant_color);
-        } else {
-            // Setting a color without any adjacent neighbors --> Completely random color
-            self.squares.set_color_at(
-                square_x,
-                square_y,
-                [
-                    rng.gen_range(0..255),
-                    rng.gen_range(0..255),
-                    rng.gen_range(0..255),
-                ],
-            );
+    fn supports_tiling(&self) -> bool {
+        true
+    }
+    fn set_tileable(&mut self, tileable: bool) {
+        self.tileable = tileable;
+    }
+    fn parameters(&self) -> Vec<Parameter> {
+        let mut parameters = self.propagation.parameters();
+        parameters.extend([
+            Parameter::new(
+                "additional_random_points",
+                "The number of randomly colored squares per 1000x1000 pixels of a 1080p image",
+                ParameterValue::Integer(self.additional_random_points as i64),
+            )
+            .with_range(0.0, 100_000.0),
+            Parameter::new(
+                "square_size",
+                "The width and height of each square in pixels of a 1080p image",
+                ParameterValue::Float(self.square_size),
+            )
+            .with_range(1.0, 10_000.0),
+            Parameter::new(
+                "centered",
+                "Center the squares on the image, so that the squares at opposite borders are cut \
+                 off evenly",
+                ParameterValue::Boolean(self.centered),
+            ),
+            Parameter::new(
+                "offset_x",
+                "Shift the squares to the right by this many pixels of a 1080p image",
+                ParameterValue::Float(self.offset.0),
+            )
+            .with_range(-10_000.0, 10_000.0),
+            Parameter::new(
+                "offset_y",
+                "Shift the squares down by this many pixels of a 1080p image",
+                ParameterValue::Float(self.offset.1),
+            )
+            .with_range(-10_000.0, 10_000.0),
+            Parameter::new(
+                "grout",
+                "The gap between two squares in pixels of a 1080p image",
+                ParameterValue::Float(self.grout),
+            )
+            .with_range(0.0, 10_000.0),
+            Parameter::new(
+                "grout_color",
+                "The color of the gap between two squares",
+                ParameterValue::Color(self.grout_color),
+            ),
+        ]);
+        parameters
+    }
+    fn set_parameter(&mut self, name: &str, value: &ParameterValue) -> Result<(), Error> {
+        match (name, value) {
+            ("additional_random_points", ParameterValue::Integer(v)) => {
+                self.additional_random_points = *v as usize
+            },
+            ("square_size", ParameterValue::Float(v)) => self.square_size = *v,
+            ("centered", ParameterValue::Boolean(v)) => self.centered = *v,
+            ("offset_x", ParameterValue::Float(v)) => self.offset.0 = *v,
+            ("offset_y", ParameterValue::Float(v)) => self.offset.1 = *v,
+            ("grout", ParameterValue::Float(v)) => self.grout = *v,
+            ("grout_color", ParameterValue::Color(c)) => self.grout_color = *c,
+            _ => self.propagation.set_parameter(name, value)?,
         }
-        self.visited_squares[square_x][square_y] = true;
+        Ok(())
     }
 }
 
-impl<R: Rng> Algorithm<R> for SquaresOneDirection {
-    fn build(&mut self, rng: &mut R, img: &mut RgbImage) -> Result<(), String> {
-        self.squares
-            .adjust_square_count_to_image_dimensions(img.width() as usize, img.height() as usize);
-        self.visited_squares =
-            vec![vec![false; self.squares.squares_v()]; self.squares.squares_h()];
-        // n points per 1000x1000 pixels
-        self.additional_random_points = (self.additional_random_points as f64
-            * (img.width() as f64 * img.height() as f64)
-            / (1000.0 * 1000.0)) as usize;
-        self.populate_points(rng);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ChaCha8Rng, SeedableRng};
 
-        for x in 0..self.squares.squares_h() {
-            for y in 0..self.squares.squares_v() {
-                self.color_square_average(rng, x, y);
+    #[test]
+    fn squares_fill_images_that_are_no_multiple_of_the_square_size() {
+        // Half of 1366x768, where squares of 4 pixels leave a strip of 3 pixels
+        let mut img = RgbImage::new(683, 384);
+        let mut algorithm = SquaresOneDirection::new_horiz();
+        algorithm
+            .build(&mut ChaCha8Rng::seed_from_u64(1), &mut img)
+            .unwrap();
+        let right = (0..384).map(|y| img.get_pixel(682, y));
+        assert!(right.filter(|pixel| pixel.0 == [0, 0, 0]).count() < 10);
+        let bottom = (0..683).map(|x| img.get_pixel(x, 383));
+        assert!(bottom.filter(|pixel| pixel.0 == [0, 0, 0]).count() < 10);
+        Algorithm::<ChaCha8Rng>::configure(
+            &mut algorithm,
+            "centered",
+            ParameterValue::Boolean(true),
+        )
+        .unwrap();
+        let mut tileable = RgbImage::new(64, 48);
+        algorithm.tileable = true;
+        assert!(algorithm
+            .build(&mut ChaCha8Rng::seed_from_u64(1), &mut tileable)
+            .is_err());
+    }
+
+    /// Get the mean difference between the given pixel columns, or rows if vertical
+    fn line_difference(img: &RgbImage, (a, b): (u32, u32), vertical: bool) -> f64 {
+        let length = if vertical { img.width() } else { img.height() };
+        let sum: u32 = (0..length)
+            .map(|i| {
+                let (pa, pb) = if vertical {
+                    (img.get_pixel(i, a), img.get_pixel(i, b))
+                } else {
+                    (img.get_pixel(a, i), img.get_pixel(b, i))
+                };
+                (0..3)
+                    .map(|c| pa.0[c].abs_diff(pb.0[c]) as u32)
+                    .sum::<u32>()
+            })
+            .sum();
+        sum as f64 / (length * 3) as f64
+    }
+
+    #[test]
+    fn tileable_squares_continue_at_both_seams() {
+        for seed in 0..3 {
+            for mut algorithm in [
+                SquaresOneDirection::new_nodir(),
+                SquaresOneDirection::new_horiz(),
+                SquaresOneDirection::new_vert_randomized(),
+            ] {
+                algorithm.tileable = true;
+                algorithm.square_size = 40.0;
+                let mut img = RgbImage::new(320, 240);
+                algorithm
+                    .build(&mut ChaCha8Rng::seed_from_u64(seed), &mut img)
+                    .unwrap();
+                for vertical in [false, true] {
+                    let size = if vertical { 240 } else { 320 };
+                    let seam = line_difference(&img, (size - 1, 0), vertical);
+                    let steps: Vec<f64> = (1..size)
+                        .filter(|i| i % 8 == 0)
+                        .map(|i| line_difference(&img, (i - 1, i), vertical))
+                        .collect();
+                    let step = steps.iter().sum::<f64>() / steps.len() as f64;
+                    // The seam is not more visible than the steps between the other lines
+                    assert!(seam <= 1.5 * step, "{} {} {}", vertical, seam, step);
+                }
             }
         }
-        self.squares.draw(img)?;
-        Ok(())
     }
 }
@@ -1,30 +1,51 @@
-use crate::algorithms::Algorithm;
+use crate::algorithms::parameters::unknown_parameter;
+use crate::algorithms::{check_min_dimensions, Algorithm, Parameter, ParameterValue};
+use crate::seed::Streams;
 use crate::utils::perlin::Perlin;
+use crate::utils::scale::Scale;
+use crate::Error;
 use image::{Rgb, RgbImage};
 use rand::Rng;
 
-#[derive(Default)]
-pub struct Tangles {}
+pub struct Tangles {
+    /// The color of the image background
+    background_color: [u8; 3],
+    /// The image area in square virtual units that is used to compute the number of rectangles
+    pixels_per_rectangle: u32,
+}
+
+impl Default for Tangles {
+    fn default() -> Self {
+        Tangles {
+            background_color: [230, 230, 230],
+            pixels_per_rectangle: 80000,
+        }
+    }
+}
 
 impl Tangles {
-    /// Draw the given rectangle and shade the given base color with random perlin noise
+    /// Draw the given rectangle and shade the given base color with random perlin noise.
+    /// The position and size of the rectangle are given in virtual units.
     fn draw_rectangle(
         &self,
         rng: &mut impl Rng,
         img: &mut RgbImage,
-        x0: usize,
-        y0: usize,
-        w: usize,
-        h: usize,
+        scale: &Scale,
+        (x0, y0): (f64, f64),
+        (w, h): (f64, f64),
         base_color: [u8; 3],
-    ) -> Result<(), String> {
-        let mut perlin = Perlin::new(w, h);
+    ) -> Result<(), Error> {
+        let mut perlin = Perlin::for_fractal(w, h, 0.002, 6);
         perlin.regenerate_noise(rng);
-        for x in x0..(x0 + w) {
-            for y in y0..(y0 + h) {
-                let noise_shade: f64 =
-                    200.0 + (perlin.fractal((x - x0) as f64, (y - y0) as f64, 0.002, 6)? * 55.0);
-                let pixel = img.get_pixel_mut(x as u32, y as u32);
+        let (px0, py0) = scale.pixel_at(x0, y0);
+        let px1 = (scale.to_pixels(x0 + w).round() as u32).min(img.width());
+        let py1 = (scale.to_pixels(y0 + h).round() as u32).min(img.height());
+        for x in px0..px1 {
+            for y in py0..py1 {
+                let noise_x = (scale.to_units(x as f64) - x0).clamp(0.0, w);
+                let noise_y = (scale.to_units(y as f64) - y0).clamp(0.0, h);
+                let noise_shade: f64 = 200.0 + (perlin.fractal(noise_x, noise_y, 0.002, 6)? * 55.0);
+                let pixel = img.get_pixel_mut(x, y);
                 *pixel = Rgb([
                     (base_color[0] as f64 * (noise_shade / 255.0)) as u8,
                     (base_color[1] as f64 * (noise_shade / 255.0)) as u8,
@@ -34,49 +55,93 @@ impl Tangles {
         }
         Ok(())
     }
-    /// Draw a rectangle at a random position with a maximum size of maxsize
+    /// Draw a rectangle at a random position with a maximum size of maxsize virtual units
     fn draw_random_rectangle(
         &self,
         rng: &mut impl Rng,
+        noise_rng: &mut impl Rng,
         img: &mut RgbImage,
-        maxsize: usize,
+        scale: &Scale,
+        maxsize: f64,
         base_color: [u8; 3],
-    ) -> Result<(), String> {
-        let w: usize = rng.gen_range(0..maxsize);
-        let h: usize = maxsize - w;
-        let x0: usize = rng.gen_range(0..(img.width() as usize - w));
-        let y0: usize = rng.gen_range(0..(img.height() as usize - h));
-        self.draw_rectangle(rng, img, x0, y0, w, h, base_color)
+    ) -> Result<(), Error> {
+        let w: f64 = rng.gen::<f64>() * maxsize.min(scale.width());
+        let h: f64 = maxsize - w;
+        let x0: f64 = rng.gen::<f64>() * (scale.width() - w).max(0.0);
+        let y0: f64 = rng.gen::<f64>() * (scale.height() - h).max(0.0);
+        self.draw_rectangle(noise_rng, img, scale, (x0, y0), (w, h), base_color)
     }
 }
 
 impl<R: Rng> Algorithm<R> for Tangles {
-    fn build(&mut self, rng: &mut R, img: &mut RgbImage) -> Result<(), String> {
+    fn build(&mut self, rng: &mut R, img: &mut RgbImage) -> Result<(), Error> {
+        check_min_dimensions::<R, _>(self, img, "Tangles")?;
         // Set Background Color
         for (_, _, pixel) in img.enumerate_pixels_mut() {
-            *pixel = Rgb([230, 230, 230]);
+            *pixel = Rgb(self.background_color);
         }
-        let mut base_r: u8 = 128 + rng.gen_range(0..128);
-        let mut base_g: u8 = 128 + rng.gen_range(0..128);
-        let mut base_b: u8 = 128 + rng.gen_range(0..128);
-        let accent: u8 = 240 + rng.gen_range(0..16);
-        match rng.gen_range(0..3) {
+        let streams = Streams::from_rng(rng);
+        let mut palette = streams.rng("palette");
+        let mut base_r: u8 = 128 + palette.gen_range(0..128);
+        let mut base_g: u8 = 128 + palette.gen_range(0..128);
+        let mut base_b: u8 = 128 + palette.gen_range(0..128);
+        let accent: u8 = 240 + palette.gen_range(0..16);
+        match palette.gen_range(0..3) {
             0 => base_r = accent,
             1 => base_g = accent,
-            2 => base_b = accent,
-            _ => panic!("This code should never be reached!"),
+            _ => base_b = accent,
         };
-        let num_rectangles = (img.width() * img.height() / 80000).clamp(16, 255);
+        let mut rectangles = streams.rng("rectangles");
+        let mut noise = streams.rng("noise");
+        let scale = Scale::of(img);
+        let num_rectangles =
+            ((scale.area() / self.pixels_per_rectangle as f64) as u32).clamp(16, 255);
         for i in 0..num_rectangles {
-            let maxsize = (img.height() - i * img.height() / 40)
-                .try_into()
-                .expect("maxsize out of bounds");
+            // The rectangles get smaller with each iteration, stop as soon as they would vanish
+            let maxsize = scale.height() * (1.0 - i as f64 / 40.0);
+            if maxsize <= 0.0 {
+                break;
+            }
+            let darken: u8 = (i * 5).min(u8::MAX as u32) as u8;
             let base_color = [
-                base_r - i as u8 * 5,
-                base_g - i as u8 * 5,
-                base_b - i as u8 * 5,
+                base_r.saturating_sub(darken),
+                base_g.saturating_sub(darken),
+                base_b.saturating_sub(darken),
             ];
-            self.draw_random_rectangle(rng, img, maxsize, base_color)?;
+            self.draw_random_rectangle(
+                &mut rectangles,
+                &mut noise,
+                img,
+                &scale,
+                maxsize,
+                base_color,
+            )?;
+        }
+        Ok(())
+    }
+    fn parameters(&self) -> Vec<Parameter> {
+        vec![
+            Parameter::new(
+                "background_color",
+                "The color of the image background",
+                ParameterValue::Color(self.background_color),
+            ),
+            Parameter::new(
+                "pixels_per_rectangle",
+                "The image area per rectangle in pixels of a 1080p image, at least 16 and at most 255 \
+                 rectangles are drawn",
+                ParameterValue::Integer(self.pixels_per_rectangle as i64),
+            )
+            .with_range(1.0, 10_000_000.0),
+        ]
+    }
+    fn set_parameter(&mut self, name: &str, value: &ParameterValue) -> Result<(), Error> {
+        match (name, value) {
+            ("background_color", ParameterValue::Color(c)) => self.background_color = *c,
+            ("pixels_per_rectangle", ParameterValue::Integer(v)) => {
+                self.pixels_per_rectangle = *v as u32
+            },
+            _ => return Err(unknown_parameter(name)),
         }
         Ok(())
     }
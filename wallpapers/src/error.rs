@@ -0,0 +1,87 @@
+use std::fmt::{Display, Formatter};
+
+/// All errors that can occur while generating a wallpaper
+#[derive(Debug)]
+pub enum Error {
+    /// The image dimensions are not supported by an algorithm or layer
+    InvalidDimensions {
+        width: u32,
+        height: u32,
+        reason: String,
+    },
+    /// A parameter has an invalid value
+    InvalidParameter { name: String, reason: String },
+    /// No algorithm with the given name is registered
+    UnknownAlgorithm(String),
+    /// A noise value was requested outside of the area the noise was generated for
+    NoiseOutOfBounds {
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+    },
+    /// A pattern definition could not be parsed
+    InvalidPattern(String),
+    /// Generation metadata could not be embedded into or read from an image file
+    Metadata(String),
+    /// Reading or writing a file failed
+    Io(std::io::Error),
+    /// Encoding or decoding an image failed
+    Image(image::ImageError),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::InvalidDimensions {
+                width,
+                height,
+                reason,
+            } => write!(
+                f,
+                "Invalid image dimensions {}x{}: {}",
+                width, height, reason
+            ),
+            Error::InvalidParameter { name, reason } => {
+                write!(f, "Invalid parameter {}: {}", name, reason)
+            },
+            Error::UnknownAlgorithm(name) => write!(f, "Unknown algorithm: {}", name),
+            Error::NoiseOutOfBounds {
+                x,
+                y,
+                width,
+                height,
+            } => write!(
+                f,
+                "Coordinates ({},{}) out of bounds for noise of size ({},{})",
+                x, y, width, height
+            ),
+            Error::InvalidPattern(reason) => write!(f, "Invalid pattern: {}", reason),
+            Error::Metadata(reason) => write!(f, "Metadata error: {}", reason),
+            Error::Io(err) => write!(f, "I/O error: {}", err),
+            Error::Image(err) => write!(f, "Image error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            Error::Image(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<image::ImageError> for Error {
+    fn from(err: image::ImageError) -> Self {
+        Error::Image(err)
+    }
+}
@@ -0,0 +1,461 @@
+//! Layers made of cells of a regular grid, e.g. hexagons, each filled with a single color.
+//!
+//! All grids share the [`CellGrid`] interface, so that algorithms can color the cells of any grid
+//! by looking at the colors of their neighbors. The cells of a [`CellLayer`] are laid out by a
+//! [`CellShape`], which maps pixels to cells. Cells at the image border may be cut off, so that the
+//! grid covers the complete image.
+use crate::layers::squareslayer::SquaresLayer;
+use crate::layers::{mask, Layer, MixMode};
+use crate::Error;
+use image::{GrayImage, Rgba, RgbaImage};
+use std::cmp::min;
+
+/// A grid of colored cells, addressed by column and row
+pub trait CellGrid<Color> {
+    /// Get the number of cells in each direction as (columns, rows)
+    fn cell_count(&self) -> (usize, usize);
+    fn get_color_at(&self, x: usize, y: usize) -> &Color;
+    fn set_color_at(&mut self, x: usize, y: usize, color: Color);
+    /// Get all cells of the grid that share an edge with the given cell
+    fn neighbors(&self, x: usize, y: usize) -> Vec<(usize, usize)>;
+    /// Get all cells of the grid that share an edge or a corner with the given cell
+    fn touching(&self, x: usize, y: usize) -> Vec<(usize, usize)>;
+    /// Adjust the number of cells, so that the cells cover an image of the given size.
+    /// This will adjust the data accordingly, filling up all new cells with Color::default()
+    fn adjust_cell_count_to_image_dimensions(&mut self, img_width: usize, img_height: usize);
+}
+
+/// The shape of the cells of a [`CellLayer`], which defines how the cells are laid out
+pub trait CellShape {
+    /// Get the number of cells as (columns, rows) that is needed to cover an image of the given
+    /// size in pixels
+    fn cell_count(&self, width: usize, height: usize) -> (usize, usize);
+    /// Get the cell as (column, row) that contains the given point in pixels. The cell may lie
+    /// outside of the grid for points outside of the image.
+    fn cell_at(&self, x: f64, y: f64) -> (i64, i64);
+    /// Get all cells that share an edge with the given cell. The cells may lie outside of the grid.
+    fn neighbors(&self, x: i64, y: i64) -> Vec<(i64, i64)>;
+    /// Get all cells that share an edge or a corner with the given cell. The cells may lie outside
+    /// of the grid. By default, these are the neighbors, which is true for hexagons.
+    fn touching(&self, x: i64, y: i64) -> Vec<(i64, i64)> {
+        self.neighbors(x, y)
+    }
+}
+
+/// Hexagons with a pointed top, in rows where every other row is shifted right by half a cell
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Hexagons {
+    /// The horizontal distance between the centers of two adjacent hexagons in pixels
+    pub size: f64,
+}
+
+impl Hexagons {
+    /// Get the vertical distance between two rows in pixels
+    fn row_height(&self) -> f64 {
+        self.size * 3.0_f64.sqrt() / 2.0
+    }
+    /// Get the center of the given cell in pixels. The first cell is centered on the top left
+    /// corner of the image.
+    fn center(&self, x: i64, y: i64) -> (f64, f64) {
+        let shift = y.rem_euclid(2) as f64 / 2.0;
+        ((x as f64 + shift) * self.size, y as f64 * self.row_height())
+    }
+}
+
+impl CellShape for Hexagons {
+    fn cell_count(&self, width: usize, height: usize) -> (usize, usize) {
+        (
+            (width as f64 / self.size).ceil() as usize + 1,
+            (height as f64 / self.row_height()).ceil() as usize + 1,
+        )
+    }
+    fn cell_at(&self, x: f64, y: f64) -> (i64, i64) {
+        // The hexagons are the areas that are nearest to the center of a cell, so the point lies
+        // in the nearest cell of the two rows around it
+        let row = (y / self.row_height()).floor() as i64;
+        [row, row + 1]
+            .into_iter()
+            .map(|row| {
+                let shift = row.rem_euclid(2) as f64 / 2.0;
+                ((x / self.size - shift).round() as i64, row)
+            })
+            .min_by(|a, b| {
+                let distance = |(cx, cy): (f64, f64)| (cx - x).hypot(cy - y);
+                distance(self.center(a.0, a.1)).total_cmp(&distance(self.center(b.0, b.1)))
+            })
+            .unwrap_or((0, row))
+    }
+    fn neighbors(&self, x: i64, y: i64) -> Vec<(i64, i64)> {
+        // The columns of the diagonal neighbors depend on whether the row is shifted
+        let left = x - 1 + y.rem_euclid(2);
+        vec![
+            (x - 1, y),
+            (x + 1, y),
+            (left, y - 1),
+            (left + 1, y - 1),
+            (left, y + 1),
+            (left + 1, y + 1),
+        ]
+    }
+}
+
+/// Equilateral triangles in rows of alternately upward and downward pointing triangles
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Triangles {
+    /// The width of the base of a triangle in pixels
+    pub size: f64,
+}
+
+impl Triangles {
+    fn row_height(&self) -> f64 {
+        self.size * 3.0_f64.sqrt() / 2.0
+    }
+    /// Check whether the given cell points upwards, i.e. has its base at the bottom
+    fn points_up(x: i64, y: i64) -> bool {
+        (x + y).rem_euclid(2) == 0
+    }
+}
+
+impl CellShape for Triangles {
+    fn cell_count(&self, width: usize, height: usize) -> (usize, usize) {
+        (
+            (2.0 * width as f64 / self.size).ceil() as usize + 1,
+            (height as f64 / self.row_height()).ceil() as usize,
+        )
+    }
+    fn cell_at(&self, x: f64, y: f64) -> (i64, i64) {
+        // Each cell spans two half-widths and has its apex or the center of its base in the
+        // middle, so a strip of half a triangle width contains parts of two cells, separated by
+        // a diagonal edge
+        let row = (y / self.row_height()).floor() as i64;
+        let fraction_y = y / self.row_height() - row as f64;
+        let u = 2.0 * x / self.size;
+        let strip = u.floor() as i64;
+        let t = u - strip as f64;
+        let right = strip + 1;
+        let in_right = if Triangles::points_up(right, row) {
+            t >= 1.0 - fraction_y
+        } else {
+            t >= fraction_y
+        };
+        (if in_right { right } else { strip }, row)
+    }
+    fn neighbors(&self, x: i64, y: i64) -> Vec<(i64, i64)> {
+        let across_base = if Triangles::points_up(x, y) {
+            y + 1
+        } else {
+            y - 1
+        };
+        vec![(x - 1, y), (x + 1, y), (x, across_base)]
+    }
+    fn touching(&self, x: i64, y: i64) -> Vec<(i64, i64)> {
+        // Three cells touch the apex from the other row, and five cells touch the base
+        let (apex_row, base_row) = if Triangles::points_up(x, y) {
+            (y - 1, y + 1)
+        } else {
+            (y + 1, y - 1)
+        };
+        let apex = (x - 1..=x + 1).map(|x| (x, apex_row));
+        let row = [x - 2, x - 1, x + 1, x + 2].map(|x| (x, y));
+        let base = (x - 2..=x + 2).map(|x| (x, base_row));
+        apex.chain(row).chain(base).collect()
+    }
+}
+
+/// Rhombi with a horizontal and a vertical diagonal, in rows where every other row is shifted right
+/// by half a cell
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Rhombi {
+    /// The length of the horizontal diagonal in pixels
+    pub width: f64,
+    /// The length of the vertical diagonal in pixels
+    pub height: f64,
+}
+
+impl Rhombi {
+    /// Convert a cell to coordinates along the edges of the rhombi, where each rhombus is a unit
+    /// square
+    fn to_edge_coordinates(x: i64, y: i64) -> (i64, i64) {
+        let shifted = 2 * x + y.rem_euclid(2);
+        ((shifted + y) / 2, (shifted - y) / 2)
+    }
+    /// Convert coordinates along the edges of the rhombi back to a cell
+    fn from_edge_coordinates(p: i64, q: i64) -> (i64, i64) {
+        let y = p - q;
+        ((p + q - y.rem_euclid(2)) / 2, y)
+    }
+}
+
+impl CellShape for Rhombi {
+    fn cell_count(&self, width: usize, height: usize) -> (usize, usize) {
+        (
+            (width as f64 / self.width).ceil() as usize + 1,
+            (2.0 * height as f64 / self.height).ceil() as usize + 1,
+        )
+    }
+    fn cell_at(&self, x: f64, y: f64) -> (i64, i64) {
+        // Scale the rhombi to a unit size and rotate them by 45 degrees, which turns them into
+        // a grid of unit squares
+        let (x, y) = (2.0 * x / self.width, 2.0 * y / self.height);
+        let p = ((x + y) / 2.0).round() as i64;
+        let q = ((x - y) / 2.0).round() as i64;
+        Rhombi::from_edge_coordinates(p, q)
+    }
+    fn neighbors(&self, x: i64, y: i64) -> Vec<(i64, i64)> {
+        let (p, q) = Rhombi::to_edge_coordinates(x, y);
+        [(p - 1, q), (p + 1, q), (p, q - 1), (p, q + 1)]
+            .into_iter()
+            .map(|(p, q)| Rhombi::from_edge_coordinates(p, q))
+            .collect()
+    }
+    fn touching(&self, x: i64, y: i64) -> Vec<(i64, i64)> {
+        let (p, q) = Rhombi::to_edge_coordinates(x, y);
+        (p - 1..=p + 1)
+            .flat_map(|p| (q - 1..=q + 1).map(move |q| (p, q)))
+            .filter(|cell| *cell != (p, q))
+            .map(|(p, q)| Rhombi::from_edge_coordinates(p, q))
+            .collect()
+    }
+}
+
+/// A layer of cells of the given shape, each filled with a single color, that can be drawn on top
+/// of an image
+pub struct CellLayer<Shape: CellShape, Color: Default + Clone> {
+    shape: Shape,
+    cells_h: usize,
+    cells_v: usize,
+    mixmode: MixMode,
+    /// The opacity of the cells in [0, 1]
+    opacity: f64,
+    mask: Option<GrayImage>,
+    data: Vec<Vec<Color>>,
+}
+
+/// A layer of hexagonal cells
+pub type HexagonLayer<Color> = CellLayer<Hexagons, Color>;
+/// A layer of triangular cells
+pub type TriangleLayer<Color> = CellLayer<Triangles, Color>;
+/// A layer of rhombic cells
+pub type RhombusLayer<Color> = CellLayer<Rhombi, Color>;
+
+// Constructor
+impl<Shape: CellShape, Color: Default + Clone> CellLayer<Shape, Color> {
+    pub fn new(shape: Shape, cells_h: usize, cells_v: usize) -> Self {
+        CellLayer {
+            shape,
+            cells_h,
+            cells_v,
+            mixmode: MixMode::Normal,
+            opacity: 1.0,
+            mask: None,
+            data: vec![vec![Color::default(); cells_v]; cells_h],
+        }
+    }
+}
+
+// Getters and setters
+impl<Shape: CellShape, Color: Default + Clone> CellLayer<Shape, Color> {
+    pub fn shape(&self) -> &Shape {
+        &self.shape
+    }
+    /// Set a new shape of the cells. The number of cells needs to be adjusted afterwards.
+    pub fn set_shape(&mut self, shape: Shape) {
+        self.shape = shape;
+    }
+    /// Set the number of cells in each direction.
+    /// This will adjust the data accordingly, filling up all new cells with Color::default()
+    pub fn set_cell_count(&mut self, cells_h: usize, cells_v: usize) {
+        self.cells_h = cells_h;
+        self.cells_v = cells_v;
+        self.data
+            .resize_with(self.cells_h, || vec![Color::default(); self.cells_v]);
+        for colvec in self.data.iter_mut() {
+            colvec.resize_with(self.cells_v, || Color::default());
+        }
+    }
+    /// Get the cell that contains the given pixel, or None if it lies outside of the grid
+    pub fn cell_at_pixel(&self, x: u32, y: u32) -> Option<(usize, usize)> {
+        let (x, y) = self.shape.cell_at(x as f64 + 0.5, y as f64 + 0.5);
+        self.in_grid(x, y)
+    }
+    /// Convert the given cell to a cell of the grid, or None if it lies outside of the grid
+    fn in_grid(&self, x: i64, y: i64) -> Option<(usize, usize)> {
+        let (x, y) = (usize::try_from(x).ok()?, usize::try_from(y).ok()?);
+        (x < self.cells_h && y < self.cells_v).then_some((x, y))
+    }
+}
+
+impl<Shape: CellShape, Color: Default + Clone> CellGrid<Color> for CellLayer<Shape, Color> {
+    fn cell_count(&self) -> (usize, usize) {
+        (self.cells_h, self.cells_v)
+    }
+    fn get_color_at(&self, x: usize, y: usize) -> &Color {
+        &self.data[x][y]
+    }
+    fn set_color_at(&mut self, x: usize, y: usize, color: Color) {
+        self.data[x][y] = color;
+    }
+    fn neighbors(&self, x: usize, y: usize) -> Vec<(usize, usize)> {
+        self.shape
+            .neighbors(x as i64, y as i64)
+            .into_iter()
+            .filter_map(|(x, y)| self.in_grid(x, y))
+            .collect()
+    }
+    fn touching(&self, x: usize, y: usize) -> Vec<(usize, usize)> {
+        self.shape
+            .touching(x as i64, y as i64)
+            .into_iter()
+            .filter_map(|(x, y)| self.in_grid(x, y))
+            .collect()
+    }
+    fn adjust_cell_count_to_image_dimensions(&mut self, img_width: usize, img_height: usize) {
+        let (cells_h, cells_v) = self.shape.cell_count(img_width, img_height);
+        self.set_cell_count(cells_h.max(1), cells_v.max(1));
+    }
+}
+
+impl<Shape: CellShape> Layer for CellLayer<Shape, [u8; 3]> {
+    /// Draw the cells onto an image. Cells that exceed the image are cut off.
+    fn draw_rgba(&self, img: &mut RgbaImage) -> Result<(), Error> {
+        mask::check_size(self.mask.as_ref(), img)?;
+        for (x, y, pixel) in img.enumerate_pixels_mut() {
+            if let Some((cell_x, cell_y)) = self.cell_at_pixel(x, y) {
+                let coverage = mask::coverage(self.mask.as_ref(), x, y);
+                let [r, g, b] = self.data[cell_x][cell_y];
+                *pixel = Rgba(self.mixmode.mix_rgba(
+                    pixel.0,
+                    [r, g, b, u8::MAX],
+                    self.opacity * coverage,
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn set_mix_mode(&mut self, mode: MixMode) {
+        self.mixmode = mode;
+    }
+
+    fn get_mix_mode(&self) -> MixMode {
+        self.mixmode
+    }
+
+    fn set_opacity(&mut self, opacity: f64) {
+        self.opacity = opacity.clamp(0.0, 1.0);
+    }
+
+    fn get_opacity(&self) -> f64 {
+        self.opacity
+    }
+
+    fn set_mask(&mut self, mask: Option<GrayImage>) {
+        self.mask = mask;
+    }
+
+    fn get_mask(&self) -> Option<&GrayImage> {
+        self.mask.as_ref()
+    }
+}
+
+impl<Color: Default + Clone> CellGrid<Color> for SquaresLayer<Color> {
+    fn cell_count(&self) -> (usize, usize) {
+        (self.squares_h(), self.squares_v())
+    }
+    fn get_color_at(&self, x: usize, y: usize) -> &Color {
+        SquaresLayer::get_color_at(self, x, y)
+    }
+    fn set_color_at(&mut self, x: usize, y: usize, color: Color) {
+        SquaresLayer::set_color_at(self, x, y, color)
+    }
+    fn neighbors(&self, x: usize, y: usize) -> Vec<(usize, usize)> {
+        self.touching(x, y)
+            .into_iter()
+            .filter(|(xx, yy)| *xx == x || *yy == y)
+            .collect()
+    }
+    fn touching(&self, x: usize, y: usize) -> Vec<(usize, usize)> {
+        let xs = x.saturating_sub(1)..=min(x + 1, self.squares_h().saturating_sub(1));
+        xs.flat_map(|xx| {
+            let ys = y.saturating_sub(1)..=min(y + 1, self.squares_v().saturating_sub(1));
+            ys.map(move |yy| (xx, yy))
+        })
+        .filter(|cell| *cell != (x, y))
+        .collect()
+    }
+    fn adjust_cell_count_to_image_dimensions(&mut self, img_width: usize, img_height: usize) {
+        self.adjust_square_count_to_image_dimensions(img_width, img_height);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Check that the neighbors of each cell are neighbors of each other and touch the cell
+    fn check_neighbors<Shape: CellShape>(shape: Shape) {
+        let mut layer: CellLayer<Shape, [u8; 3]> = CellLayer::new(shape, 0, 0);
+        layer.adjust_cell_count_to_image_dimensions(60, 40);
+        let (cells_h, cells_v) = layer.cell_count();
+        for x in 0..cells_h {
+            for y in 0..cells_v {
+                let touching = layer.touching(x, y);
+                for (nx, ny) in layer.neighbors(x, y) {
+                    assert!(layer.neighbors(nx, ny).contains(&(x, y)));
+                    assert!(touching.contains(&(nx, ny)));
+                }
+                for (nx, ny) in touching {
+                    assert!(layer.touching(nx, ny).contains(&(x, y)));
+                }
+                layer.set_color_at(x, y, [(x * 4) as u8, (y * 4) as u8, 0]);
+            }
+        }
+        // Each pixel is colored by its cell, and adjacent pixels lie in touching cells
+        let mut img = RgbaImage::new(60, 40);
+        layer.draw_rgba(&mut img).unwrap();
+        for (x, y, pixel) in img.enumerate_pixels() {
+            let cell = layer.cell_at_pixel(x, y).unwrap();
+            assert_eq!(pixel.0, [(cell.0 * 4) as u8, (cell.1 * 4) as u8, 0, 255]);
+            for (nx, ny) in [(x + 1, y), (x, y + 1)] {
+                if let Some(other) = layer.cell_at_pixel(nx, ny) {
+                    assert!(
+                        other == cell || layer.touching(cell.0, cell.1).contains(&other),
+                        "{:?} and {:?} at {}, {}",
+                        cell,
+                        other,
+                        x,
+                        y
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn cells_cover_the_image_and_touch_their_neighbors() {
+        check_neighbors(Hexagons { size: 7.0 });
+        check_neighbors(Triangles { size: 8.0 });
+        check_neighbors(Rhombi {
+            width: 9.0,
+            height: 6.0,
+        });
+        let hexagons: HexagonLayer<u8> = CellLayer::new(Hexagons { size: 10.0 }, 10, 10);
+        assert_eq!(hexagons.neighbors(4, 4).len(), 6);
+        assert_eq!(hexagons.neighbors(0, 0).len(), 2);
+        let triangles: TriangleLayer<u8> = CellLayer::new(Triangles { size: 10.0 }, 10, 10);
+        assert_eq!(triangles.neighbors(4, 4).len(), 3);
+        assert_eq!(triangles.touching(4, 4).len(), 12);
+        let rhombi: RhombusLayer<u8> = CellLayer::new(
+            Rhombi {
+                width: 10.0,
+                height: 6.0,
+            },
+            10,
+            10,
+        );
+        assert_eq!(rhombi.touching(4, 4).len(), 8);
+        let squares: SquaresLayer<u8> = SquaresLayer::new(10, 10, 4, 4);
+        assert_eq!(CellGrid::neighbors(&squares, 0, 5).len(), 3);
+        assert_eq!(squares.touching(0, 5).len(), 5);
+    }
+}
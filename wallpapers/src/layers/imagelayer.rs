@@ -0,0 +1,121 @@
+use crate::algorithms::Algorithm;
+use crate::layers::{mask, Layer, MixMode};
+use crate::Error;
+use image::{GrayImage, Rgba, RgbaImage};
+use rand::Rng;
+
+/// An Image Layer, mixing a complete image, e.g. the output of an algorithm, onto the image below.
+/// Transparent pixels of the image leave the image below untouched.
+/// An optional grayscale mask limits where the layer is applied: Black mask pixels leave the
+/// image below untouched, white mask pixels apply the layer with its full opacity.
+pub struct ImageLayer {
+    image: RgbaImage,
+    mixmode: MixMode,
+    /// The opacity of the layer in [0, 1]
+    opacity: f64,
+    mask: Option<GrayImage>,
+}
+
+// Constructors
+impl ImageLayer {
+    pub fn new(image: RgbaImage) -> Self {
+        ImageLayer {
+            image,
+            mixmode: MixMode::Normal,
+            opacity: 1.0,
+            mask: None,
+        }
+    }
+    /// Render the given algorithm into a new layer of the given size
+    pub fn render<R: Rng>(
+        algorithm: &mut dyn Algorithm<R>,
+        rng: &mut R,
+        width: u32,
+        height: u32,
+    ) -> Result<Self, Error> {
+        let mut image = RgbaImage::new(width, height);
+        algorithm.build_rgba(rng, &mut image)?;
+        Ok(ImageLayer::new(image))
+    }
+}
+
+// Getters and setters
+impl ImageLayer {
+    pub fn image(&self) -> &RgbaImage {
+        &self.image
+    }
+}
+
+impl Layer for ImageLayer {
+    /// Mix the image of the layer onto an image of the same size
+    fn draw_rgba(&self, img: &mut RgbaImage) -> Result<(), Error> {
+        if img.dimensions() != self.image.dimensions() {
+            return Err(Error::InvalidDimensions {
+                width: img.width(),
+                height: img.height(),
+                reason: format!(
+                    "The image size differs from the layer size of {}x{}",
+                    self.image.width(),
+                    self.image.height()
+                ),
+            });
+        }
+        mask::check_size(self.mask.as_ref(), img)?;
+        for (x, y, pixel) in img.enumerate_pixels_mut() {
+            let coverage = mask::coverage(self.mask.as_ref(), x, y);
+            let color = self.image.get_pixel(x, y).0;
+            *pixel = Rgba(
+                self.mixmode
+                    .mix_rgba(pixel.0, color, self.opacity * coverage),
+            );
+        }
+        Ok(())
+    }
+
+    fn set_mix_mode(&mut self, mode: MixMode) {
+        self.mixmode = mode;
+    }
+
+    fn get_mix_mode(&self) -> MixMode {
+        self.mixmode
+    }
+
+    fn set_opacity(&mut self, opacity: f64) {
+        self.opacity = opacity.clamp(0.0, 1.0);
+    }
+
+    fn get_opacity(&self) -> f64 {
+        self.opacity
+    }
+
+    fn set_mask(&mut self, mask: Option<GrayImage>) {
+        self.mask = mask;
+    }
+
+    fn get_mask(&self) -> Option<&GrayImage> {
+        self.mask.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Luma, Rgb, RgbImage};
+
+    #[test]
+    fn masks_limit_where_the_layer_is_mixed() {
+        let mut layer = ImageLayer::new(RgbaImage::from_pixel(3, 1, Rgba([200, 200, 200, 255])));
+        layer.set_mask(Some(GrayImage::from_fn(3, 1, |x, _| {
+            Luma([[0, 128, 255][x as usize]])
+        })));
+        layer.set_mix_mode(MixMode::Add);
+        let mut img = RgbImage::from_pixel(3, 1, Rgb([20, 20, 20]));
+        layer.draw(&mut img).unwrap();
+        assert_eq!(img.get_pixel(0, 0).0, [20, 20, 20]);
+        assert_eq!(img.get_pixel(1, 0).0, [120, 120, 120]);
+        assert_eq!(img.get_pixel(2, 0).0, [220, 220, 220]);
+        assert!(layer.draw(&mut RgbImage::new(4, 1)).is_err());
+        layer.set_mask(Some(GrayImage::new(2, 1)));
+        assert!(layer.draw(&mut RgbImage::new(3, 1)).is_err());
+    }
+}
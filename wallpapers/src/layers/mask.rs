@@ -0,0 +1,139 @@
+//! Grayscale masks that limit where a layer is applied.
+//!
+//! A mask has the size of the image a layer is drawn onto. Black mask pixels leave the image
+//! untouched, white mask pixels apply the layer with its full opacity, and gray pixels fade
+//! between both.
+use crate::utils::perlin::Perlin;
+use crate::utils::scale::Scale;
+use crate::Error;
+use image::{imageops, GrayImage, Luma, RgbImage, RgbaImage};
+use rand::Rng;
+use std::f64::consts::PI;
+
+/// Return an error if the given mask does not have the size of the given image
+pub(crate) fn check_size(mask: Option<&GrayImage>, img: &RgbaImage) -> Result<(), Error> {
+    match mask {
+        Some(mask) if mask.dimensions() != img.dimensions() => Err(Error::InvalidDimensions {
+            width: img.width(),
+            height: img.height(),
+            reason: format!(
+                "The image size differs from the mask size of {}x{}",
+                mask.width(),
+                mask.height()
+            ),
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Get the share in [0, 1] of the layer that is applied at the given pixel. Without a mask, the
+/// layer is applied everywhere.
+pub(crate) fn coverage(mask: Option<&GrayImage>, x: u32, y: u32) -> f64 {
+    match mask {
+        Some(mask) => mask.get_pixel(x, y).0[0] as f64 / 255.0,
+        None => 1.0,
+    }
+}
+
+/// Create a mask from the luminance of the given image, e.g. the output of an algorithm
+pub fn luminance(img: &RgbImage) -> GrayImage {
+    imageops::grayscale(img)
+}
+
+/// Create a mask from Perlin fractal noise with the given base frequency in virtual units, see
+/// [`Scale`]. A tileable mask continues at the opposite image border.
+pub fn noise(
+    rng: &mut impl Rng,
+    (width, height): (u32, u32),
+    (frequency, octaves): (f64, u32),
+    tileable: bool,
+) -> Result<GrayImage, Error> {
+    let scale = Scale::new(width, height);
+    let mut perlin = Perlin::for_fractal(scale.width(), scale.height(), frequency, octaves);
+    perlin.regenerate_noise(rng);
+    if tileable {
+        perlin.set_period(scale.width(), scale.height());
+    }
+    let mut mask = GrayImage::new(width, height);
+    for (x, y, pixel) in mask.enumerate_pixels_mut() {
+        let (x, y) = (scale.to_units(x as f64), scale.to_units(y as f64));
+        let value = 0.5 + 0.5 * perlin.fractal(x, y, frequency, octaves)?;
+        *pixel = Luma([(value.clamp(0.0, 1.0) * 255.0).round() as u8]);
+    }
+    Ok(mask)
+}
+
+/// Create a mask that fades from black to white across the image, in the direction of the given
+/// angle in degrees. At 0 degrees, the mask fades from the left to the right border, at 90
+/// degrees from the top to the bottom border.
+pub fn linear_gradient((width, height): (u32, u32), angle: f64) -> GrayImage {
+    let (sin, cos) = (angle * PI / 180.0).sin_cos();
+    let project = |x: f64, y: f64| x * cos + y * sin;
+    let (max_x, max_y) = (
+        width.saturating_sub(1) as f64,
+        height.saturating_sub(1) as f64,
+    );
+    let corners = [(0.0, 0.0), (max_x, 0.0), (0.0, max_y), (max_x, max_y)];
+    let start = corners
+        .iter()
+        .map(|(x, y)| project(*x, *y))
+        .fold(f64::INFINITY, f64::min);
+    let end = corners
+        .iter()
+        .map(|(x, y)| project(*x, *y))
+        .fold(f64::NEG_INFINITY, f64::max);
+    GrayImage::from_fn(width, height, |x, y| {
+        let t = if end > start {
+            (project(x as f64, y as f64) - start) / (end - start)
+        } else {
+            1.0
+        };
+        Luma([(t * 255.0).round() as u8])
+    })
+}
+
+/// Create a mask that is white at the given center and fades to black at the given radius.
+/// The center is given relative to the image size, i.e. (0.5, 0.5) is the center of the image,
+/// and the radius relative to half the image diagonal.
+pub fn radial_gradient((width, height): (u32, u32), center: (f64, f64), radius: f64) -> GrayImage {
+    let (center_x, center_y) = (center.0 * width as f64, center.1 * height as f64);
+    let radius = radius * 0.5 * (width as f64).hypot(height as f64);
+    GrayImage::from_fn(width, height, |x, y| {
+        let distance = (x as f64 + 0.5 - center_x).hypot(y as f64 + 0.5 - center_y);
+        let value = if radius > 0.0 {
+            1.0 - distance / radius
+        } else {
+            0.0
+        };
+        Luma([(value.clamp(0.0, 1.0) * 255.0).round() as u8])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ChaCha8Rng, SeedableRng};
+
+    #[test]
+    fn gradients_fade_across_the_image() {
+        let horizontal = linear_gradient((5, 3), 0.0);
+        assert_eq!(horizontal.get_pixel(0, 2).0, [0]);
+        assert_eq!(horizontal.get_pixel(2, 0).0, [128]);
+        assert_eq!(horizontal.get_pixel(4, 1).0, [255]);
+        let upwards = linear_gradient((5, 3), 270.0);
+        assert_eq!(upwards.get_pixel(3, 0).0, [255]);
+        assert_eq!(upwards.get_pixel(3, 2).0, [0]);
+        let radial = radial_gradient((10, 10), (0.5, 0.5), 1.0);
+        assert!(radial.get_pixel(5, 5).0[0] > 220);
+        assert!(radial.get_pixel(0, 0).0[0] < 40);
+        assert_eq!(radial.get_pixel(2, 5), radial.get_pixel(7, 5));
+        let noise = noise(
+            &mut ChaCha8Rng::seed_from_u64(1),
+            (64, 48),
+            (0.01, 4),
+            false,
+        )
+        .unwrap();
+        assert!(noise.pixels().any(|pixel| pixel != noise.get_pixel(0, 0)));
+    }
+}
@@ -1,18 +1,245 @@
-use image::RgbImage;
+use crate::Error;
+use image::{DynamicImage, GrayImage, RgbImage, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
 
+pub mod cells;
+pub mod imagelayer;
+pub mod mask;
 pub mod squareslayer;
+pub mod stack;
 
 pub trait Layer {
-    /// Draw the layer onto an image.
+    /// Draw the layer onto an opaque image.
+    /// By default, the image is converted to an image with an alpha channel and drawn with
+    /// [`Layer::draw_rgba`].
+    fn draw(&self, img: &mut RgbImage) -> Result<(), Error> {
+        let mut rgba = DynamicImage::ImageRgb8(std::mem::take(img)).to_rgba8();
+        self.draw_rgba(&mut rgba)?;
+        *img = DynamicImage::ImageRgba8(rgba).to_rgb8();
+        Ok(())
+    }
+    /// Draw the layer onto an image with an alpha channel.
     /// How the pixels are modified and what kind of values need to be set before drawing depends
     /// entirely on the Layer type!
-    fn draw(&self, img: &mut RgbImage) -> Result<(), String>;
-    // fn set_mix_mode(&mut self, mode: MixMode);
+    fn draw_rgba(&self, img: &mut RgbaImage) -> Result<(), Error>;
+    fn set_mix_mode(&mut self, mode: MixMode);
     fn get_mix_mode(&self) -> MixMode;
+    /// Set the opacity of the layer in [0, 1], where 0 leaves the image untouched and 1 draws the
+    /// layer with the full effect of its mix mode
+    fn set_opacity(&mut self, opacity: f64);
+    fn get_opacity(&self) -> f64;
+    /// Set a grayscale mask that limits where the layer is applied, see [`mask`].
+    /// The mask needs the size of the image the layer is drawn onto, which is checked when
+    /// drawing.
+    fn set_mask(&mut self, mask: Option<GrayImage>);
+    fn get_mask(&self) -> Option<&GrayImage>;
 }
 
-#[derive(Copy, Clone)]
+/// The way the colors of a layer are combined with the colors of the image below.
+/// The formulas follow the blend modes of common image editors.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
 pub enum MixMode {
     /// Normal Mix Mode, replaces the pixel values with the new color.
+    #[default]
     Normal,
+    /// Multiplies the colors, which always darkens the image
+    Multiply,
+    /// Multiplies the inverted colors, which always lightens the image
+    Screen,
+    /// Multiplies dark and screens light parts of the image, which increases the contrast
+    Overlay,
+    /// Adds the colors
+    Add,
+    /// Subtracts the new color from the image
+    Subtract,
+    /// Keeps the darker color of each channel
+    Darken,
+    /// Keeps the lighter color of each channel
+    Lighten,
+    /// The absolute difference of the colors
+    Difference,
+    /// A softer version of hard light, like shining a diffuse light onto the image
+    SoftLight,
+    /// Overlay with the image and the new color swapped, like shining a harsh light onto the image
+    HardLight,
+}
+
+impl MixMode {
+    /// The names of all mix modes, in the order of their declaration
+    pub const NAMES: &'static [&'static str] = &[
+        "normal",
+        "multiply",
+        "screen",
+        "overlay",
+        "add",
+        "subtract",
+        "darken",
+        "lighten",
+        "difference",
+        "softlight",
+        "hardlight",
+    ];
+
+    /// Mix a single color channel, given in [0, 1], of the image below with the color of the layer
+    fn mix_channel(&self, below: f64, color: f64) -> f64 {
+        match self {
+            MixMode::Normal => color,
+            MixMode::Multiply => below * color,
+            MixMode::Screen => 1.0 - (1.0 - below) * (1.0 - color),
+            MixMode::Overlay => MixMode::HardLight.mix_channel(color, below),
+            MixMode::Add => (below + color).min(1.0),
+            MixMode::Subtract => (below - color).max(0.0),
+            MixMode::Darken => below.min(color),
+            MixMode::Lighten => below.max(color),
+            MixMode::Difference => (below - color).abs(),
+            MixMode::SoftLight => {
+                if color <= 0.5 {
+                    below - (1.0 - 2.0 * color) * below * (1.0 - below)
+                } else {
+                    let lightened = if below <= 0.25 {
+                        ((16.0 * below - 12.0) * below + 4.0) * below
+                    } else {
+                        below.sqrt()
+                    };
+                    below + (2.0 * color - 1.0) * (lightened - below)
+                }
+            },
+            MixMode::HardLight => {
+                if color <= 0.5 {
+                    2.0 * below * color
+                } else {
+                    1.0 - 2.0 * (1.0 - below) * (1.0 - color)
+                }
+            },
+        }
+    }
+    /// Mix the color of an image pixel with the color of a layer that has the given opacity in
+    /// [0, 1]
+    pub fn mix(&self, below: [u8; 3], color: [u8; 3], opacity: f64) -> [u8; 3] {
+        let [r, g, b, _] = self.mix_rgba(
+            [below[0], below[1], below[2], u8::MAX],
+            [color[0], color[1], color[2], u8::MAX],
+            opacity,
+        );
+        [r, g, b]
+    }
+    /// Mix a pixel with an alpha channel of a layer that has the given opacity in [0, 1] onto a
+    /// pixel with an alpha channel of the image below.
+    /// The mixed color is only applied where the image below is opaque, the color of the layer
+    /// is drawn unchanged over transparent parts of the image below.
+    pub fn mix_rgba(&self, below: [u8; 4], color: [u8; 4], opacity: f64) -> [u8; 4] {
+        let alpha_below = below[3] as f64 / 255.0;
+        let alpha = color[3] as f64 / 255.0 * opacity;
+        let alpha_out = alpha + alpha_below * (1.0 - alpha);
+        let to_u8 = |value: f64| (value * 255.0).round().clamp(0.0, 255.0) as u8;
+        if alpha_out <= 0.0 {
+            return [0; 4];
+        }
+        let [r, g, b] = [0, 1, 2].map(|c| {
+            let below = below[c] as f64 / 255.0;
+            let color = color[c] as f64 / 255.0;
+            let mixed = (1.0 - alpha_below) * color + alpha_below * self.mix_channel(below, color);
+            to_u8((alpha * mixed + alpha_below * below * (1.0 - alpha)) / alpha_out)
+        });
+        [r, g, b, to_u8(alpha_out)]
+    }
+}
+
+impl FromStr for MixMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "normal" => Ok(MixMode::Normal),
+            "multiply" => Ok(MixMode::Multiply),
+            "screen" => Ok(MixMode::Screen),
+            "overlay" => Ok(MixMode::Overlay),
+            "add" => Ok(MixMode::Add),
+            "subtract" => Ok(MixMode::Subtract),
+            "darken" => Ok(MixMode::Darken),
+            "lighten" => Ok(MixMode::Lighten),
+            "difference" => Ok(MixMode::Difference),
+            "softlight" => Ok(MixMode::SoftLight),
+            "hardlight" => Ok(MixMode::HardLight),
+            _ => Err(Error::InvalidParameter {
+                name: "mix_mode".to_string(),
+                reason: format!("Expected one of {}, got {}", MixMode::NAMES.join(", "), s),
+            }),
+        }
+    }
+}
+
+impl Display for MixMode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let index = *self as usize;
+        write!(f, "{}", MixMode::NAMES[index])
+    }
+}
+
+impl TryFrom<String> for MixMode {
+    type Error = Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<MixMode> for String {
+    fn from(mode: MixMode) -> Self {
+        mode.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mix_modes_blend_colors() {
+        let below = [200, 100, 0];
+        let color = [100, 255, 51];
+        assert_eq!(MixMode::Normal.mix(below, color, 1.0), color);
+        assert_eq!(MixMode::Multiply.mix(below, color, 1.0), [78, 100, 0]);
+        assert_eq!(MixMode::Screen.mix(below, color, 1.0), [222, 255, 51]);
+        assert_eq!(MixMode::Add.mix(below, color, 1.0), [255, 255, 51]);
+        assert_eq!(MixMode::Subtract.mix(below, color, 1.0), [100, 0, 0]);
+        assert_eq!(MixMode::Darken.mix(below, color, 1.0), [100, 100, 0]);
+        assert_eq!(MixMode::Lighten.mix(below, color, 1.0), [200, 255, 51]);
+        assert_eq!(MixMode::Difference.mix(below, color, 1.0), [100, 155, 51]);
+        assert_eq!(MixMode::Overlay.mix(below, color, 1.0), [188, 200, 0]);
+        assert_eq!(MixMode::HardLight.mix(below, color, 1.0), [157, 255, 0]);
+        // Soft light with a neutral gray leaves the image unchanged
+        assert_eq!(MixMode::SoftLight.mix(below, [128; 3], 1.0), [200, 100, 0]);
+        // The opacity fades between the image and the mixed color
+        assert_eq!(MixMode::Normal.mix(below, color, 0.0), below);
+        assert_eq!(MixMode::Normal.mix(below, color, 0.5), [150, 178, 26]);
+        for name in MixMode::NAMES {
+            assert_eq!(name.parse::<MixMode>().unwrap().to_string(), *name);
+        }
+        assert!("dodge".parse::<MixMode>().is_err());
+    }
+
+    #[test]
+    fn transparent_pixels_are_composited() {
+        let below = [200, 100, 0, 255];
+        // Transparent layer pixels keep the image below, half transparent pixels count like half
+        // the opacity
+        assert_eq!(MixMode::Multiply.mix_rgba(below, [0, 0, 0, 0], 1.0), below);
+        assert_eq!(
+            MixMode::Normal.mix_rgba(below, [100, 255, 51, 128], 1.0),
+            MixMode::Normal.mix_rgba(below, [100, 255, 51, 255], 128.0 / 255.0)
+        );
+        // Over a transparent image, the layer is drawn without mixing
+        assert_eq!(
+            MixMode::Multiply.mix_rgba([0; 4], [100, 255, 51, 128], 1.0),
+            [100, 255, 51, 128]
+        );
+        assert_eq!(
+            MixMode::Screen.mix_rgba([10, 20, 30, 128], [10, 20, 30, 0], 1.0),
+            [10, 20, 30, 128]
+        );
+    }
 }
@@ -1,16 +1,34 @@
-use crate::layers::{Layer, MixMode};
-use image::{Rgb, RgbImage};
+use crate::layers::{mask, Layer, MixMode};
+use crate::Error;
+use image::{GrayImage, Rgba, RgbaImage};
 use std::borrow::Borrow;
 use std::cmp::max;
 
 /// A Squares Layer, representing "large pixels" with certain dimensions that can be drawn
 /// on top of an image.
+/// The squares are laid out on a grid, optionally separated by a grout of a certain width. Squares
+/// at the image border may be cut off, so that the grid covers the complete image.
 pub struct SquaresLayer<Color: Default + Clone> {
     squaresize_h: usize,
     squaresize_v: usize,
     squares_h: usize,
     squares_v: usize,
+    /// The pixel position of the top left corner of the first square. Negative values cut off the
+    /// first column or row.
+    origin: (i64, i64),
+    /// The offset of the grid in pixels, which shifts the squares to the right and down
+    offset: (i64, i64),
+    /// If true, the grid is centered on the image, so that squares are cut off evenly at opposite
+    /// image borders
+    centered: bool,
+    /// The gap between two squares in pixels
+    grout: usize,
+    /// The color of the gap between squares. Without a color, the image below shows through.
+    grout_color: Option<Color>,
     mixmode: MixMode,
+    /// The opacity of the squares in [0, 1]
+    opacity: f64,
+    mask: Option<GrayImage>,
     data: Vec<Vec<Color>>,
 }
 
@@ -27,7 +45,14 @@ impl<Color: Default + Clone> SquaresLayer<Color> {
             squaresize_v,
             squares_h,
             squares_v,
+            origin: (0, 0),
+            offset: (0, 0),
+            centered: false,
+            grout: 0,
+            grout_color: None,
             mixmode: MixMode::Normal,
+            opacity: 1.0,
+            mask: None,
             data: vec![vec![Color::default(); squares_v]; squares_h],
         }
     }
@@ -35,12 +60,12 @@ impl<Color: Default + Clone> SquaresLayer<Color> {
 
 // Getters
 impl<Color: Default + Clone> SquaresLayer<Color> {
-    // pub fn squaresize_h(&self) -> usize {
-    //     self.squaresize_h
-    // }
-    // pub fn squaresize_v(&self) -> usize {
-    //     self.squaresize_v
-    // }
+    pub fn squaresize_h(&self) -> usize {
+        self.squaresize_h
+    }
+    pub fn squaresize_v(&self) -> usize {
+        self.squaresize_v
+    }
     pub fn squares_v(&self) -> usize {
         self.squares_v
     }
@@ -50,20 +75,44 @@ impl<Color: Default + Clone> SquaresLayer<Color> {
     pub fn get_color_at(&self, x: usize, y: usize) -> &Color {
         self.data[x][y].borrow()
     }
+    /// Get the pixel position of the top left corner of the first square
+    pub fn origin(&self) -> (i64, i64) {
+        self.origin
+    }
+    pub fn offset(&self) -> (i64, i64) {
+        self.offset
+    }
+    pub fn centered(&self) -> bool {
+        self.centered
+    }
+    pub fn grout(&self) -> usize {
+        self.grout
+    }
+    pub fn grout_color(&self) -> Option<&Color> {
+        self.grout_color.as_ref()
+    }
+    /// Get the distance in pixels between the left edges of two adjacent squares
+    fn pitch_h(&self) -> usize {
+        max(1, self.squaresize_h + self.grout)
+    }
+    /// Get the distance in pixels between the top edges of two adjacent squares
+    fn pitch_v(&self) -> usize {
+        max(1, self.squaresize_v + self.grout)
+    }
 }
 
 // Setters
 impl<Color: Default + Clone> SquaresLayer<Color> {
-    // /// Set the new horizontal square size and return the newly-set value
-    // pub fn set_squaresize_h(&mut self, new_squaresize_h: usize) -> usize {
-    //     self.squaresize_h = new_squaresize_h;
-    //     self.squaresize_h
-    // }
-    // /// Set the new vertical square size and return the newly-set value
-    // pub fn set_squaresize_v(&mut self, new_squaresize_v: usize) -> usize {
-    //     self.squaresize_v = new_squaresize_v;
-    //     self.squaresize_v
-    // }
+    /// Set the new horizontal square size and return the newly-set value
+    pub fn set_squaresize_h(&mut self, new_squaresize_h: usize) -> usize {
+        self.squaresize_h = new_squaresize_h;
+        self.squaresize_h
+    }
+    /// Set the new vertical square size and return the newly-set value
+    pub fn set_squaresize_v(&mut self, new_squaresize_v: usize) -> usize {
+        self.squaresize_v = new_squaresize_v;
+        self.squaresize_v
+    }
     // /// Set the new vertical square count and return the newly-set value.
     // /// This operation is expensive, since it needs to re-allocate memory
     // pub fn set_squares_v(&mut self, new_squares_v: usize) -> usize {
@@ -77,11 +126,64 @@ impl<Color: Default + Clone> SquaresLayer<Color> {
     //     self.squares_h
     // }
 
-    /// Adjust the dimensions of the squares to the given image.
+    /// Set the pixel position of the top left corner of the first square
+    pub fn set_origin(&mut self, x: i64, y: i64) {
+        self.origin = (x, y);
+    }
+    /// Shift the grid to the right and down by the given number of pixels.
+    /// The offset is applied by [`Self::adjust_square_count_to_image_dimensions`].
+    pub fn set_offset(&mut self, x: i64, y: i64) {
+        self.offset = (x, y);
+    }
+    /// Center the grid on the image.
+    /// The centering is applied by [`Self::adjust_square_count_to_image_dimensions`].
+    pub fn set_centered(&mut self, centered: bool) {
+        self.centered = centered;
+    }
+    /// Set the gap between two squares in pixels and the color of the gap. Without a color, the
+    /// image below shows through the gaps.
+    pub fn set_grout(&mut self, grout: usize, color: Option<Color>) {
+        self.grout = grout;
+        self.grout_color = color;
+    }
+
+    /// Adjust the dimensions of the squares to the given image, so that the squares cover the
+    /// complete image. Squares at the right and bottom border, and at the left and top border if
+    /// the grid is shifted or centered, may be cut off.
     /// This will adjust the data accordingly, filling up all new squares with Color::default()
     pub fn adjust_square_count_to_image_dimensions(&mut self, img_width: usize, img_height: usize) {
-        self.squares_v = max(1, img_height / self.squaresize_v);
-        self.squares_h = max(1, img_width / self.squaresize_h);
+        let (pitch_h, pitch_v) = (self.pitch_h(), self.pitch_v());
+        let origin_x = self.grid_start(img_width, self.squaresize_h, pitch_h, self.offset.0);
+        let origin_y = self.grid_start(img_height, self.squaresize_v, pitch_v, self.offset.1);
+        self.origin = (origin_x, origin_y);
+        // The origin is never positive, so the covered extent is at least the image size
+        let extent_h = img_width + origin_x.unsigned_abs() as usize;
+        let extent_v = img_height + origin_y.unsigned_abs() as usize;
+        self.set_square_count(
+            max(1, extent_h.div_ceil(pitch_h)),
+            max(1, extent_v.div_ceil(pitch_v)),
+        );
+    }
+    /// Get the position in (-pitch, 0] of the first square along an image axis of the given size
+    fn grid_start(&self, img_size: usize, squaresize: usize, pitch: usize, offset: i64) -> i64 {
+        let centering = if self.centered {
+            // Put the center of a square onto the center of the image
+            (img_size as i64 - squaresize as i64) / 2
+        } else {
+            0
+        };
+        let start = (centering + offset).rem_euclid(pitch as i64);
+        if start > 0 {
+            start - pitch as i64
+        } else {
+            start
+        }
+    }
+    /// Set the number of squares in each direction.
+    /// This will adjust the data accordingly, filling up all new squares with Color::default()
+    pub fn set_square_count(&mut self, squares_h: usize, squares_v: usize) {
+        self.squares_h = squares_h;
+        self.squares_v = squares_v;
         self.data
             .resize_with(self.squares_h, || vec![Color::default(); self.squares_v]);
         for colvec in self.data.iter_mut() {
@@ -95,55 +197,140 @@ impl<Color: Default + Clone> SquaresLayer<Color> {
 
 // Helpers for internal use
 impl SquaresLayer<[u8; 3]> {
-    /// Color the given square in the given color and mark it as visited.
-    fn color_square(&self, square_x: usize, square_y: usize, img: &mut RgbImage, color: [u8; 3]) {
-        let x_start = square_x * self.squaresize_h;
-        let y_start = square_y * self.squaresize_v;
-        let x_end = (square_x + 1) * self.squaresize_h;
-        let y_end = (square_y + 1) * self.squaresize_v;
-        for x in x_start..x_end {
-            for y in y_start..y_end {
-                let pixel = img.get_pixel_mut(x as u32, y as u32);
-                match self.get_mix_mode() {
-                    MixMode::Normal => *pixel = Rgb(color),
-                };
+    /// Mix the given color onto the given image pixel
+    fn color_pixel(&self, img: &mut RgbaImage, x: u32, y: u32, color: [u8; 3]) {
+        let coverage = mask::coverage(self.mask.as_ref(), x, y);
+        let pixel = img.get_pixel_mut(x, y);
+        let color = [color[0], color[1], color[2], u8::MAX];
+        *pixel = Rgba(
+            self.mixmode
+                .mix_rgba(pixel.0, color, self.opacity * coverage),
+        );
+    }
+    /// Color the part of the given square that lies within the image in the given color
+    fn color_square(&self, square_x: usize, square_y: usize, img: &mut RgbaImage, color: [u8; 3]) {
+        let x_start = self.origin.0 + (square_x * self.pitch_h()) as i64;
+        let y_start = self.origin.1 + (square_y * self.pitch_v()) as i64;
+        let x_end = x_start + self.squaresize_h as i64;
+        let y_end = y_start + self.squaresize_v as i64;
+        for x in x_start.max(0)..x_end.min(img.width() as i64) {
+            for y in y_start.max(0)..y_end.min(img.height() as i64) {
+                self.color_pixel(img, x as u32, y as u32, color);
+            }
+        }
+    }
+    /// Color all image pixels in the gaps between the squares in the given color
+    fn color_grout(&self, img: &mut RgbaImage, color: [u8; 3]) {
+        let (pitch_h, pitch_v) = (self.pitch_h() as i64, self.pitch_v() as i64);
+        for x in 0..img.width() {
+            for y in 0..img.height() {
+                let u = (x as i64 - self.origin.0).rem_euclid(pitch_h);
+                let v = (y as i64 - self.origin.1).rem_euclid(pitch_v);
+                if u >= self.squaresize_h as i64 || v >= self.squaresize_v as i64 {
+                    self.color_pixel(img, x, y, color);
+                }
             }
         }
     }
 }
 
 impl Layer for SquaresLayer<[u8; 3]> {
-    /// Draw the squares onto an image
-    fn draw(&self, img: &mut RgbImage) -> Result<(), String> {
-        let layerheight = self.squares_v * self.squaresize_v;
-        let layerwidth = self.squares_h * self.squaresize_h;
-        if layerheight > img.height() as usize {
-            return Err(format!(
-                "The image height of {} was smaller than the layer height of {}",
-                img.height(),
-                layerheight
-            ));
-        }
-        if layerwidth > img.width() as usize {
-            return Err(format!(
-                "The image width of {} was smaller than the layer width of {}",
-                img.width(),
-                layerwidth
-            ));
-        }
+    /// Draw the squares onto an image. Squares that exceed the image are cut off.
+    fn draw_rgba(&self, img: &mut RgbaImage) -> Result<(), Error> {
+        mask::check_size(self.mask.as_ref(), img)?;
         for x in 0..self.squares_h() {
             for y in 0..self.squares_v() {
                 self.color_square(x, y, img, self.data[x][y]);
             }
         }
+        if let Some(color) = self.grout_color.filter(|_| self.grout > 0) {
+            self.color_grout(img, color);
+        }
         Ok(())
     }
 
-    // fn set_mix_mode(&mut self, mode: MixMode) {
-    //     self.mixmode = mode;
-    // }
+    fn set_mix_mode(&mut self, mode: MixMode) {
+        self.mixmode = mode;
+    }
 
     fn get_mix_mode(&self) -> MixMode {
         self.mixmode
     }
+
+    fn set_opacity(&mut self, opacity: f64) {
+        self.opacity = opacity.clamp(0.0, 1.0);
+    }
+
+    fn get_opacity(&self) -> f64 {
+        self.opacity
+    }
+
+    fn set_mask(&mut self, mask: Option<GrayImage>) {
+        self.mask = mask;
+    }
+
+    fn get_mask(&self) -> Option<&GrayImage> {
+        self.mask.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithms::{Algorithm, Clouds};
+    use crate::{ChaCha8Rng, SeedableRng};
+    use image::RgbImage;
+
+    #[test]
+    fn squares_are_blended_over_the_image() {
+        let mut background = RgbImage::new(40, 20);
+        Clouds::default()
+            .build(&mut ChaCha8Rng::seed_from_u64(3), &mut background)
+            .unwrap();
+        let mut layer = SquaresLayer::new(2, 1, 10, 10);
+        layer.set_color_at(0, 0, [255, 128, 0]);
+        layer.set_mix_mode(MixMode::Multiply);
+        layer.set_opacity(0.5);
+        let mut img = background.clone();
+        layer.draw(&mut img).unwrap();
+        for (x, y, pixel) in img.enumerate_pixels() {
+            let below = background.get_pixel(x, y).0;
+            let expected = match (x, y) {
+                (0..10, 0..10) => MixMode::Multiply.mix(below, [255, 128, 0], 0.5),
+                (10..20, 0..10) => MixMode::Multiply.mix(below, [0, 0, 0], 0.5),
+                _ => below,
+            };
+            assert_eq!(pixel.0, expected);
+        }
+    }
+
+    #[test]
+    fn squares_cover_images_of_any_size() {
+        let white = Rgba([255, 255, 255, 255]);
+        let mut layer = SquaresLayer::new(0, 0, 4, 4);
+        layer.adjust_square_count_to_image_dimensions(10, 7);
+        assert_eq!((layer.squares_h(), layer.squares_v()), (3, 2));
+        let mut img = RgbaImage::new(10, 7);
+        layer.draw_rgba(&mut img).unwrap();
+        assert!(img.pixels().all(|pixel| pixel.0 == [0, 0, 0, 255]));
+        // Centered squares are cut off evenly, with a square in the middle of the image
+        layer.set_centered(true);
+        layer.adjust_square_count_to_image_dimensions(10, 7);
+        assert_eq!(layer.origin(), (-1, -3));
+        assert_eq!((layer.squares_h(), layer.squares_v()), (3, 3));
+        // The grout between the squares is drawn in its own color
+        layer.set_centered(false);
+        layer.set_offset(2, 0);
+        layer.set_grout(1, Some([255, 255, 255]));
+        layer.adjust_square_count_to_image_dimensions(10, 7);
+        assert_eq!(layer.origin(), (-3, 0));
+        assert_eq!((layer.squares_h(), layer.squares_v()), (3, 2));
+        let mut img = RgbaImage::from_pixel(10, 7, Rgba([0, 0, 255, 255]));
+        layer.draw_rgba(&mut img).unwrap();
+        for (x, y, pixel) in img.enumerate_pixels() {
+            let grout = x % 5 == 1 || y % 5 == 4;
+            assert_eq!(*pixel == white, grout, "{}, {}", x, y);
+            assert_ne!(pixel.0, [0, 0, 255, 255]);
+        }
+    }
 }
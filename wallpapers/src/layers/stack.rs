@@ -0,0 +1,29 @@
+use crate::layers::Layer;
+use crate::Error;
+use image::RgbaImage;
+
+/// A stack of layers that are composited onto an image in order, from the bottom to the top
+#[derive(Default)]
+pub struct LayerStack {
+    layers: Vec<Box<dyn Layer>>,
+}
+
+impl LayerStack {
+    pub fn new() -> Self {
+        LayerStack { layers: vec![] }
+    }
+    /// Put the given layer on top of the stack
+    pub fn push(&mut self, layer: Box<dyn Layer>) {
+        self.layers.push(layer);
+    }
+    pub fn layers(&self) -> &[Box<dyn Layer>] {
+        &self.layers
+    }
+    /// Draw all layers onto the given image, starting with the bottom layer
+    pub fn draw(&self, img: &mut RgbaImage) -> Result<(), Error> {
+        for layer in &self.layers {
+            layer.draw_rgba(img)?;
+        }
+        Ok(())
+    }
+}
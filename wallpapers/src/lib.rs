@@ -1,13 +1,22 @@
 //! Procedural Wallpapers in Rust - A collection of algorithms for procedural wallpaper generation.
 //!
+pub use error::Error;
 pub use image::{ImageBuffer, RgbImage};
 pub use rand::Rng;
 pub use rand_chacha::rand_core::SeedableRng;
 pub use rand_chacha::ChaCha8Rng;
 
 pub mod algorithms;
+mod error;
 pub mod layers;
+pub mod metadata;
+pub mod monitors;
 pub mod patterns;
+pub mod postprocessing;
+pub mod propagation;
+pub mod recipe;
+pub mod registry;
+pub mod seed;
 pub mod utils;
 
 pub fn add(left: u64, right: u64) -> u64 {
@@ -0,0 +1,411 @@
+//! Generation metadata that is embedded into saved wallpapers, so that a wallpaper can be
+//! regenerated later from the image file alone.
+//!
+//! PNG files get one tEXt/iTXt chunk per entry. JPEG and WebP files get an XMP packet, which is
+//! stored in an APP1 segment (JPEG) or an `XMP ` chunk (WebP).
+//! Every format additionally stores the complete [`Recipe`] as JSON.
+use crate::recipe::Recipe;
+use crate::Error;
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::{DynamicImage, ImageEncoder, ImageFormat};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+
+/// The prefix of all metadata keys written by this crate
+pub const KEY_PREFIX: &str = "procedural_wallpapers:";
+/// The name of the entry that contains the complete recipe as JSON
+pub const RECIPE_KEY: &str = "recipe";
+
+const XMP_NAMESPACE: &str = "https://github.com/lukas-kirschner/procedural-wallpapers-rs/xmp/1.0/";
+/// The signature that precedes an XMP packet in a JPEG APP1 segment
+const JPEG_XMP_SIGNATURE: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+
+/// Get the software name and version that is written into the metadata
+pub fn software() -> String {
+    format!("procedural_wallpapers {}", env!("CARGO_PKG_VERSION"))
+}
+
+/// Get all metadata entries of the given recipe as key-value pairs, without the key prefix
+pub fn entries(recipe: &Recipe) -> Result<Vec<(&'static str, String)>, Error> {
+    // The output path is specific to the machine the wallpaper was generated on
+    let recipe = Recipe {
+        output: None,
+        ..recipe.clone()
+    };
+    let (width, height) = recipe.canvas_size()?;
+    let mut entries = vec![
+        ("version", env!("CARGO_PKG_VERSION").to_string()),
+        ("mode", recipe.mode.clone()),
+        ("width", width.to_string()),
+        ("height", height.to_string()),
+    ];
+    if let Some(monitors) = &recipe.monitors {
+        entries.push(("monitors", monitors.to_string()));
+    }
+    if let Some(seed) = recipe.seed {
+        entries.push(("seed", seed.to_string()));
+    }
+    entries.push(("parameters", to_json(&recipe.params)?));
+    entries.push((RECIPE_KEY, to_json(&recipe)?));
+    Ok(entries)
+}
+
+/// Check whether generation metadata can be embedded into files of the given format
+pub fn is_supported(format: ImageFormat) -> bool {
+    matches!(
+        format,
+        ImageFormat::Png | ImageFormat::Jpeg | ImageFormat::WebP
+    )
+}
+
+/// Save the given image to the given path and embed the given recipe.
+/// Images with an alpha channel are saved as 8-bit RGBA, all other images as 8-bit RGB.
+/// Metadata is only embedded into the formats accepted by [`is_supported`], other formats are
+/// saved as-is.
+pub fn save(
+    img: &DynamicImage,
+    path: &Path,
+    format: ImageFormat,
+    recipe: &Recipe,
+) -> Result<(), Error> {
+    let img = if img.color().has_alpha() {
+        DynamicImage::ImageRgba8(img.to_rgba8())
+    } else {
+        DynamicImage::ImageRgb8(img.to_rgb8())
+    };
+    match format {
+        ImageFormat::Png => save_png(&img, path, recipe),
+        ImageFormat::Jpeg => {
+            let mut encoded = vec![];
+            JpegEncoder::new(&mut encoded).write_image(
+                img.as_bytes(),
+                img.width(),
+                img.height(),
+                img.color().into(),
+            )?;
+            write_file(path, &embed_jpeg_xmp(&encoded, &xmp_packet(recipe)?)?)
+        },
+        ImageFormat::WebP => {
+            let mut encoded = vec![];
+            WebPEncoder::new_lossless(&mut encoded).write_image(
+                img.as_bytes(),
+                img.width(),
+                img.height(),
+                img.color().into(),
+            )?;
+            write_file(path, &embed_webp_xmp(&encoded, &xmp_packet(recipe)?)?)
+        },
+        format => Ok(img.save_with_format(path, format)?),
+    }
+}
+
+/// Read the recipe that was embedded into the given image file by [`save`]
+pub fn read_recipe(path: &Path) -> Result<Recipe, Error> {
+    let format = ImageFormat::from_path(path)?;
+    if !is_supported(format) {
+        return Err(Error::Metadata(format!(
+            "{} files cannot store generation metadata, only PNG, JPEG and WebP wallpapers can \
+             be reproduced",
+            format!("{:?}", format).to_uppercase()
+        )));
+    }
+    let json = match format {
+        ImageFormat::Png => read_png_recipe(path)?,
+        ImageFormat::Jpeg => find_jpeg_xmp(&std::fs::read(path)?)?.and_then(|xmp| xmp_recipe(&xmp)),
+        ImageFormat::WebP => find_webp_xmp(&std::fs::read(path)?)?.and_then(|xmp| xmp_recipe(&xmp)),
+        _ => None,
+    };
+    let json = json.ok_or_else(|| {
+        Error::Metadata(format!(
+            "{} does not contain generation metadata",
+            path.display()
+        ))
+    })?;
+    serde_json::from_str(&json).map_err(|err| Error::Metadata(err.to_string()))
+}
+
+fn to_json<T: serde::Serialize>(value: &T) -> Result<String, Error> {
+    serde_json::to_string(value).map_err(|err| Error::Metadata(err.to_string()))
+}
+
+fn write_file(path: &Path, data: &[u8]) -> Result<(), Error> {
+    let mut file = BufWriter::new(File::create(path)?);
+    file.write_all(data)?;
+    file.flush()?;
+    Ok(())
+}
+
+fn png_error(err: impl std::fmt::Display) -> Error {
+    Error::Metadata(format!("PNG error: {}", err))
+}
+
+fn save_png(img: &DynamicImage, path: &Path, recipe: &Recipe) -> Result<(), Error> {
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(file, img.width(), img.height());
+    encoder.set_color(if img.color().has_alpha() {
+        png::ColorType::Rgba
+    } else {
+        png::ColorType::Rgb
+    });
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .add_text_chunk("Software".to_string(), software())
+        .map_err(png_error)?;
+    for (key, value) in entries(recipe)? {
+        // iTXt chunks are UTF-8, tEXt chunks only allow Latin-1
+        encoder
+            .add_itxt_chunk(format!("{}{}", KEY_PREFIX, key), value)
+            .map_err(png_error)?;
+    }
+    let mut writer = encoder.write_header().map_err(png_error)?;
+    writer.write_image_data(img.as_bytes()).map_err(png_error)?;
+    writer.finish().map_err(png_error)
+}
+
+fn read_png_recipe(path: &Path) -> Result<Option<String>, Error> {
+    let decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+    let reader = decoder.read_info().map_err(png_error)?;
+    let key = format!("{}{}", KEY_PREFIX, RECIPE_KEY);
+    for chunk in &reader.info().utf8_text {
+        if chunk.keyword == key {
+            return chunk.get_text().map(Some).map_err(png_error);
+        }
+    }
+    Ok(reader
+        .info()
+        .uncompressed_latin1_text
+        .iter()
+        .find(|chunk| chunk.keyword == key)
+        .map(|chunk| chunk.text.clone()))
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn unescape_xml(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Create an XMP packet containing all metadata entries of the given recipe
+fn xmp_packet(recipe: &Recipe) -> Result<String, Error> {
+    let mut packet = String::from(
+        "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n\
+         <x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n\
+         <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n",
+    );
+    packet.push_str(&format!(
+        "<rdf:Description rdf:about=\"\" xmlns:xmp=\"http://ns.adobe.com/xap/1.0/\" xmlns:pw=\"{}\">\n",
+        XMP_NAMESPACE
+    ));
+    packet.push_str(&format!(
+        "<xmp:CreatorTool>{}</xmp:CreatorTool>\n",
+        escape_xml(&software())
+    ));
+    for (key, value) in entries(recipe)? {
+        packet.push_str(&format!("<pw:{0}>{1}</pw:{0}>\n", key, escape_xml(&value)));
+    }
+    packet.push_str("</rdf:Description>\n</rdf:RDF>\n</x:xmpmeta>\n<?xpacket end=\"w\"?>");
+    Ok(packet)
+}
+
+/// Extract the recipe JSON from an XMP packet created by [`xmp_packet`]
+fn xmp_recipe(packet: &str) -> Option<String> {
+    let start_tag = format!("<pw:{}>", RECIPE_KEY);
+    let end_tag = format!("</pw:{}>", RECIPE_KEY);
+    packet.find(&start_tag).and_then(|start| {
+        let content = &packet[start + start_tag.len()..];
+        content
+            .find(&end_tag)
+            .map(|end| unescape_xml(&content[..end]))
+    })
+}
+
+fn invalid_file(format: &str) -> Error {
+    Error::Metadata(format!("Invalid {} file", format))
+}
+
+/// Insert an APP1 segment containing the given XMP packet right after the SOI marker
+fn embed_jpeg_xmp(jpeg: &[u8], packet: &str) -> Result<Vec<u8>, Error> {
+    if !jpeg.starts_with(&[0xff, 0xd8]) {
+        return Err(invalid_file("JPEG"));
+    }
+    let length = 2 + JPEG_XMP_SIGNATURE.len() + packet.len();
+    let length = u16::try_from(length)
+        .map_err(|_| Error::Metadata("The metadata is too large for a JPEG file".to_string()))?;
+    let mut result = Vec::with_capacity(jpeg.len() + length as usize + 2);
+    result.extend_from_slice(&jpeg[..2]);
+    result.extend_from_slice(&[0xff, 0xe1]);
+    result.extend_from_slice(&length.to_be_bytes());
+    result.extend_from_slice(JPEG_XMP_SIGNATURE);
+    result.extend_from_slice(packet.as_bytes());
+    result.extend_from_slice(&jpeg[2..]);
+    Ok(result)
+}
+
+/// Find the XMP packet in the APP1 segments of a JPEG file
+fn find_jpeg_xmp(jpeg: &[u8]) -> Result<Option<String>, Error> {
+    if !jpeg.starts_with(&[0xff, 0xd8]) {
+        return Err(invalid_file("JPEG"));
+    }
+    let mut pos = 2;
+    // Metadata segments are always located before the start of the scan (0xDA)
+    while pos + 4 <= jpeg.len() && jpeg[pos] == 0xff && jpeg[pos + 1] != 0xda {
+        let marker = jpeg[pos + 1];
+        let length = u16::from_be_bytes([jpeg[pos + 2], jpeg[pos + 3]]) as usize;
+        let end = (pos + 2 + length).min(jpeg.len());
+        let payload = &jpeg[(pos + 4).min(end)..end];
+        if marker == 0xe1 {
+            if let Some(packet) = payload.strip_prefix(JPEG_XMP_SIGNATURE) {
+                return Ok(Some(String::from_utf8_lossy(packet).into_owned()));
+            }
+        }
+        pos = end;
+    }
+    Ok(None)
+}
+
+/// Iterate over the chunks of a RIFF container as (fourcc, payload) pairs
+fn webp_chunks(webp: &[u8]) -> Result<Vec<([u8; 4], &[u8])>, Error> {
+    if webp.len() < 12 || &webp[..4] != b"RIFF" || &webp[8..12] != b"WEBP" {
+        return Err(invalid_file("WebP"));
+    }
+    let mut chunks = vec![];
+    let mut pos = 12;
+    while pos + 8 <= webp.len() {
+        let fourcc = [webp[pos], webp[pos + 1], webp[pos + 2], webp[pos + 3]];
+        let size = u32::from_le_bytes([webp[pos + 4], webp[pos + 5], webp[pos + 6], webp[pos + 7]])
+            as usize;
+        let end = pos + 8 + size;
+        if end > webp.len() {
+            return Err(invalid_file("WebP"));
+        }
+        chunks.push((fourcc, &webp[pos + 8..end]));
+        // Chunks are padded to an even size
+        pos = end + size % 2;
+    }
+    Ok(chunks)
+}
+
+/// Convert a WebP file into the extended format and append an `XMP ` chunk
+fn embed_webp_xmp(webp: &[u8], packet: &str) -> Result<Vec<u8>, Error> {
+    let chunks = webp_chunks(webp)?;
+    let mut body = b"WEBP".to_vec();
+    let mut push_chunk = |fourcc: &[u8; 4], payload: &[u8]| {
+        body.extend_from_slice(fourcc);
+        body.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        body.extend_from_slice(payload);
+        if payload.len() % 2 == 1 {
+            body.push(0);
+        }
+    };
+    const XMP_FLAG: u8 = 0x04;
+    match chunks.first() {
+        Some((fourcc, payload)) if fourcc == b"VP8X" && payload.len() >= 10 => {
+            let mut header = payload.to_vec();
+            header[0] |= XMP_FLAG;
+            push_chunk(b"VP8X", &header);
+        },
+        Some((fourcc, payload)) => {
+            let (width, height) = webp_dimensions(fourcc, payload)?;
+            let mut header = vec![XMP_FLAG, 0, 0, 0];
+            header.extend_from_slice(&(width - 1).to_le_bytes()[..3]);
+            header.extend_from_slice(&(height - 1).to_le_bytes()[..3]);
+            push_chunk(b"VP8X", &header);
+            push_chunk(fourcc, payload);
+        },
+        None => return Err(invalid_file("WebP")),
+    }
+    for (fourcc, payload) in chunks.iter().skip(1) {
+        if fourcc != b"XMP " {
+            push_chunk(fourcc, payload);
+        }
+    }
+    push_chunk(b"XMP ", packet.as_bytes());
+    let mut result = b"RIFF".to_vec();
+    result.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    result.extend_from_slice(&body);
+    Ok(result)
+}
+
+/// Read the canvas size from the bitstream chunk of a simple-format WebP file
+fn webp_dimensions(fourcc: &[u8; 4], payload: &[u8]) -> Result<(u32, u32), Error> {
+    match fourcc {
+        b"VP8L" if payload.len() >= 5 => {
+            let bits = u32::from_le_bytes([payload[1], payload[2], payload[3], payload[4]]);
+            Ok(((bits & 0x3fff) + 1, ((bits >> 14) & 0x3fff) + 1))
+        },
+        b"VP8 " if payload.len() >= 10 => Ok((
+            u16::from_le_bytes([payload[6], payload[7]]) as u32 & 0x3fff,
+            u16::from_le_bytes([payload[8], payload[9]]) as u32 & 0x3fff,
+        )),
+        _ => Err(invalid_file("WebP")),
+    }
+}
+
+/// Find the XMP packet in a WebP file
+fn find_webp_xmp(webp: &[u8]) -> Result<Option<String>, Error> {
+    Ok(webp_chunks(webp)?
+        .into_iter()
+        .find(|(fourcc, _)| fourcc == b"XMP ")
+        .map(|(_, payload)| String::from_utf8_lossy(payload).into_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithms::ParameterValue;
+    use crate::seed::Seed;
+
+    #[test]
+    fn recipe_roundtrips_through_all_formats() {
+        let mut recipe = Recipe::new("islands", 16, 8);
+        recipe.seed = Some(Seed::from(42));
+        recipe
+            .params
+            .insert("border_color".to_string(), ParameterValue::Color([1, 2, 3]));
+        let img = DynamicImage::new_rgb8(16, 8);
+        let dir = std::env::temp_dir();
+        for extension in ["png", "jpg", "webp"] {
+            let path = dir.join(format!(
+                "procedural_wallpapers_metadata_{}.{}",
+                std::process::id(),
+                extension
+            ));
+            save(&img, &path, ImageFormat::from_path(&path).unwrap(), &recipe).unwrap();
+            let loaded = read_recipe(&path);
+            let decoded = image::open(&path);
+            std::fs::remove_file(&path).unwrap();
+            assert_eq!(loaded.unwrap(), recipe, "{}", extension);
+            assert_eq!(decoded.unwrap().width(), 16, "{}", extension);
+        }
+    }
+
+    #[test]
+    fn formats_without_metadata_are_refused() {
+        let recipe = Recipe::new("islands", 16, 8);
+        let img = DynamicImage::new_rgb8(16, 8);
+        for extension in ["bmp", "tiff"] {
+            let path = std::env::temp_dir().join(format!(
+                "procedural_wallpapers_metadata_{}.{}",
+                std::process::id(),
+                extension
+            ));
+            save(&img, &path, ImageFormat::from_path(&path).unwrap(), &recipe).unwrap();
+            let loaded = read_recipe(&path);
+            std::fs::remove_file(&path).unwrap();
+            let err = loaded.unwrap_err().to_string();
+            assert!(err.contains("cannot store generation metadata"), "{}", err);
+        }
+    }
+}
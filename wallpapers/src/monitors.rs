@@ -0,0 +1,247 @@
+//! Monitor layouts, to render one continuous wallpaper that spans multiple monitors.
+//!
+//! A layout is written like the geometry of an X11 display, e.g. `2560x1440+0+0,1920x1080+2560+180`
+//! for a 1440p monitor with a 1080p monitor to its right, whose top edge is 180 pixels lower.
+use crate::Error;
+use image::{imageops, ImageBuffer, Pixel};
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// A monitor with its resolution and position in pixels
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Monitor {
+    pub width: u32,
+    pub height: u32,
+    pub x: i64,
+    pub y: i64,
+}
+
+impl FromStr for Monitor {
+    type Err = Error;
+
+    /// Parse a monitor given as WIDTHxHEIGHT+X+Y. Offsets may also be negative, e.g. 1920x1080-1920+0
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::InvalidParameter {
+            name: "monitors".to_string(),
+            reason: format!("Expected a monitor as WIDTHxHEIGHT+X+Y, got \"{}\"", s),
+        };
+        let s = s.trim();
+        let offset_start = s.find(['+', '-']).ok_or_else(invalid)?;
+        let (size, offsets) = s.split_at(offset_start);
+        let (width, height) = size.split_once('x').ok_or_else(invalid)?;
+        let y_start = offsets[1..].find(['+', '-']).ok_or_else(invalid)? + 1;
+        let (x, y) = offsets.split_at(y_start);
+        let monitor = Monitor {
+            width: width.parse().map_err(|_| invalid())?,
+            height: height.parse().map_err(|_| invalid())?,
+            x: x.parse().map_err(|_| invalid())?,
+            y: y.parse().map_err(|_| invalid())?,
+        };
+        if monitor.width == 0 || monitor.height == 0 {
+            return Err(invalid());
+        }
+        // The far edges of the monitor are compared with the positions of the other monitors
+        monitor
+            .x
+            .checked_add(monitor.width as i64)
+            .ok_or_else(too_large)?;
+        monitor
+            .y
+            .checked_add(monitor.height as i64)
+            .ok_or_else(too_large)?;
+        Ok(monitor)
+    }
+}
+
+impl Display for Monitor {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}x{}{:+}{:+}", self.width, self.height, self.x, self.y)
+    }
+}
+
+/// Get the error that is returned if a monitor layout does not fit into an image
+fn too_large() -> Error {
+    Error::InvalidParameter {
+        name: "monitors".to_string(),
+        reason: "The monitor layout is too large".to_string(),
+    }
+}
+
+/// The arrangement of all monitors of a desk
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Layout {
+    monitors: Vec<Monitor>,
+}
+
+impl Layout {
+    /// Create a layout from at least one monitor
+    pub fn new(monitors: Vec<Monitor>) -> Result<Self, Error> {
+        if monitors.is_empty() {
+            return Err(Error::InvalidParameter {
+                name: "monitors".to_string(),
+                reason: "A layout needs at least one monitor".to_string(),
+            });
+        }
+        let layout = Layout { monitors };
+        // Without bezels, every monitor must fit into the canvas
+        layout.regions(0)?;
+        Ok(layout)
+    }
+    pub fn monitors(&self) -> &[Monitor] {
+        &self.monitors
+    }
+    /// Get the region of each monitor on the canvas as (x, y, width, height).
+    /// The given bezel gap in pixels is inserted between adjacent monitors, so that the wallpaper
+    /// continues behind the monitor frames.
+    /// Returns an error if the canvas would be larger than the largest possible image.
+    pub fn regions(&self, bezel: u32) -> Result<Vec<(u32, u32, u32, u32)>, Error> {
+        let min_x = self.monitors.iter().map(|m| m.x).min().unwrap_or(0);
+        let min_y = self.monitors.iter().map(|m| m.y).min().unwrap_or(0);
+        // Get the position of a monitor on the canvas, whose far edge must fit into an image
+        let position = |coordinate: i64, min: i64, gaps: u32, size: u32| {
+            let offset = u32::try_from(coordinate.checked_sub(min)?).ok()?;
+            let position = offset.checked_add(gaps.checked_mul(bezel)?)?;
+            position.checked_add(size).map(|_| position)
+        };
+        self.monitors
+            .iter()
+            .map(|monitor| {
+                // Count the monitors that are completely left of or above this monitor
+                let gaps_x = self.distinct_edges_before(monitor.x, |m| m.x + m.width as i64);
+                let gaps_y = self.distinct_edges_before(monitor.y, |m| m.y + m.height as i64);
+                Ok((
+                    position(monitor.x, min_x, gaps_x, monitor.width).ok_or_else(too_large)?,
+                    position(monitor.y, min_y, gaps_y, monitor.height).ok_or_else(too_large)?,
+                    monitor.width,
+                    monitor.height,
+                ))
+            })
+            .collect()
+    }
+    /// Count the distinct far edges of all monitors that end at or before the given position
+    fn distinct_edges_before(&self, position: i64, edge: impl Fn(&Monitor) -> i64) -> u32 {
+        let mut edges: Vec<i64> = self
+            .monitors
+            .iter()
+            .map(edge)
+            .filter(|edge| *edge <= position)
+            .collect();
+        edges.sort_unstable();
+        edges.dedup();
+        edges.len() as u32
+    }
+    /// Get the size of the canvas that contains all monitors and bezel gaps
+    pub fn canvas_size(&self, bezel: u32) -> Result<(u32, u32), Error> {
+        Ok(self
+            .regions(bezel)?
+            .into_iter()
+            .fold((0, 0), |(width, height), (x, y, w, h)| {
+                (width.max(x + w), height.max(y + h))
+            }))
+    }
+    /// Split the given canvas into one image per monitor, in the order of the monitors
+    pub fn split<P: Pixel + 'static>(
+        &self,
+        canvas: &ImageBuffer<P, Vec<P::Subpixel>>,
+        bezel: u32,
+    ) -> Result<Vec<ImageBuffer<P, Vec<P::Subpixel>>>, Error> {
+        let (width, height) = self.canvas_size(bezel)?;
+        if canvas.width() < width || canvas.height() < height {
+            return Err(Error::InvalidDimensions {
+                width: canvas.width(),
+                height: canvas.height(),
+                reason: format!("The monitor layout needs a canvas of {}x{}", width, height),
+            });
+        }
+        Ok(self
+            .regions(bezel)?
+            .into_iter()
+            .map(|(x, y, w, h)| imageops::crop_imm(canvas, x, y, w, h).to_image())
+            .collect())
+    }
+}
+
+impl FromStr for Layout {
+    type Err = Error;
+
+    /// Parse a comma-separated list of monitors
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Layout::new(s.split(',').map(str::parse).collect::<Result<_, _>>()?)
+    }
+}
+
+impl Display for Layout {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let monitors: Vec<String> = self.monitors.iter().map(Monitor::to_string).collect();
+        write!(f, "{}", monitors.join(","))
+    }
+}
+
+impl TryFrom<String> for Layout {
+    type Error = Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Layout> for String {
+    fn from(layout: Layout) -> Self {
+        layout.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbImage;
+
+    #[test]
+    fn parse_and_arrange_monitors() {
+        let layout: Layout = "2560x1440+0+0,1920x1080+2560+180".parse().unwrap();
+        assert_eq!(layout.to_string(), "2560x1440+0+0,1920x1080+2560+180");
+        assert_eq!(layout.canvas_size(0).unwrap(), (4480, 1440));
+        assert_eq!(
+            layout.regions(50).unwrap(),
+            vec![(0, 0, 2560, 1440), (2610, 180, 1920, 1080)]
+        );
+        assert_eq!(layout.canvas_size(50).unwrap(), (4530, 1440));
+        let stacked: Layout = "1920x1080+0-1080,1920x1080+0+0".parse().unwrap();
+        assert_eq!(
+            stacked.regions(10).unwrap(),
+            vec![(0, 0, 1920, 1080), (0, 1090, 1920, 1080)]
+        );
+        assert!("1920x1080".parse::<Layout>().is_err());
+        assert!("0x1080+0+0".parse::<Layout>().is_err());
+    }
+
+    #[test]
+    fn layouts_must_fit_into_an_image() {
+        assert!("1920x1080+9223372036854775807+0".parse::<Layout>().is_err());
+        assert!(
+            "1920x1080-9223372036854775808+0,1920x1080+9223372036854775000+0"
+                .parse::<Layout>()
+                .is_err()
+        );
+        assert!("1920x1080+0+0,1920x1080+4294967295+0"
+            .parse::<Layout>()
+            .is_err());
+        assert!("4294967295x1080+0+0".parse::<Layout>().is_ok());
+        // Bezels can push a layout beyond the largest image
+        let layout: Layout = "1920x1080+0+0,1920x1080+1920+0".parse().unwrap();
+        assert!(layout.canvas_size(u32::MAX - 1000).is_err());
+        assert!(layout.split(&RgbImage::new(1, 1), u32::MAX).is_err());
+    }
+
+    #[test]
+    fn split_crops_each_monitor() {
+        let layout: Layout = "4x2+0+0,2x2+4+1".parse().unwrap();
+        let (width, height) = layout.canvas_size(1).unwrap();
+        let canvas = RgbImage::from_fn(width, height, |x, y| image::Rgb([x as u8, y as u8, 0]));
+        let images = layout.split(&canvas, 1).unwrap();
+        assert_eq!(images[1].dimensions(), (2, 2));
+        assert_eq!(images[1].get_pixel(0, 0).0, [5, 1, 0]);
+    }
+}
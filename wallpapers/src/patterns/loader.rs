@@ -0,0 +1,242 @@
+//! Loading patterns from files, so that new patterns can be added without writing Rust.
+//!
+//! Patterns can be given as image (e.g. a small PNG with a transparent background) or as ASCII art.
+//! An ASCII art file starts with a legend that maps characters to pattern colors, followed by a
+//! line `---` and the pattern itself:
+//! ```text
+//! # A diamond with random colors
+//! x = random #282828 #ffffff
+//! o = alpha #ff8000 128
+//! ---
+//! ..x..
+//! .xox.
+//! x...x
+//! .x.x.
+//! ..x..
+//! ```
+//! The legend supports `none`, `solid COLOR`, `alpha COLOR ALPHA`, `perlin COLOR` and
+//! `random MINCOLOR MAXCOLOR`, where colors are given as hex code (`#ff8000`) or as comma-separated
+//! list without spaces (`255,128,0`). Lines starting with `#` in the legend are comments.
+//! `.` and spaces are transparent unless the legend declares them otherwise, and short rows are
+//! filled up with transparent pixels.
+use crate::algorithms::parse_color;
+use crate::patterns::pattern::Pattern;
+use crate::patterns::PatternColor;
+use crate::Error;
+use image::{ImageFormat, RgbaImage};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// The line that separates the legend from the pattern in ASCII art files
+const SEPARATOR: &str = "---";
+
+impl Pattern {
+    /// Load a pattern from an image or an ASCII art file.
+    /// Files with the extension of a supported image format are loaded as image.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        if ImageFormat::from_path(path).is_ok() {
+            Pattern::from_image(&image::open(path)?.to_rgba8())
+        } else {
+            Pattern::from_ascii(&std::fs::read_to_string(path)?)
+        }
+    }
+    /// Create a pattern from an image. Fully transparent pixels are skipped when drawing, partly
+    /// transparent pixels are blended over the image.
+    pub fn from_image(img: &RgbaImage) -> Result<Self, Error> {
+        let rows = img
+            .rows()
+            .map(|row| {
+                row.map(|pixel| {
+                    let [r, g, b, alpha] = pixel.0;
+                    match alpha {
+                        0 => PatternColor::None,
+                        u8::MAX => PatternColor::Solid { color: [r, g, b] },
+                        alpha => PatternColor::Alpha {
+                            color: [r, g, b],
+                            alpha,
+                        },
+                    }
+                })
+                .collect()
+            })
+            .collect();
+        Pattern::from_rows(rows)
+    }
+    /// Create a pattern from ASCII art with a legend, see the [module documentation](self)
+    pub fn from_ascii(text: &str) -> Result<Self, Error> {
+        let mut legend: HashMap<char, PatternColor> =
+            HashMap::from([('.', PatternColor::None), (' ', PatternColor::None)]);
+        let mut lines = text.lines().enumerate();
+        let mut separated = false;
+        for (number, line) in lines.by_ref() {
+            let line = line.trim();
+            if line == SEPARATOR {
+                separated = true;
+                break;
+            }
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (character, color) = parse_legend_entry(line).map_err(|reason| {
+                Error::InvalidPattern(format!("Line {}: {}", number + 1, reason))
+            })?;
+            legend.insert(character, color);
+        }
+        if !separated {
+            return Err(Error::InvalidPattern(format!(
+                "Expected a line {} between the legend and the pattern",
+                SEPARATOR
+            )));
+        }
+        let mut rows: Vec<Vec<PatternColor>> = lines
+            .map(|(number, line)| {
+                line.chars()
+                    .enumerate()
+                    .map(|(column, character)| {
+                        legend.get(&character).copied().ok_or_else(|| {
+                            Error::InvalidPattern(format!(
+                                "Line {}, column {}: The character '{}' is not in the legend",
+                                number + 1,
+                                column + 1,
+                                character
+                            ))
+                        })
+                    })
+                    .collect()
+            })
+            .collect::<Result<_, _>>()?;
+        // Trailing empty lines are not part of the pattern
+        while rows.last().is_some_and(Vec::is_empty) {
+            rows.pop();
+        }
+        Pattern::from_rows(rows)
+    }
+}
+
+/// Parse a legend entry like `x = solid #ff8000`
+fn parse_legend_entry(line: &str) -> Result<(char, PatternColor), String> {
+    let (character, color) = line
+        .split_once('=')
+        .ok_or_else(|| format!("Expected CHARACTER = COLOR, got \"{}\"", line))?;
+    let mut characters = character.trim().chars();
+    let character = match (characters.next(), characters.next()) {
+        (Some(character), None) => character,
+        _ => {
+            return Err(format!(
+                "Expected a single character, got \"{}\"",
+                character.trim()
+            ))
+        },
+    };
+    if character == '#' {
+        return Err("The character # starts a comment and cannot be used".to_string());
+    }
+    Ok((character, parse_pattern_color(color)?))
+}
+
+/// Parse a pattern color like `alpha #ff8000 128`
+fn parse_pattern_color(value: &str) -> Result<PatternColor, String> {
+    let words: Vec<&str> = value.split_whitespace().collect();
+    let color = |word: &str| parse_color(word).ok_or_else(|| format!("Invalid color \"{}\"", word));
+    match words.as_slice() {
+        ["none"] => Ok(PatternColor::None),
+        ["solid", c] => Ok(PatternColor::Solid { color: color(c)? }),
+        ["alpha", c, alpha] => Ok(PatternColor::Alpha {
+            color: color(c)?,
+            alpha: alpha
+                .parse()
+                .map_err(|_| format!("Invalid alpha \"{}\", expected 0 to 255", alpha))?,
+        }),
+        ["perlin", c] => Ok(PatternColor::Perlin {
+            blendcolor: color(c)?,
+        }),
+        ["random", min, max] => {
+            let (minvalue, maxvalue) = (color(min)?, color(max)?);
+            if (0..3).any(|c| minvalue[c] > maxvalue[c]) {
+                return Err(format!(
+                    "The minimum color {} must not exceed the maximum color {} in any channel",
+                    min, max
+                ));
+            }
+            Ok(PatternColor::Random { minvalue, maxvalue })
+        },
+        _ => Err(format!(
+            "Expected none, solid COLOR, alpha COLOR ALPHA, perlin COLOR or random MINCOLOR \
+             MAXCOLOR, got \"{}\"",
+            value.trim()
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Count how many pixels of the given pattern have the given color
+    fn count(pattern: &Pattern, color: PatternColor) -> usize {
+        pattern
+            .enumerate_pixels()
+            .filter(|(_, _, pixel)| **pixel == color)
+            .count()
+    }
+
+    #[test]
+    fn parse_ascii_patterns() {
+        let pattern = Pattern::from_ascii(
+            "# Comment\n\
+             x = solid #ff8000\n\
+             o = alpha 0,0,255 128\n\
+             ---\n\
+             x.o\n\
+             .x\n\
+             oxo\n\
+             \n",
+        )
+        .unwrap();
+        assert_eq!((pattern.width(), pattern.height()), (3, 3));
+        let solid = PatternColor::Solid {
+            color: [255, 128, 0],
+        };
+        let alpha = PatternColor::Alpha {
+            color: [0, 0, 255],
+            alpha: 128,
+        };
+        assert_eq!(count(&pattern, solid), 3);
+        assert_eq!(count(&pattern, alpha), 3);
+        assert_eq!(count(&pattern, PatternColor::None), 3);
+        let wide = Pattern::from_ascii("x = perlin #ff8000\n---\nx.x.x\n.x").unwrap();
+        assert_eq!((wide.width(), wide.height()), (5, 2));
+        let perlin = PatternColor::Perlin {
+            blendcolor: [255, 128, 0],
+        };
+        assert_eq!(count(&wide, perlin), 4);
+        assert_eq!(*wide.color_at(4, 0), perlin);
+        assert_eq!(*wide.color_at(4, 1), PatternColor::None);
+        assert!(Pattern::from_ascii("x = solid #ff8000\nxx").is_err());
+        assert!(Pattern::from_ascii("x = solid #ff8000\n---\nxy").is_err());
+        assert!(Pattern::from_ascii("x = sparkly\n---\nx").is_err());
+        assert!(Pattern::from_ascii("x = random #000000 #ffffff\n---\nx").is_ok());
+        // Swapped bounds would make the random color unsamplable
+        assert!(Pattern::from_ascii("x = random #ffffff #000000\n---\nx").is_err());
+        assert!(Pattern::from_ascii("x = random #00ff00 #ff00ff\n---\nx").is_err());
+        assert!(Pattern::from_ascii("---\n\n").is_err());
+    }
+
+    #[test]
+    fn transparent_image_pixels_are_skipped() {
+        let img = RgbaImage::from_fn(3, 3, |x, _| {
+            image::Rgba([10, 20, 30, [0, 100, 255][x as usize]])
+        });
+        let pattern = Pattern::from_image(&img).unwrap();
+        assert_eq!(count(&pattern, PatternColor::None), 3);
+        let alpha = PatternColor::Alpha {
+            color: [10, 20, 30],
+            alpha: 100,
+        };
+        assert_eq!(count(&pattern, alpha), 3);
+        let solid = PatternColor::Solid {
+            color: [10, 20, 30],
+        };
+        assert_eq!(count(&pattern, solid), 3);
+    }
+}
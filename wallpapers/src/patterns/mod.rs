@@ -1,7 +1,12 @@
+use crate::Error;
+use rand::Rng;
+
+mod loader;
 pub mod pattern;
+pub mod tiling;
 
 /// A color value used in pattern definitions
-#[derive(Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum PatternColor {
     /// A simple solid color
     Solid { color: [u8; 3] },
@@ -17,3 +22,76 @@ pub enum PatternColor {
         maxvalue: [u8; 3],
     },
 }
+
+impl PatternColor {
+    /// Get the new color of an image pixel after drawing this pattern color over its old color.
+    /// The shade in [0, 1] that Perlin colors are multiplied with is only computed if it is needed.
+    pub fn blend<R: Rng>(
+        &self,
+        old: [u8; 3],
+        rng: &mut R,
+        shade: impl FnOnce(&mut R) -> Result<f64, Error>,
+    ) -> Result<[u8; 3], Error> {
+        Ok(match *self {
+            PatternColor::Solid { color } => color,
+            PatternColor::Alpha { color, alpha } => [0, 1, 2].map(|c| {
+                ((color[c] as u32 * alpha as u32 + old[c] as u32 * (255 - alpha as u32)) / 255)
+                    as u8
+            }),
+            PatternColor::Perlin { blendcolor } => {
+                let shade = shade(rng)?;
+                blendcolor.map(|c| (c as f64 * shade) as u8)
+            },
+            PatternColor::None => old,
+            PatternColor::Random { minvalue, maxvalue } => {
+                if (0..3).any(|c| minvalue[c] > maxvalue[c]) {
+                    return Err(Error::InvalidPattern(format!(
+                        "The minimum color {:?} of a random color must not exceed the maximum \
+                         color {:?} in any channel",
+                        minvalue, maxvalue
+                    )));
+                }
+                [0, 1, 2].map(|c| rng.gen_range(minvalue[c]..=maxvalue[c]))
+            },
+        })
+    }
+    /// Get this pattern color in the given color, keeping its transparency.
+    /// Random colors become solid colors.
+    pub fn recolored(&self, new_color: [u8; 3]) -> Self {
+        match *self {
+            PatternColor::Solid { .. } | PatternColor::Random { .. } => {
+                PatternColor::Solid { color: new_color }
+            },
+            PatternColor::Alpha { alpha, .. } => PatternColor::Alpha {
+                color: new_color,
+                alpha,
+            },
+            PatternColor::Perlin { .. } => PatternColor::Perlin {
+                blendcolor: new_color,
+            },
+            PatternColor::None => PatternColor::None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ChaCha8Rng, SeedableRng};
+
+    #[test]
+    fn inverted_random_colors_are_rejected() {
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        let valid = PatternColor::Random {
+            minvalue: [10, 20, 30],
+            maxvalue: [10, 200, 255],
+        };
+        let color = valid.blend([0; 3], &mut rng, |_| Ok(1.0)).unwrap();
+        assert_eq!(color[0], 10);
+        let inverted = PatternColor::Random {
+            minvalue: [10, 200, 30],
+            maxvalue: [10, 20, 255],
+        };
+        assert!(inverted.blend([0; 3], &mut rng, |_| Ok(1.0)).is_err());
+    }
+}
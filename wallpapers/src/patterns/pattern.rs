@@ -1,61 +1,229 @@
-use crate::algorithms::Algorithm;
+use crate::algorithms::{unknown_parameter, Algorithm, Parameter, ParameterValue};
+use crate::patterns::tiling::Tiling;
 use crate::patterns::PatternColor;
-use image::RgbImage;
+use crate::utils::perlin::Perlin;
+use crate::Error;
+use image::{GrayImage, Luma, RgbImage};
 use rand::Rng;
+use std::f64::consts::PI;
+
+/// The base frequency of the noise of Perlin pattern colors, in pixels
+const NOISE_FREQUENCY: f64 = 0.01;
+/// The number of octaves of the noise of Perlin pattern colors
+const NOISE_OCTAVES: u32 = 4;
+
+/// Create the noise that is used by Perlin pattern colors for an image of the given size in pixels
+pub(crate) fn create_noise(rng: &mut impl Rng, width: f64, height: f64, tileable: bool) -> Perlin {
+    let mut perlin = Perlin::for_fractal(width, height, NOISE_FREQUENCY, NOISE_OCTAVES);
+    perlin.regenerate_noise(rng);
+    if tileable {
+        perlin.set_period(width, height);
+    }
+    perlin
+}
+
+/// Get the shade in [0, 1] of Perlin pattern colors at the given pixel
+pub(crate) fn noise_shade(noise: &Perlin, x: f64, y: f64) -> Result<f64, Error> {
+    let value = noise.fractal(x, y, NOISE_FREQUENCY, NOISE_OCTAVES)?;
+    Ok((0.5 + 0.5 * value).clamp(0.0, 1.0))
+}
 
 /// A simple pattern to be drawn onto an image
 pub struct Pattern {
-    /// The actual pattern
-    data: Vec<Vec<PatternColor>>,
+    /// The pixels of the pattern in row-major order, i.e. row by row from top to bottom
+    data: Vec<PatternColor>,
     width: usize,
     height: usize,
+    /// If true, the image size must be a multiple of the period of the tiling, so that the image
+    /// can be tiled seamlessly
+    tileable: bool,
+    /// The layout in which the pattern is repeated
+    tiling: Tiling,
+    /// The horizontal and vertical gap between two tiles in pixels
+    spacing: (usize, usize),
+    /// The offset of the tiling in pixels
+    offset: (i64, i64),
     // Add noise parameters, etc. here
 }
 
 impl Pattern {
     /// Initialize a new pattern from the given bool array by cloning the given pixel to each
-    /// pixel that is set to true.
+    /// pixel that is set to true. The array is given as rows, i.e. a pattern of width N and
+    /// height M.
     pub fn from_boolarray<const N: usize, const M: usize>(
         arr: &[[bool; N]; M],
         pixel: &PatternColor,
     ) -> Self {
-        let mut new_data = vec![vec![PatternColor::None; arr[0].len()]; arr.len()];
-        for (x, xx) in arr.iter().enumerate() {
-            for (y, yy) in xx.iter().enumerate() {
-                if *yy {
-                    new_data[x][y] = *pixel;
-                }
-            }
-        }
+        let data = arr
+            .iter()
+            .flatten()
+            .map(|set| if *set { *pixel } else { PatternColor::None })
+            .collect();
+        Self::with_data(data, N, M)
+    }
+    /// Initialize a new pattern with the given pixels in row-major order that is repeated on a
+    /// plain grid
+    fn with_data(data: Vec<PatternColor>, width: usize, height: usize) -> Self {
         Self {
-            data: new_data,
-            width: N,
-            height: M,
+            data,
+            width,
+            height,
+            tileable: false,
+            tiling: Tiling::Grid,
+            spacing: (0, 0),
+            offset: (0, 0),
         }
     }
+    /// Initialize a new pattern from the given rows of pixels.
+    /// Rows that are shorter than the longest row are filled up with [`PatternColor::None`].
+    pub fn from_rows(mut rows: Vec<Vec<PatternColor>>) -> Result<Self, Error> {
+        let width = rows.iter().map(Vec::len).max().unwrap_or(0);
+        if width == 0 {
+            return Err(Error::InvalidPattern(
+                "A pattern needs at least one pixel".to_string(),
+            ));
+        }
+        for row in rows.iter_mut() {
+            row.resize(width, PatternColor::None);
+        }
+        let height = rows.len();
+        Ok(Self::with_data(rows.concat(), width, height))
+    }
+    /// Get the width of the pattern in pixels
+    pub fn width(&self) -> usize {
+        self.width
+    }
+    /// Get the height of the pattern in pixels
+    pub fn height(&self) -> usize {
+        self.height
+    }
+    /// Get the color of the pattern pixel in the given column and row
+    pub fn color_at(&self, x: usize, y: usize) -> &PatternColor {
+        &self.data[y * self.width + x]
+    }
+    /// Iterate over all pixels row by row as (column, row, color)
     pub fn enumerate_pixels(&self) -> impl Iterator<Item = (usize, usize, &PatternColor)> {
-        (0..self.height)
-            .flat_map(move |h| (0..self.width).map(move |w| (w, h)))
-            .map(|(x, y)| (x, y, &self.data[x][y]))
+        self.data
+            .iter()
+            .enumerate()
+            .map(|(i, color)| (i % self.width, i / self.width, color))
+    }
+    /// Get the pattern color that is drawn at the given image pixel by the tiling
+    fn color_at_pixel(&self, x: u32, y: u32) -> PatternColor {
+        let position = (x as i64 - self.offset.0, y as i64 - self.offset.1);
+        self.tiling
+            .locate(position, (self.width, self.height), self.spacing)
+            .into_iter()
+            .flatten()
+            .map(|(u, v)| *self.color_at(u, v))
+            .find(|color| *color != PatternColor::None)
+            .unwrap_or(PatternColor::None)
+    }
+    /// Create a layer mask of the given size from the tiled pattern.
+    /// All pixels that are drawn by the pattern are white, except for colors with an alpha
+    /// channel, which give their alpha value. Pixels that are not drawn are black.
+    pub fn mask(&self, width: u32, height: u32) -> GrayImage {
+        GrayImage::from_fn(width, height, |x, y| {
+            Luma([match self.color_at_pixel(x, y) {
+                PatternColor::None => 0,
+                PatternColor::Alpha { alpha, .. } => alpha,
+                _ => u8::MAX,
+            }])
+        })
     }
 }
 
 impl<R: Rng> Algorithm<R> for Pattern {
-    fn build(&mut self, rng: &mut R, img: &mut RgbImage) -> Result<(), String> {
+    fn build(&mut self, rng: &mut R, img: &mut RgbImage) -> Result<(), Error> {
+        let size = (self.width, self.height);
+        let (period_x, period_y) = self.tiling.period(size, self.spacing);
+        if self.tileable
+            && (!(img.width() as usize).is_multiple_of(period_x)
+                || !(img.height() as usize).is_multiple_of(period_y))
+        {
+            return Err(Error::InvalidDimensions {
+                width: img.width(),
+                height: img.height(),
+                reason: format!(
+                    "A tileable {} pattern needs an image size that is a multiple of {}x{} pixels",
+                    self.tiling, period_x, period_y
+                ),
+            });
+        }
+        // The noise is only created if the pattern contains Perlin colors
+        let (width, height) = (img.width() as f64, img.height() as f64);
+        let mut noise: Option<Perlin> = None;
         for (x, y, pixel) in img.enumerate_pixels_mut() {
-            let val = self.data[x as usize % self.data.len()][y as usize % self.data[0].len()];
-            let old_val = pixel.0;
-            *pixel = match val {
-                PatternColor::Solid { color } => image::Rgb(color),
-                PatternColor::Alpha { .. } => todo!(),
-                PatternColor::Perlin { .. } => todo!(),
-                PatternColor::None => image::Rgb(old_val),
-                PatternColor::Random { minvalue, maxvalue } => image::Rgb([
-                    rng.gen_range(minvalue[0]..=maxvalue[0]),
-                    rng.gen_range(minvalue[1]..=maxvalue[1]),
-                    rng.gen_range(minvalue[2]..=maxvalue[2]),
-                ]),
-            };
+            let val = self.color_at_pixel(x, y);
+            let new_val = val.blend(pixel.0, rng, |rng| {
+                let noise =
+                    noise.get_or_insert_with(|| create_noise(rng, width, height, self.tileable));
+                noise_shade(noise, x as f64, y as f64)
+            })?;
+            *pixel = image::Rgb(new_val);
+        }
+        Ok(())
+    }
+    fn supports_tiling(&self) -> bool {
+        true
+    }
+    fn set_tileable(&mut self, tileable: bool) {
+        self.tileable = tileable;
+    }
+    fn supports_patterns(&self) -> bool {
+        true
+    }
+    fn set_pattern(&mut self, pattern: Pattern) {
+        *self = Pattern {
+            tileable: self.tileable,
+            tiling: self.tiling,
+            spacing: self.spacing,
+            offset: self.offset,
+            ..pattern
+        };
+    }
+    fn parameters(&self) -> Vec<Parameter> {
+        vec![
+            Parameter::new(
+                "tiling",
+                "The layout in which the pattern is repeated",
+                ParameterValue::Choice(self.tiling.to_string()),
+            )
+            .with_choices(Tiling::NAMES),
+            Parameter::new(
+                "spacing_x",
+                "The horizontal gap between two tiles in pixels",
+                ParameterValue::Integer(self.spacing.0 as i64),
+            )
+            .with_range(0.0, 1000.0),
+            Parameter::new(
+                "spacing_y",
+                "The vertical gap between two tiles in pixels",
+                ParameterValue::Integer(self.spacing.1 as i64),
+            )
+            .with_range(0.0, 1000.0),
+            Parameter::new(
+                "offset_x",
+                "Shift the tiling to the right by this many pixels",
+                ParameterValue::Integer(self.offset.0),
+            )
+            .with_range(-10_000.0, 10_000.0),
+            Parameter::new(
+                "offset_y",
+                "Shift the tiling down by this many pixels",
+                ParameterValue::Integer(self.offset.1),
+            )
+            .with_range(-10_000.0, 10_000.0),
+        ]
+    }
+    fn set_parameter(&mut self, name: &str, value: &ParameterValue) -> Result<(), Error> {
+        match (name, value) {
+            ("tiling", ParameterValue::Choice(tiling)) => self.tiling = tiling.parse()?,
+            ("spacing_x", ParameterValue::Integer(v)) => self.spacing.0 = *v as usize,
+            ("spacing_y", ParameterValue::Integer(v)) => self.spacing.1 = *v as usize,
+            ("offset_x", ParameterValue::Integer(v)) => self.offset.0 = *v,
+            ("offset_y", ParameterValue::Integer(v)) => self.offset.1 = *v,
+            _ => return Err(unknown_parameter(name)),
         }
         Ok(())
     }
@@ -65,6 +233,74 @@ impl<R: Rng> Algorithm<R> for Pattern {
 pub struct Patterns {}
 
 impl Patterns {
+    /// The names of all pre-defined patterns, see [`Patterns::by_name`]
+    pub const NAMES: &'static [&'static str] = &[
+        "diamond",
+        "checker",
+        "stripes",
+        "dots",
+        "crosses",
+        "herringbone",
+        "chevron",
+        "waves",
+        "greekkey",
+        "houndstooth",
+        "argyle",
+        "scales",
+    ];
+
+    /// Get the pre-defined pattern with the given name
+    pub fn by_name(name: &str) -> Result<Pattern, Error> {
+        Ok(match name {
+            "diamond" => Patterns::diamond(),
+            "checker" => Patterns::checker(),
+            "stripes" => Patterns::stripes(),
+            "dots" => Patterns::dots(),
+            "crosses" => Patterns::crosses(),
+            "herringbone" => Patterns::herringbone(),
+            "chevron" => Patterns::chevron(),
+            "waves" => Patterns::waves(),
+            "greekkey" => Patterns::greek_key(),
+            "houndstooth" => Patterns::houndstooth(),
+            "argyle" => Patterns::argyle(),
+            "scales" => Patterns::scales(),
+            _ => {
+                return Err(Error::InvalidParameter {
+                    name: "pattern_name".to_string(),
+                    reason: format!(
+                        "Expected one of {}, got {}",
+                        Patterns::NAMES.join(", "),
+                        name
+                    ),
+                })
+            },
+        })
+    }
+    /// Create a pattern of the given size with the color of each pixel given by a function of its
+    /// column and row
+    fn generate(
+        width: usize,
+        height: usize,
+        color: impl Fn(usize, usize) -> PatternColor,
+    ) -> Pattern {
+        let data = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| color(x, y))
+            .collect();
+        Pattern::with_data(data, width, height)
+    }
+    /// Create a pattern from ASCII art, where each character is replaced by its color in the
+    /// legend. Characters that are not in the legend are transparent.
+    fn from_art(art: &[&str], legend: &[(char, PatternColor)]) -> Pattern {
+        let width = art.iter().map(|row| row.len()).max().unwrap_or(0);
+        Patterns::generate(width, art.len(), |x, y| {
+            let character = art[y].chars().nth(x);
+            legend
+                .iter()
+                .find(|(c, _)| Some(*c) == character)
+                .map_or(PatternColor::None, |(_, color)| *color)
+        })
+    }
     pub fn diamond() -> Pattern {
         Pattern::from_boolarray(
             &[
@@ -80,4 +316,289 @@ impl Patterns {
             },
         )
     }
+    /// A checkerboard of light and dark squares
+    pub fn checker() -> Pattern {
+        Patterns::generate(8, 8, |x, y| {
+            let color = if (x / 4 + y / 4) % 2 == 0 {
+                [225, 220, 210]
+            } else {
+                [40, 40, 45]
+            };
+            PatternColor::Solid { color }
+        })
+    }
+    /// Diagonal stripes
+    pub fn stripes() -> Pattern {
+        Patterns::generate(8, 8, |x, y| {
+            let color = if (x + y) % 8 < 4 {
+                [40, 70, 140]
+            } else {
+                [230, 225, 210]
+            };
+            PatternColor::Solid { color }
+        })
+    }
+    /// Round dots shaded with Perlin noise
+    pub fn dots() -> Pattern {
+        Patterns::generate(8, 8, |x, y| {
+            let (dx, dy) = (x as f64 - 3.5, y as f64 - 3.5);
+            if dx * dx + dy * dy < 2.5 * 2.5 {
+                PatternColor::Perlin {
+                    blendcolor: [250, 200, 80],
+                }
+            } else {
+                PatternColor::None
+            }
+        })
+    }
+    /// Small crosses
+    pub fn crosses() -> Pattern {
+        Patterns::generate(7, 7, |x, y| {
+            if (x == 3 && (1..=5).contains(&y)) || (y == 3 && (1..=5).contains(&x)) {
+                PatternColor::Solid {
+                    color: [200, 40, 40],
+                }
+            } else {
+                PatternColor::None
+            }
+        })
+    }
+    /// A herringbone twill, whose diagonal ribs change direction every four columns
+    pub fn herringbone() -> Pattern {
+        Patterns::generate(8, 8, |x, y| {
+            let rib = if x < 4 { x + y } else { y + 8 - x };
+            let color = if rib % 4 < 2 {
+                [120, 80, 50]
+            } else {
+                [190, 150, 100]
+            };
+            PatternColor::Solid { color }
+        })
+    }
+    /// Zigzag bands
+    pub fn chevron() -> Pattern {
+        Patterns::generate(16, 8, |x, y| {
+            let rise = if x < 8 { x } else { 15 - x };
+            let color = if (y + rise) % 8 < 3 {
+                [220, 120, 40]
+            } else {
+                [40, 40, 60]
+            };
+            PatternColor::Solid { color }
+        })
+    }
+    /// Thin wavy lines
+    pub fn waves() -> Pattern {
+        Patterns::generate(16, 8, |x, y| {
+            let wave = 3.5 + 2.5 * (2.0 * PI * (x as f64 + 0.5) / 16.0).sin();
+            if (y as f64 - wave).abs() < 0.9 {
+                PatternColor::Alpha {
+                    color: [70, 150, 210],
+                    alpha: 220,
+                }
+            } else {
+                PatternColor::None
+            }
+        })
+    }
+    /// A running meander, i.e. a line that winds into a square spiral and back out along a
+    /// baseline
+    pub fn greek_key() -> Pattern {
+        Patterns::from_art(
+            &[
+                "........", "xxxxxxx.", "x.....x.", "x.xxx.x.", "x.x.x.x.", "x.x...x.", "x.xxxxx.",
+                "x.......", "xxxxxxxx",
+            ],
+            &[(
+                'x',
+                PatternColor::Solid {
+                    color: [200, 160, 60],
+                },
+            )],
+        )
+    }
+    /// The broken checks of a houndstooth fabric, i.e. a twill woven of four dark and four light
+    /// threads in each direction
+    pub fn houndstooth() -> Pattern {
+        Patterns::generate(8, 8, |x, y| {
+            // The warp thread lies on top of the weft thread in half of the pixels
+            let dark = if (x + y) % 4 < 2 { x < 4 } else { y < 4 };
+            let color = if dark { [30, 30, 35] } else { [235, 230, 220] };
+            PatternColor::Solid { color }
+        })
+    }
+    /// Diamonds of two alternating colors, crossed by thin diagonal lines
+    pub fn argyle() -> Pattern {
+        Patterns::generate(16, 16, |x, y| {
+            let (dx, dy) = ((x as f64 - 7.5).abs(), (y as f64 - 7.5).abs());
+            let color = if x == y || x + y == 15 {
+                [220, 220, 200]
+            } else if dx + dy <= 8.0 {
+                [150, 30, 50]
+            } else {
+                [40, 50, 90]
+            };
+            PatternColor::Solid { color }
+        })
+    }
+    /// Overlapping fish scales shaded with Perlin noise, where each row of scales covers the top of
+    /// the row below it, like roof shingles
+    pub fn scales() -> Pattern {
+        const SPACING: i64 = 8;
+        const RADIUS: f64 = 10.0;
+        const OUTLINE: f64 = 1.2;
+        Patterns::generate(16, 16, |x, y| {
+            let (px, py) = (x as f64 + 0.5, y as f64 + 0.5);
+            let row = y as i64 / SPACING;
+            // The scale centers of every other row are shifted by half a scale
+            let distance = (row - 1..=row + 1)
+                .flat_map(|row| {
+                    let shift = row.rem_euclid(2) * SPACING;
+                    (-1..=1).map(move |column| (shift + 2 * SPACING * column, SPACING * row))
+                })
+                .map(|(cx, cy)| (px - cx as f64).hypot(py - cy as f64))
+                .find(|distance| *distance < RADIUS)
+                .unwrap_or(0.0);
+            if distance > RADIUS - OUTLINE {
+                PatternColor::Solid {
+                    color: [230, 220, 180],
+                }
+            } else {
+                PatternColor::Perlin {
+                    blendcolor: [60, 150, 140],
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ChaCha8Rng, SeedableRng};
+
+    const CHECKERBOARD: [[bool; 2]; 2] = [[true, false], [false, true]];
+    /// An asymmetric pattern of 7x3 pixels, an L with a dot in the top right corner
+    const ASYMMETRIC: [[bool; 7]; 3] = [
+        [true, false, false, false, false, false, true],
+        [true, false, false, false, false, false, false],
+        [true, true, true, false, false, false, false],
+    ];
+    const WHITE: PatternColor = PatternColor::Solid {
+        color: [255, 255, 255],
+    };
+
+    #[test]
+    fn non_square_patterns_are_stored_row_by_row() {
+        let pattern = Pattern::from_boolarray(&ASYMMETRIC, &WHITE);
+        assert_eq!((pattern.width(), pattern.height()), (7, 3));
+        assert_eq!(*pattern.color_at(6, 0), WHITE);
+        assert_eq!(*pattern.color_at(0, 2), WHITE);
+        assert_eq!(*pattern.color_at(2, 2), WHITE);
+        assert_eq!(*pattern.color_at(2, 0), PatternColor::None);
+        assert_eq!(*pattern.color_at(6, 2), PatternColor::None);
+        let pixels: Vec<(usize, usize)> = pattern
+            .enumerate_pixels()
+            .filter(|(_, _, color)| **color == WHITE)
+            .map(|(x, y, _)| (x, y))
+            .collect();
+        assert_eq!(pixels, vec![(0, 0), (6, 0), (0, 1), (0, 2), (1, 2), (2, 2)]);
+        let rows: Vec<Vec<PatternColor>> = ASYMMETRIC
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|set| if *set { WHITE } else { PatternColor::None })
+                    .collect()
+            })
+            .collect();
+        let from_rows = Pattern::from_rows(rows).unwrap();
+        assert!(from_rows.enumerate_pixels().eq(pattern.enumerate_pixels()));
+    }
+
+    #[test]
+    fn non_square_patterns_are_drawn_upright() {
+        let mut pattern = Pattern::from_boolarray(&ASYMMETRIC, &WHITE);
+        let mut img = RgbImage::new(14, 6);
+        pattern
+            .build(&mut ChaCha8Rng::seed_from_u64(1), &mut img)
+            .unwrap();
+        for (x, y, pixel) in img.enumerate_pixels() {
+            let set = ASYMMETRIC[y as usize % 3][x as usize % 7];
+            assert_eq!(pixel.0 == [255, 255, 255], set, "{}, {}", x, y);
+        }
+    }
+
+    #[test]
+    fn masks_follow_the_tiling() {
+        let mut pattern = Pattern::from_boolarray(&ASYMMETRIC, &WHITE);
+        Algorithm::<ChaCha8Rng>::configure(&mut pattern, "spacing_x", ParameterValue::Integer(1))
+            .unwrap();
+        let mask = pattern.mask(16, 6);
+        for (x, y, pixel) in mask.enumerate_pixels() {
+            let set = ASYMMETRIC[y as usize % 3].get(x as usize % 8) == Some(&true);
+            assert_eq!(pixel.0, [if set { 255 } else { 0 }], "{}, {}", x, y);
+        }
+        let translucent = Pattern::from_boolarray(
+            &CHECKERBOARD,
+            &PatternColor::Alpha {
+                color: [0; 3],
+                alpha: 100,
+            },
+        );
+        assert_eq!(translucent.mask(2, 1).into_raw(), vec![100, 0]);
+    }
+
+    #[test]
+    fn alpha_colors_are_blended_over_the_image() {
+        let mut pattern = Pattern::from_boolarray(
+            &CHECKERBOARD,
+            &PatternColor::Alpha {
+                color: [255, 0, 100],
+                alpha: 51,
+            },
+        );
+        let mut img = RgbImage::from_pixel(4, 4, image::Rgb([0, 255, 100]));
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        pattern.build(&mut rng, &mut img).unwrap();
+        assert_eq!(img.get_pixel(0, 0).0, [51, 204, 100]);
+        assert_eq!(img.get_pixel(1, 0).0, [0, 255, 100]);
+    }
+
+    #[test]
+    fn perlin_colors_shade_the_blend_color() {
+        let mut pattern = Pattern::from_boolarray(
+            &CHECKERBOARD,
+            &PatternColor::Perlin {
+                blendcolor: [200, 100, 50],
+            },
+        );
+        let mut img = RgbImage::new(64, 48);
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        pattern.build(&mut rng, &mut img).unwrap();
+        let shaded: Vec<[u8; 3]> = img
+            .enumerate_pixels()
+            .filter(|(x, y, _)| (x + y) % 2 == 0)
+            .map(|(_, _, pixel)| pixel.0)
+            .collect();
+        assert!(shaded
+            .iter()
+            .all(|pixel| pixel[0] <= 200 && pixel[1] <= 100 && pixel[2] <= 50));
+        assert!(shaded.iter().any(|pixel| *pixel != shaded[0]));
+    }
+
+    #[test]
+    fn predefined_patterns_are_found_by_name() {
+        for name in Patterns::NAMES {
+            let pattern = Patterns::by_name(name).unwrap();
+            let colors: Vec<PatternColor> = pattern
+                .enumerate_pixels()
+                .map(|(_, _, color)| *color)
+                .collect();
+            assert!(colors.iter().any(|color| *color != colors[0]), "{}", name);
+        }
+        let checker = Patterns::by_name("checker").unwrap();
+        assert_eq!(checker.color_at(3, 0), checker.color_at(4, 4));
+        assert_ne!(checker.color_at(3, 0), checker.color_at(4, 0));
+        assert!(Patterns::by_name("paisley").is_err());
+    }
 }
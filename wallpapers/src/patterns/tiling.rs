@@ -0,0 +1,189 @@
+//! Layouts in which a pattern is repeated over an image, like the repeats of wallpaper and fabric
+//! designs.
+use crate::Error;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// The way a pattern is repeated over an image
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Tiling {
+    /// A plain grid of tiles
+    Grid,
+    /// Every other row of tiles is shifted by half a tile
+    Brick,
+    /// Every other column of tiles is shifted by half a tile
+    HalfDrop,
+    /// Every other column of tiles is mirrored horizontally, every other row vertically
+    Mirror,
+    /// The tiles alternate between the pattern and the pattern rotated by 90 degrees, like a
+    /// checkerboard
+    Rotate,
+    /// The tiles are packed like hexagons: every other row is shifted by half a tile, and the rows
+    /// are moved closer together
+    Hexagonal,
+}
+
+impl Tiling {
+    /// The names of all tilings, in the order of their declaration
+    pub const NAMES: &'static [&'static str] =
+        &["grid", "brick", "halfdrop", "mirror", "rotate", "hexagonal"];
+
+    /// Get the size of a single cell of the tiling for a pattern of the given size, including the
+    /// spacing between the tiles
+    fn cell_size(
+        &self,
+        (width, height): (usize, usize),
+        (space_x, space_y): (usize, usize),
+    ) -> (usize, usize) {
+        match self {
+            Tiling::Rotate => {
+                let size = width.max(height);
+                (size + space_x, size + space_y)
+            },
+            _ => (width + space_x, height + space_y),
+        }
+    }
+    /// Get the vertical distance between two rows of tiles with the given cell height
+    fn row_step(&self, cell_height: usize) -> usize {
+        match self {
+            Tiling::Hexagonal => ((cell_height as f64 * 3f64.sqrt() / 2.0).round() as usize).max(1),
+            _ => cell_height,
+        }
+    }
+    /// Get the size after which the tiling repeats itself, for a pattern of the given size
+    pub fn period(&self, size: (usize, usize), spacing: (usize, usize)) -> (usize, usize) {
+        let (cell_width, cell_height) = self.cell_size(size, spacing);
+        match self {
+            Tiling::Grid => (cell_width, cell_height),
+            Tiling::Brick => (cell_width, 2 * cell_height),
+            Tiling::HalfDrop => (2 * cell_width, cell_height),
+            Tiling::Mirror | Tiling::Rotate => (2 * cell_width, 2 * cell_height),
+            Tiling::Hexagonal => (cell_width, 2 * self.row_step(cell_height)),
+        }
+    }
+    /// Get the pixels of a pattern of the given size that may be drawn at the given image pixel,
+    /// in the order of precedence. Tiles only overlap in the hexagonal tiling, where the lower row
+    /// is drawn over the upper row.
+    pub fn locate(
+        &self,
+        (x, y): (i64, i64),
+        size: (usize, usize),
+        spacing: (usize, usize),
+    ) -> [Option<(usize, usize)>; 2] {
+        let (cell_width, cell_height) = self.cell_size(size, spacing);
+        let (cell_width, cell_height) = (cell_width as i64, cell_height as i64);
+        if *self == Tiling::Hexagonal {
+            let step = self.row_step(cell_height as usize) as i64;
+            let lower = y.div_euclid(step);
+            let mut candidates = [None, None];
+            for (candidate, row) in candidates.iter_mut().zip([lower, lower - 1]) {
+                let v = y - row * step;
+                if v < cell_height {
+                    let x = x + row.rem_euclid(2) * cell_width / 2;
+                    *candidate = self.pixel_in_cell(size, (x.rem_euclid(cell_width), v), false);
+                }
+            }
+            return candidates;
+        }
+        let column = x.div_euclid(cell_width);
+        let row = y.div_euclid(cell_height);
+        let (x, y) = match self {
+            Tiling::Brick => (x + row.rem_euclid(2) * cell_width / 2, y),
+            Tiling::HalfDrop => (x, y + column.rem_euclid(2) * cell_height / 2),
+            _ => (x, y),
+        };
+        let (mut u, mut v) = (x.rem_euclid(cell_width), y.rem_euclid(cell_height));
+        if *self == Tiling::Mirror {
+            let (width, height) = (size.0 as i64, size.1 as i64);
+            if column.rem_euclid(2) == 1 && u < width {
+                u = width - 1 - u;
+            }
+            if row.rem_euclid(2) == 1 && v < height {
+                v = height - 1 - v;
+            }
+        }
+        let rotated = *self == Tiling::Rotate && (column + row).rem_euclid(2) == 1;
+        [self.pixel_in_cell(size, (u, v), rotated), None]
+    }
+    /// Get the pattern pixel at the given position within a cell, if it is not in the spacing
+    /// between the tiles. Rotated tiles are turned clockwise by 90 degrees.
+    fn pixel_in_cell(
+        &self,
+        (width, height): (usize, usize),
+        (u, v): (i64, i64),
+        rotated: bool,
+    ) -> Option<(usize, usize)> {
+        let (u, v) = (u as usize, v as usize);
+        let (x, y) = if rotated {
+            (v, height.checked_sub(u + 1)?)
+        } else {
+            (u, v)
+        };
+        (x < width && y < height).then_some((x, y))
+    }
+}
+
+impl FromStr for Tiling {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "grid" => Ok(Tiling::Grid),
+            "brick" => Ok(Tiling::Brick),
+            "halfdrop" => Ok(Tiling::HalfDrop),
+            "mirror" => Ok(Tiling::Mirror),
+            "rotate" => Ok(Tiling::Rotate),
+            "hexagonal" => Ok(Tiling::Hexagonal),
+            _ => Err(Error::InvalidParameter {
+                name: "tiling".to_string(),
+                reason: format!("Expected one of {}, got {}", Tiling::NAMES.join(", "), s),
+            }),
+        }
+    }
+}
+
+impl Display for Tiling {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let index = *self as usize;
+        write!(f, "{}", Tiling::NAMES[index])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tilings_place_the_pattern_pixels() {
+        let size = (3, 2);
+        let first = |tiling: Tiling, x, y| tiling.locate((x, y), size, (1, 0))[0];
+        // The spacing leaves a gap of one pixel after each tile
+        assert_eq!(first(Tiling::Grid, 4, 1), Some((0, 1)));
+        assert_eq!(first(Tiling::Grid, 3, 0), None);
+        assert_eq!(first(Tiling::Grid, -1, -1), None);
+        assert_eq!(first(Tiling::Grid, -2, -1), Some((2, 1)));
+        // The second row is shifted by half a cell of 4 pixels
+        assert_eq!(first(Tiling::Brick, 0, 2), Some((2, 0)));
+        // The second column is shifted by one of two rows
+        assert_eq!(first(Tiling::HalfDrop, 4, 0), Some((0, 1)));
+        assert_eq!(first(Tiling::Mirror, 4, 0), Some((2, 0)));
+        assert_eq!(first(Tiling::Mirror, 0, 2), Some((0, 1)));
+        // Rotated cells are 4x3 pixels, the pattern occupies 2x3 pixels of them
+        assert_eq!(first(Tiling::Rotate, 4, 0), Some((0, 1)));
+        assert_eq!(first(Tiling::Rotate, 5, 2), Some((2, 0)));
+        assert_eq!(first(Tiling::Rotate, 6, 0), None);
+        for tiling in Tiling::NAMES {
+            let tiling: Tiling = tiling.parse().unwrap();
+            assert_eq!(tiling.to_string().parse::<Tiling>().unwrap(), tiling);
+            let (period_x, period_y) = tiling.period(size, (1, 0));
+            for (x, y) in [(0, 0), (2, 1), (5, 3)] {
+                assert_eq!(
+                    tiling.locate((x, y), size, (1, 0)),
+                    tiling.locate((x + period_x as i64, y + period_y as i64), size, (1, 0)),
+                    "{}",
+                    tiling
+                );
+            }
+        }
+    }
+}
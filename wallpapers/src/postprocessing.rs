@@ -0,0 +1,176 @@
+//! Image operations that are applied to a wallpaper after it has been generated
+use crate::algorithms::ParameterValue;
+use crate::Error;
+use image::{imageops, DynamicImage, Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+
+/// A palette that recolors an image by mapping the luminance of each pixel onto a gradient
+/// between the palette colors, from dark to bright.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "Vec<ParameterValue>", into = "Vec<ParameterValue>")]
+pub struct Palette {
+    colors: Vec<[u8; 3]>,
+}
+
+impl Palette {
+    /// Create a new palette from at least one color
+    pub fn new(colors: Vec<[u8; 3]>) -> Result<Self, Error> {
+        if colors.is_empty() {
+            return Err(Error::InvalidParameter {
+                name: "palette".to_string(),
+                reason: "A palette needs at least one color".to_string(),
+            });
+        }
+        Ok(Palette { colors })
+    }
+    pub fn colors(&self) -> &[[u8; 3]] {
+        &self.colors
+    }
+    /// Get the color of the gradient at the given position in [0,1]
+    pub fn color_at(&self, t: f64) -> [u8; 3] {
+        let scaled = t.clamp(0.0, 1.0) * (self.colors.len() - 1) as f64;
+        let index = (scaled as usize).min(self.colors.len() - 1);
+        let next = (index + 1).min(self.colors.len() - 1);
+        let weight = scaled - index as f64;
+        let mix = |a: u8, b: u8| (a as f64 * (1.0 - weight) + b as f64 * weight).round() as u8;
+        [
+            mix(self.colors[index][0], self.colors[next][0]),
+            mix(self.colors[index][1], self.colors[next][1]),
+            mix(self.colors[index][2], self.colors[next][2]),
+        ]
+    }
+    /// Recolor the given image, keeping its alpha channel
+    pub fn apply(&self, img: &mut RgbaImage) {
+        for pixel in img.pixels_mut() {
+            let [r, g, b, alpha] = pixel.0;
+            let luminance = (0.299 * r as f64 + 0.587 * g as f64 + 0.114 * b as f64) / 255.0;
+            let [r, g, b] = self.color_at(luminance);
+            *pixel = Rgba([r, g, b, alpha]);
+        }
+    }
+}
+
+impl TryFrom<Vec<ParameterValue>> for Palette {
+    type Error = Error;
+
+    fn try_from(values: Vec<ParameterValue>) -> Result<Self, Self::Error> {
+        let colors = values
+            .into_iter()
+            .map(|value| match value {
+                ParameterValue::Color(color) => Ok(color),
+                value => Err(Error::InvalidParameter {
+                    name: "palette".to_string(),
+                    reason: format!("Expected a color, got {}", value),
+                }),
+            })
+            .collect::<Result<_, _>>()?;
+        Palette::new(colors)
+    }
+}
+
+impl From<Palette> for Vec<ParameterValue> {
+    fn from(palette: Palette) -> Self {
+        palette
+            .colors
+            .into_iter()
+            .map(ParameterValue::Color)
+            .collect()
+    }
+}
+
+/// An image operation that is applied to a generated wallpaper
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum PostProcessing {
+    /// Gaussian blur with the given standard deviation
+    Blur { sigma: f32 },
+    /// Add the given value to each color channel. Negative values darken the image.
+    Brighten { value: i32 },
+    /// Adjust the contrast by the given amount. Negative values decrease the contrast.
+    Contrast { value: f32 },
+    /// Rotate the hue of each pixel by the given angle in degrees
+    HueRotate { degrees: i32 },
+    /// Invert all colors
+    Invert,
+    /// Convert the image to grayscale
+    Grayscale,
+}
+
+impl PostProcessing {
+    /// Check that the values of this operation can be applied. Large blurs need a lot of memory
+    /// and time, and NaN or infinite values give garbage.
+    pub fn validate(&self) -> Result<(), Error> {
+        match self {
+            PostProcessing::Blur { sigma } if !(*sigma > 0.0 && *sigma <= 1000.0) => {
+                Err(Error::InvalidParameter {
+                    name: "sigma".to_string(),
+                    reason: format!("The value {} is not in the range (0,1000]", sigma),
+                })
+            },
+            PostProcessing::Contrast { value } if !value.is_finite() => {
+                Err(Error::InvalidParameter {
+                    name: "value".to_string(),
+                    reason: format!("The value {} is not a finite number", value),
+                })
+            },
+            _ => Ok(()),
+        }
+    }
+    /// Apply this operation to the given image. Only blurring changes the alpha channel.
+    pub fn apply(&self, img: &mut RgbaImage) {
+        match self {
+            PostProcessing::Blur { sigma } => *img = imageops::blur(img, *sigma),
+            PostProcessing::Brighten { value } => {
+                imageops::colorops::brighten_in_place(img, *value)
+            },
+            PostProcessing::Contrast { value } => {
+                imageops::colorops::contrast_in_place(img, *value)
+            },
+            PostProcessing::HueRotate { degrees } => {
+                imageops::colorops::huerotate_in_place(img, *degrees)
+            },
+            PostProcessing::Invert => imageops::invert(img),
+            PostProcessing::Grayscale => {
+                *img = DynamicImage::ImageLumaA8(imageops::grayscale_alpha(img)).to_rgba8()
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn palette_interpolates_between_colors() {
+        let palette = Palette::new(vec![[0, 0, 0], [200, 100, 0], [200, 200, 200]]).unwrap();
+        assert_eq!(palette.color_at(0.0), [0, 0, 0]);
+        assert_eq!(palette.color_at(0.25), [100, 50, 0]);
+        assert_eq!(palette.color_at(0.5), [200, 100, 0]);
+        assert_eq!(palette.color_at(1.0), [200, 200, 200]);
+        assert!(Palette::new(vec![]).is_err());
+    }
+
+    #[test]
+    fn invalid_blurs_and_contrasts_are_rejected() {
+        for step in [
+            PostProcessing::Blur { sigma: 2.5 },
+            PostProcessing::Blur { sigma: 1000.0 },
+            PostProcessing::Contrast { value: -40.0 },
+            PostProcessing::Invert,
+        ] {
+            assert!(step.validate().is_ok(), "{:?}", step);
+        }
+        for step in [
+            PostProcessing::Blur { sigma: 0.0 },
+            PostProcessing::Blur { sigma: f32::NAN },
+            PostProcessing::Blur { sigma: 1e9 },
+            PostProcessing::Contrast { value: f32::NAN },
+            PostProcessing::Contrast {
+                value: f32::INFINITY,
+            },
+        ] {
+            assert!(step.validate().is_err(), "{:?}", step);
+        }
+    }
+}
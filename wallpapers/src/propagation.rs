@@ -0,0 +1,911 @@
+//! Color propagation on grids of cells.
+//!
+//! The cells of a [`CellGrid`] are visited one after another in the order of a [`Traversal`].
+//! Each cell gets the weighted average color of the cells of its [`Neighborhood`] that have already
+//! been colored, which is then randomly changed by a [`Mutation`]. Cells without colored
+//! neighbors get a random color. This is the engine behind the squares and mosaic modes.
+use crate::algorithms::{unknown_parameter, Parameter, ParameterValue};
+use crate::layers::cells::CellGrid;
+use crate::seed::Streams;
+use crate::utils::color::{Hsv, Oklch};
+use crate::Error;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::cmp::{max, min};
+use std::collections::VecDeque;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// The order in which the cells are colored
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Traversal {
+    /// Column by column from left to right, each column from top to bottom
+    Columns,
+    /// Row by row from top to bottom, each row from left to right
+    Rows,
+    /// In a square spiral from the center outwards
+    Spiral,
+    /// In a random order
+    Random,
+    /// Breadth-first from the randomly colored cells, or from a random cell if there are none
+    BreadthFirst,
+    /// Along a Hilbert curve, which keeps subsequent cells close together
+    Hilbert,
+}
+
+impl Traversal {
+    /// The names of all traversals, in the order of their declaration
+    pub const NAMES: &'static [&'static str] = &[
+        "columns",
+        "rows",
+        "spiral",
+        "random",
+        "breadthfirst",
+        "hilbert",
+    ];
+}
+
+/// The cells whose colors are averaged to color a cell
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Neighborhood {
+    /// The cells that share an edge, i.e. the 4 direct neighbors of a square
+    Four,
+    /// The cells that share an edge or a corner, i.e. the 8 surrounding cells of a square
+    Eight,
+    /// The 6 neighbors of a hexagon, treating every other row as shifted right by half a cell
+    Hex,
+}
+
+impl Neighborhood {
+    /// The names of all neighborhoods, in the order of their declaration
+    pub const NAMES: &'static [&'static str] = &["four", "eight", "hex"];
+
+    /// Get the neighborhood of the given cell. If the grid wraps around, the neighborhood
+    /// continues at the opposite border, treating the cells as a rectangular grid.
+    pub fn cells(
+        &self,
+        grid: &(impl CellGrid<[u8; 3]> + ?Sized),
+        (x, y): (usize, usize),
+        wrap: bool,
+    ) -> Vec<(usize, usize)> {
+        let (cells_h, cells_v) = grid.cell_count();
+        let offsets: Vec<(i64, i64)> = match self {
+            Neighborhood::Four if !wrap => return grid.neighbors(x, y),
+            Neighborhood::Eight if !wrap => return grid.touching(x, y),
+            Neighborhood::Four => vec![(-1, 0), (1, 0), (0, -1), (0, 1)],
+            Neighborhood::Eight => (-1..=1)
+                .flat_map(|dx| (-1..=1).map(move |dy| (dx, dy)))
+                .filter(|offset| *offset != (0, 0))
+                .collect(),
+            Neighborhood::Hex => {
+                let left = y as i64 % 2 - 1;
+                vec![
+                    (-1, 0),
+                    (1, 0),
+                    (left, -1),
+                    (left + 1, -1),
+                    (left, 1),
+                    (left + 1, 1),
+                ]
+            },
+        };
+        let (w, h) = (cells_h as i64, cells_v as i64);
+        offsets
+            .into_iter()
+            .map(|(dx, dy)| (x as i64 + dx, y as i64 + dy))
+            .filter_map(|(x, y)| {
+                if wrap {
+                    Some((x.rem_euclid(w) as usize, y.rem_euclid(h) as usize))
+                } else {
+                    ((0..w).contains(&x) && (0..h).contains(&y)).then_some((x as usize, y as usize))
+                }
+            })
+            .collect()
+    }
+}
+
+/// How many times the color of a neighbor is counted in the average
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Weighting {
+    /// All neighbors count once
+    Uniform,
+    /// The neighbors in the same row are weighted higher
+    Horizontal,
+    /// The neighbors in the same column are weighted higher
+    Vertical,
+    /// The neighbors in other rows and columns are weighted higher
+    Diagonal,
+}
+
+impl Weighting {
+    /// The names of all weightings, in the order of their declaration
+    pub const NAMES: &'static [&'static str] = &["uniform", "horizontal", "vertical", "diagonal"];
+
+    /// Get how many times the given neighbor of the given cell is counted, where the prioritized
+    /// neighbors count `weight` times and all other cells once
+    pub fn weight_of(
+        &self,
+        cell: (usize, usize),
+        neighbor: (usize, usize),
+        weight: usize,
+    ) -> usize {
+        let (same_column, same_row) = (cell.0 == neighbor.0, cell.1 == neighbor.1);
+        let prioritized = match self {
+            Weighting::Uniform => false,
+            Weighting::Horizontal => same_row && !same_column,
+            Weighting::Vertical => same_column && !same_row,
+            Weighting::Diagonal => !same_column && !same_row,
+        };
+        if prioritized {
+            weight
+        } else {
+            1
+        }
+    }
+}
+
+/// The random change of the average color of the neighbors
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Mutation {
+    /// Add a random offset to one random RGB channel
+    Channel,
+    /// Add a separate random offset to each RGB channel
+    All,
+    /// Add the same random offset to all RGB channels, which only changes the brightness
+    Brightness,
+    /// Add separate random offsets to the hue, chroma and lightness in the perceptual OKLCH color
+    /// space, so that the colors drift evenly
+    Oklch,
+    /// Add separate random offsets to the hue, saturation and value in the HSV color model
+    Hsv,
+}
+
+impl Mutation {
+    /// The names of all mutations, in the order of their declaration
+    pub const NAMES: &'static [&'static str] = &["channel", "all", "brightness", "oklch", "hsv"];
+
+    /// Change the given color by random offsets of at most the given RGB amount, or of at most the
+    /// given variation for the mutations in other color spaces
+    pub fn apply(
+        &self,
+        rng: &mut impl Rng,
+        color: [u8; 3],
+        (amount, variation): (u8, &Variation),
+    ) -> [u8; 3] {
+        let amount = amount as i32;
+        let mut offset = |max: f64| rng.gen_range(-max..=max);
+        let shift = |value: u8, offset: i32| (value as i32 + offset).clamp(0, 255) as u8;
+        match self {
+            Mutation::Channel => {
+                let offset = rng.gen_range(-amount..=amount);
+                let channel = rng.gen_range(0..3);
+                let mut color = color;
+                color[channel] = shift(color[channel], offset);
+                color
+            },
+            Mutation::All => color.map(|value| shift(value, rng.gen_range(-amount..=amount))),
+            Mutation::Brightness => {
+                let offset = rng.gen_range(-amount..=amount);
+                color.map(|value| shift(value, offset))
+            },
+            Mutation::Oklch => {
+                let color = Oklch::from_rgb(color);
+                Oklch {
+                    lightness: variation.limit(color.lightness + offset(variation.lightness)),
+                    chroma: color.chroma + offset(variation.chroma),
+                    hue: color.hue + offset(variation.hue),
+                }
+                .to_rgb()
+            },
+            Mutation::Hsv => {
+                let color = Hsv::from_rgb(color);
+                Hsv {
+                    value: variation.limit(color.value + offset(variation.lightness)),
+                    saturation: color.saturation + offset(variation.chroma),
+                    hue: color.hue + offset(variation.hue),
+                }
+                .to_rgb()
+            },
+        }
+    }
+    /// Move the lightness of the given color into the lightness range of the given variation, if
+    /// this mutation works in a color space with a lightness
+    pub fn limit(&self, color: [u8; 3], variation: &Variation) -> [u8; 3] {
+        match self {
+            Mutation::Channel | Mutation::All | Mutation::Brightness => color,
+            Mutation::Oklch => {
+                let color = Oklch::from_rgb(color);
+                Oklch {
+                    lightness: variation.limit(color.lightness),
+                    ..color
+                }
+                .to_rgb()
+            },
+            Mutation::Hsv => {
+                let color = Hsv::from_rgb(color);
+                Hsv {
+                    value: variation.limit(color.value),
+                    ..color
+                }
+                .to_rgb()
+            },
+        }
+    }
+}
+
+/// The maximum random offsets of the mutations in other color spaces than RGB.
+/// For [`Mutation::Oklch`], the chroma and lightness are given in OKLCH units, where the lightness
+/// goes from 0 to 1 and the most colorful colors have a chroma of about 0.3.
+/// For [`Mutation::Hsv`], the chroma is the saturation and the lightness is the value, both from
+/// 0 to 1.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Variation {
+    /// The maximum offset of the hue in degrees
+    pub hue: f64,
+    pub chroma: f64,
+    pub lightness: f64,
+    /// The lowest and highest allowed lightness, which keeps all colors within a brightness range
+    pub lightness_range: (f64, f64),
+}
+
+impl Default for Variation {
+    fn default() -> Self {
+        Variation {
+            hue: 8.0,
+            chroma: 0.02,
+            lightness: 0.03,
+            lightness_range: (0.0, 1.0),
+        }
+    }
+}
+
+impl Variation {
+    /// Move the given lightness into the lightness range
+    fn limit(&self, lightness: f64) -> f64 {
+        let (min, max) = self.lightness_range;
+        lightness.max(min).min(max)
+    }
+}
+
+/// The configuration of a color propagation
+#[derive(Debug, Clone, PartialEq)]
+pub struct Propagation {
+    pub traversal: Traversal,
+    pub neighborhood: Neighborhood,
+    pub weighting: Weighting,
+    /// How many times the prioritized neighbors of the weighting are counted
+    pub weight: usize,
+    pub mutation: Mutation,
+    /// The maximum random offset of the RGB mutations
+    pub variation_amount: u8,
+    /// The maximum random offsets of the other mutations
+    pub variation: Variation,
+    /// If true, the neighborhood wraps around at the grid borders, and the last quarter of the
+    /// columns and rows is blended towards the first column and row, so that the grid can be tiled
+    pub wrap: bool,
+}
+
+impl Default for Propagation {
+    fn default() -> Self {
+        Propagation {
+            traversal: Traversal::Columns,
+            neighborhood: Neighborhood::Eight,
+            weighting: Weighting::Uniform,
+            weight: 1,
+            mutation: Mutation::Channel,
+            variation_amount: 20,
+            variation: Variation::default(),
+            wrap: false,
+        }
+    }
+}
+
+impl Propagation {
+    /// The parameters of the propagation, shared by all algorithms that use it
+    pub fn parameters(&self) -> Vec<Parameter> {
+        vec![
+            Parameter::new(
+                "variation_amount",
+                "The maximum random offset that is added to the RGB color of each cell",
+                ParameterValue::Integer(self.variation_amount as i64),
+            )
+            .with_range(0.0, 255.0),
+            Parameter::new(
+                "weight",
+                "How many times the neighbors prioritized by the weighting are counted",
+                ParameterValue::Integer(self.weight as i64),
+            )
+            .with_range(0.0, 1000.0),
+            Parameter::new(
+                "traversal",
+                "The order in which the cells are colored",
+                ParameterValue::Choice(self.traversal.to_string()),
+            )
+            .with_choices(Traversal::NAMES),
+            Parameter::new(
+                "neighborhood",
+                "The neighbors whose average color is used for each cell",
+                ParameterValue::Choice(self.neighborhood.to_string()),
+            )
+            .with_choices(Neighborhood::NAMES),
+            Parameter::new(
+                "weighting",
+                "The neighbors that are counted weight times",
+                ParameterValue::Choice(self.weighting.to_string()),
+            )
+            .with_choices(Weighting::NAMES),
+            Parameter::new(
+                "mutation",
+                "How the average color of the neighbors is randomly changed",
+                ParameterValue::Choice(self.mutation.to_string()),
+            )
+            .with_choices(Mutation::NAMES),
+            Parameter::new(
+                "hue_variation",
+                "The maximum random offset of the hue in degrees, for the oklch and hsv mutations",
+                ParameterValue::Float(self.variation.hue),
+            )
+            .with_range(0.0, 180.0),
+            Parameter::new(
+                "chroma_variation",
+                "The maximum random offset of the chroma (oklch) or saturation (hsv)",
+                ParameterValue::Float(self.variation.chroma),
+            )
+            .with_range(0.0, 1.0),
+            Parameter::new(
+                "lightness_variation",
+                "The maximum random offset of the lightness (oklch) or value (hsv)",
+                ParameterValue::Float(self.variation.lightness),
+            )
+            .with_range(0.0, 1.0),
+            Parameter::new(
+                "min_lightness",
+                "The lowest lightness (oklch) or value (hsv) of all colors",
+                ParameterValue::Float(self.variation.lightness_range.0),
+            )
+            .with_range(0.0, 1.0),
+            Parameter::new(
+                "max_lightness",
+                "The highest lightness (oklch) or value (hsv) of all colors",
+                ParameterValue::Float(self.variation.lightness_range.1),
+            )
+            .with_range(0.0, 1.0),
+        ]
+    }
+    pub fn set_parameter(&mut self, name: &str, value: &ParameterValue) -> Result<(), Error> {
+        match (name, value) {
+            ("variation_amount", ParameterValue::Integer(v)) => self.variation_amount = *v as u8,
+            ("weight", ParameterValue::Integer(v)) => self.weight = *v as usize,
+            ("traversal", ParameterValue::Choice(v)) => self.traversal = v.parse()?,
+            ("neighborhood", ParameterValue::Choice(v)) => self.neighborhood = v.parse()?,
+            ("weighting", ParameterValue::Choice(v)) => self.weighting = v.parse()?,
+            ("mutation", ParameterValue::Choice(v)) => self.mutation = v.parse()?,
+            ("hue_variation", ParameterValue::Float(v)) => self.variation.hue = *v,
+            ("chroma_variation", ParameterValue::Float(v)) => self.variation.chroma = *v,
+            ("lightness_variation", ParameterValue::Float(v)) => self.variation.lightness = *v,
+            ("min_lightness", ParameterValue::Float(v)) => self.variation.lightness_range.0 = *v,
+            ("max_lightness", ParameterValue::Float(v)) => self.variation.lightness_range.1 = *v,
+            _ => return Err(unknown_parameter(name)),
+        }
+        Ok(())
+    }
+    /// Color all cells of the given grid, starting with the given number of randomly colored cells
+    pub fn run(
+        &self,
+        grid: &mut (impl CellGrid<[u8; 3]> + ?Sized),
+        streams: &Streams,
+        random_cells: usize,
+    ) -> Result<(), Error> {
+        let (min, max) = self.variation.lightness_range;
+        if min > max {
+            return Err(Error::InvalidParameter {
+                name: "min_lightness".to_string(),
+                reason: format!("The value {} is greater than max_lightness {}", min, max),
+            });
+        }
+        let (cells_h, cells_v) = grid.cell_count();
+        let mut visited = vec![vec![false; cells_v]; cells_h];
+        let seeds = self.populate_cells(grid, &mut visited, streams, random_cells);
+        let order = self.order(grid, seeds, &mut streams.rng("traversal"));
+        let mut variation = streams.rng("variation");
+        // Cells whose first column or row cell has not been colored yet are blended after the
+        // traversal
+        let mut unblended = vec![];
+        for (x, y) in order {
+            let color = match self.average_color(grid, &visited, (x, y)) {
+                Some(average) => self.mutation.apply(
+                    &mut variation,
+                    average,
+                    (self.variation_amount, &self.variation),
+                ),
+                // Setting a color without any adjacent neighbors --> Completely random color
+                None => self.mutation.limit(
+                    [
+                        variation.gen_range(0..255),
+                        variation.gen_range(0..255),
+                        variation.gen_range(0..255),
+                    ],
+                    &self.variation,
+                ),
+            };
+            grid.set_color_at(x, y, color);
+            visited[x][y] = true;
+            if self.wrap {
+                let seams = [
+                    ((0, y), seam_weight(x, cells_h)),
+                    ((x, 0), seam_weight(y, cells_v)),
+                ];
+                for (target, weight) in seams {
+                    if let Some(weight) = weight {
+                        if visited[target.0][target.1] {
+                            Self::blend_towards(grid, (x, y), target, weight);
+                        } else {
+                            unblended.push(((x, y), target, weight));
+                        }
+                    }
+                }
+            }
+        }
+        for (cell, target, weight) in unblended {
+            Self::blend_towards(grid, cell, target, weight);
+        }
+        Ok(())
+    }
+    /// Give the given number of random cells a random color and return them
+    fn populate_cells(
+        &self,
+        grid: &mut (impl CellGrid<[u8; 3]> + ?Sized),
+        visited: &mut [Vec<bool>],
+        streams: &Streams,
+        count: usize,
+    ) -> Vec<(usize, usize)> {
+        let (cells_h, cells_v) = grid.cell_count();
+        let mut positions = streams.rng("points");
+        let mut palette = streams.rng("palette");
+        let mut cells = vec![];
+        for _ in 0..count {
+            let x = positions.gen_range(0..cells_h);
+            let y = positions.gen_range(0..cells_v);
+            let color: [u8; 3] = [
+                palette.gen_range(0..255),
+                palette.gen_range(0..255),
+                palette.gen_range(0..255),
+            ];
+            grid.set_color_at(x, y, self.mutation.limit(color, &self.variation));
+            visited[x][y] = true;
+            cells.push((x, y));
+        }
+        cells
+    }
+    /// Get all cells of the grid in the order of the traversal
+    fn order(
+        &self,
+        grid: &(impl CellGrid<[u8; 3]> + ?Sized),
+        seeds: Vec<(usize, usize)>,
+        rng: &mut impl Rng,
+    ) -> Vec<(usize, usize)> {
+        let (cells_h, cells_v) = grid.cell_count();
+        let columns = || (0..cells_h).flat_map(move |x| (0..cells_v).map(move |y| (x, y)));
+        match self.traversal {
+            Traversal::Columns => columns().collect(),
+            Traversal::Rows => (0..cells_v)
+                .flat_map(|y| (0..cells_h).map(move |x| (x, y)))
+                .collect(),
+            Traversal::Spiral => spiral(cells_h, cells_v),
+            Traversal::Random => {
+                let mut cells: Vec<(usize, usize)> = columns().collect();
+                cells.shuffle(rng);
+                cells
+            },
+            Traversal::BreadthFirst => {
+                let mut seeds = seeds;
+                if seeds.is_empty() {
+                    seeds.push((rng.gen_range(0..cells_h), rng.gen_range(0..cells_v)));
+                }
+                let mut queued = vec![vec![false; cells_v]; cells_h];
+                let mut queue = VecDeque::new();
+                for (x, y) in seeds {
+                    if !queued[x][y] {
+                        queued[x][y] = true;
+                        queue.push_back((x, y));
+                    }
+                }
+                let mut cells = vec![];
+                while let Some(cell) = queue.pop_front() {
+                    cells.push(cell);
+                    for (x, y) in self.neighborhood.cells(grid, cell, self.wrap) {
+                        if !queued[x][y] {
+                            queued[x][y] = true;
+                            queue.push_back((x, y));
+                        }
+                    }
+                }
+                // Cells that cannot be reached from the seeds are colored last
+                cells.extend(columns().filter(|(x, y)| !queued[*x][*y]));
+                cells
+            },
+            Traversal::Hilbert => hilbert(cells_h, cells_v),
+        }
+    }
+    /// Gets the weighted average color of the given cell and its neighborhood.
+    /// All cells that have not been visited yet are skipped in the calculation.
+    /// If none of the cells has been visited, return None
+    fn average_color(
+        &self,
+        grid: &(impl CellGrid<[u8; 3]> + ?Sized),
+        visited: &[Vec<bool>],
+        cell: (usize, usize),
+    ) -> Option<[u8; 3]> {
+        let mut cells = self.neighborhood.cells(grid, cell, self.wrap);
+        cells.push(cell);
+        let mut sum = [0usize; 3];
+        let mut count = 0;
+        for (x, y) in cells {
+            if visited[x][y] {
+                let weight = self.weighting.weight_of(cell, (x, y), self.weight);
+                let color = grid.get_color_at(x, y);
+                for c in 0..3 {
+                    sum[c] += color[c] as usize * weight;
+                }
+                count += weight;
+            }
+        }
+        (count > 0).then(|| sum.map(|sum| (sum / count) as u8))
+    }
+    /// Blend the color of the given cell towards the color of the given target cell by the given
+    /// weight in [0, 1]
+    fn blend_towards(
+        grid: &mut (impl CellGrid<[u8; 3]> + ?Sized),
+        (x, y): (usize, usize),
+        (target_x, target_y): (usize, usize),
+        weight: f64,
+    ) {
+        let color = *grid.get_color_at(x, y);
+        let target = *grid.get_color_at(target_x, target_y);
+        let blended = [0, 1, 2]
+            .map(|c| (color[c] as f64 * (1.0 - weight) + target[c] as f64 * weight).round() as u8);
+        grid.set_color_at(x, y, blended);
+    }
+}
+
+/// Get how much a cell at the given position along an axis with the given number of cells is
+/// blended towards the first cell of the axis, if the grid wraps around.
+/// The colors drift away from the first cells while the cells are colored, and averaging across
+/// the border alone is not enough to continue them at the opposite border. Therefore, the last
+/// quarter of the cells is blended towards the first cell, increasingly towards the border.
+fn seam_weight(position: usize, count: usize) -> Option<f64> {
+    let band = max(1, count / 4);
+    let start = count.saturating_sub(band);
+    if position == 0 || position < start {
+        return None;
+    }
+    Some((position - start + 1) as f64 / (band + 1) as f64)
+}
+
+/// Get all cells of a grid of the given size in a square spiral from the center outwards
+fn spiral(cells_h: usize, cells_v: usize) -> Vec<(usize, usize)> {
+    let total = cells_h * cells_v;
+    let mut cells = Vec::with_capacity(total);
+    let (mut x, mut y) = ((cells_h / 2) as i64, (cells_v / 2) as i64);
+    let directions = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+    let push = |x: i64, y: i64, cells: &mut Vec<(usize, usize)>| {
+        if (0..cells_h as i64).contains(&x) && (0..cells_v as i64).contains(&y) {
+            cells.push((x as usize, y as usize));
+        }
+    };
+    push(x, y, &mut cells);
+    // The legs of the spiral grow by one cell after every second turn
+    let mut leg = 1;
+    let mut turn = 0;
+    while cells.len() < total {
+        let (dx, dy) = directions[turn % 4];
+        for _ in 0..leg {
+            x += dx;
+            y += dy;
+            push(x, y, &mut cells);
+        }
+        turn += 1;
+        if turn % 2 == 0 {
+            leg += 1;
+        }
+    }
+    cells
+}
+
+/// Get all cells of a grid of the given size along Hilbert curves. The grid is covered by a row or
+/// column of square blocks along its longer side, whose power of two side length fits the shorter
+/// side of the grid. Each block is walked along its own curve, which ends next to the start of the
+/// following block.
+fn hilbert(cells_h: usize, cells_v: usize) -> Vec<(usize, usize)> {
+    let side = min(cells_h, cells_v).next_power_of_two();
+    let blocks = max(cells_h, cells_v).div_ceil(side);
+    (0..blocks)
+        .flat_map(|block| {
+            (0..side * side).map(move |index| {
+                let (along, across) = hilbert_position(side, index);
+                if cells_h >= cells_v {
+                    (block * side + along, across)
+                } else {
+                    (across, block * side + along)
+                }
+            })
+        })
+        .filter(|(x, y)| *x < cells_h && *y < cells_v)
+        .collect()
+}
+
+/// Convert an index along a Hilbert curve through a square of the given power of two side length
+/// to a position. The curve starts at (0, 0) and ends at (side - 1, 0).
+fn hilbert_position(side: usize, index: usize) -> (usize, usize) {
+    // Convert the index to a position one quadrant level at a time
+    let (mut x, mut y, mut t) = (0, 0, index);
+    let mut size = 1;
+    while size < side {
+        let rx = 1 & (t / 2);
+        let ry = 1 & (t ^ rx);
+        if ry == 0 {
+            if rx == 1 {
+                x = size - 1 - x;
+                y = size - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        x += size * rx;
+        y += size * ry;
+        t /= 4;
+        size *= 2;
+    }
+    (x, y)
+}
+
+impl FromStr for Traversal {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "columns" => Ok(Traversal::Columns),
+            "rows" => Ok(Traversal::Rows),
+            "spiral" => Ok(Traversal::Spiral),
+            "random" => Ok(Traversal::Random),
+            "breadthfirst" => Ok(Traversal::BreadthFirst),
+            "hilbert" => Ok(Traversal::Hilbert),
+            _ => Err(Error::InvalidParameter {
+                name: "traversal".to_string(),
+                reason: format!("Expected one of {}, got {}", Traversal::NAMES.join(", "), s),
+            }),
+        }
+    }
+}
+
+impl Display for Traversal {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let index = *self as usize;
+        write!(f, "{}", Traversal::NAMES[index])
+    }
+}
+
+impl FromStr for Neighborhood {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "four" => Ok(Neighborhood::Four),
+            "eight" => Ok(Neighborhood::Eight),
+            "hex" => Ok(Neighborhood::Hex),
+            _ => Err(Error::InvalidParameter {
+                name: "neighborhood".to_string(),
+                reason: format!(
+                    "Expected one of {}, got {}",
+                    Neighborhood::NAMES.join(", "),
+                    s
+                ),
+            }),
+        }
+    }
+}
+
+impl Display for Neighborhood {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let index = *self as usize;
+        write!(f, "{}", Neighborhood::NAMES[index])
+    }
+}
+
+impl FromStr for Weighting {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "uniform" => Ok(Weighting::Uniform),
+            "horizontal" => Ok(Weighting::Horizontal),
+            "vertical" => Ok(Weighting::Vertical),
+            "diagonal" => Ok(Weighting::Diagonal),
+            _ => Err(Error::InvalidParameter {
+                name: "weighting".to_string(),
+                reason: format!("Expected one of {}, got {}", Weighting::NAMES.join(", "), s),
+            }),
+        }
+    }
+}
+
+impl Display for Weighting {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let index = *self as usize;
+        write!(f, "{}", Weighting::NAMES[index])
+    }
+}
+
+impl FromStr for Mutation {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "channel" => Ok(Mutation::Channel),
+            "all" => Ok(Mutation::All),
+            "brightness" => Ok(Mutation::Brightness),
+            "oklch" => Ok(Mutation::Oklch),
+            "hsv" => Ok(Mutation::Hsv),
+            _ => Err(Error::InvalidParameter {
+                name: "mutation".to_string(),
+                reason: format!("Expected one of {}, got {}", Mutation::NAMES.join(", "), s),
+            }),
+        }
+    }
+}
+
+impl Display for Mutation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let index = *self as usize;
+        write!(f, "{}", Mutation::NAMES[index])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layers::squareslayer::SquaresLayer;
+    use crate::seed::Seed;
+
+    #[test]
+    fn traversals_visit_every_cell_once() {
+        let grid: SquaresLayer<[u8; 3]> = SquaresLayer::new(7, 5, 1, 1);
+        let mut rng = Streams::new(Seed::from(1)).rng("traversal");
+        for name in Traversal::NAMES {
+            let propagation = Propagation {
+                traversal: name.parse().unwrap(),
+                ..Propagation::default()
+            };
+            let mut order = propagation.order(&grid, vec![(6, 4)], &mut rng);
+            if propagation.traversal == Traversal::BreadthFirst {
+                assert_eq!(order[0], (6, 4));
+            }
+            order.sort();
+            let expected: Vec<(usize, usize)> =
+                (0..7).flat_map(|x| (0..5).map(move |y| (x, y))).collect();
+            assert_eq!(order, expected, "{}", name);
+        }
+        assert_eq!(&spiral(3, 3)[..3], &[(1, 1), (2, 1), (2, 2)]);
+        assert_eq!(hilbert(2, 2), vec![(0, 0), (0, 1), (1, 1), (1, 0)]);
+        // Subsequent cells of a curve over whole blocks are always adjacent
+        for (cells_h, cells_v) in [(16, 4), (4, 16), (8, 8)] {
+            let order = hilbert(cells_h, cells_v);
+            assert_eq!(order.len(), cells_h * cells_v);
+            for pair in order.windows(2) {
+                let distance = pair[0].0.abs_diff(pair[1].0) + pair[0].1.abs_diff(pair[1].1);
+                assert_eq!(distance, 1, "{:?}", pair);
+            }
+        }
+        assert_eq!(hilbert(60_000, 4).len(), 240_000);
+        assert!("zigzag".parse::<Traversal>().is_err());
+        assert_eq!(
+            Neighborhood::NAMES
+                .iter()
+                .map(|name| name.parse::<Neighborhood>().unwrap().to_string())
+                .collect::<Vec<_>>(),
+            Neighborhood::NAMES
+        );
+    }
+
+    #[test]
+    fn colors_propagate_from_the_neighbors() {
+        let mut grid: SquaresLayer<[u8; 3]> = SquaresLayer::new(8, 6, 1, 1);
+        let propagation = Propagation {
+            variation_amount: 0,
+            ..Propagation::default()
+        };
+        propagation
+            .run(&mut grid, &Streams::new(Seed::from(3)), 0)
+            .unwrap();
+        // Without a variation, the color of the first cell spreads over the whole grid
+        let first = *grid.get_color_at(0, 0);
+        for x in 0..8 {
+            for y in 0..6 {
+                assert_eq!(*grid.get_color_at(x, y), first);
+            }
+        }
+        let wrapping = Neighborhood::Hex.cells(&grid, (0, 1), true);
+        assert_eq!(
+            wrapping,
+            vec![(7, 1), (1, 1), (0, 0), (1, 0), (0, 2), (1, 2)]
+        );
+        assert_eq!(Neighborhood::Four.cells(&grid, (0, 0), false).len(), 2);
+        assert_eq!(Weighting::Horizontal.weight_of((3, 3), (2, 3), 6), 6);
+        assert_eq!(Weighting::Horizontal.weight_of((3, 3), (3, 2), 6), 1);
+    }
+
+    #[test]
+    fn wrapping_grids_continue_at_the_opposite_border() {
+        for name in Traversal::NAMES {
+            let mut grid: SquaresLayer<[u8; 3]> = SquaresLayer::new(16, 12, 1, 1);
+            let propagation = Propagation {
+                traversal: name.parse().unwrap(),
+                wrap: true,
+                ..Propagation::default()
+            };
+            propagation
+                .run(&mut grid, &Streams::new(Seed::from(9)), 0)
+                .unwrap();
+            let difference = |pairs: Vec<((usize, usize), (usize, usize))>| {
+                let count = pairs.len() as u32 * 3;
+                let sum: u32 = pairs
+                    .into_iter()
+                    .flat_map(|(a, b)| {
+                        let (a, b) = (*grid.get_color_at(a.0, a.1), *grid.get_color_at(b.0, b.1));
+                        (0..3).map(move |c| a[c].abs_diff(b[c]) as u32)
+                    })
+                    .sum();
+                sum / count
+            };
+            // The last column and row are blended 4/5 and 3/4 of the way towards the first ones
+            let columns = difference((0..12).map(|y| ((0, y), (15, y))).collect());
+            let rows = difference((0..16).map(|x| ((x, 0), (x, 11))).collect());
+            assert!(columns < 20 && rows < 20, "{}: {} {}", name, columns, rows);
+        }
+    }
+
+    #[test]
+    fn perceptual_mutations_keep_the_lightness_range() {
+        for mutation in [Mutation::Oklch, Mutation::Hsv] {
+            let mut grid: SquaresLayer<[u8; 3]> = SquaresLayer::new(12, 8, 1, 1);
+            let propagation = Propagation {
+                mutation,
+                variation: Variation {
+                    lightness: 0.2,
+                    lightness_range: (0.4, 0.6),
+                    ..Variation::default()
+                },
+                ..Propagation::default()
+            };
+            propagation
+                .run(&mut grid, &Streams::new(Seed::from(5)), 4)
+                .unwrap();
+            for x in 0..12 {
+                for y in 0..8 {
+                    let color = *grid.get_color_at(x, y);
+                    let lightness = match mutation {
+                        Mutation::Oklch => Oklch::from_rgb(color).lightness,
+                        _ => Hsv::from_rgb(color).value,
+                    };
+                    // Allow for the rounding to 8 bit colors
+                    assert!((0.39..=0.61).contains(&lightness), "{}", lightness);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn empty_lightness_ranges_are_rejected() {
+        let mut propagation = Propagation::default();
+        propagation
+            .set_parameter("min_lightness", &ParameterValue::Float(0.7))
+            .unwrap();
+        propagation
+            .set_parameter("max_lightness", &ParameterValue::Float(0.3))
+            .unwrap();
+        let mut grid: SquaresLayer<[u8; 3]> = SquaresLayer::new(4, 4, 1, 1);
+        let streams = Streams::new(Seed::from(5));
+        assert!(propagation.run(&mut grid, &streams, 1).is_err());
+        propagation.variation.lightness_range = (0.3, 0.3);
+        assert!(propagation.run(&mut grid, &streams, 1).is_ok());
+    }
+}
@@ -0,0 +1,856 @@
+//! Recipes describe a complete wallpaper generation, so that a wallpaper can be saved to a file
+//! (e.g. TOML or JSON) and rendered again later.
+use crate::algorithms::{Algorithm, ParameterValue};
+use crate::layers::imagelayer::ImageLayer;
+use crate::layers::stack::LayerStack;
+use crate::layers::{mask, Layer, MixMode};
+use crate::metadata;
+use crate::monitors::Layout;
+use crate::patterns::pattern::{Pattern, Patterns};
+use crate::postprocessing::{Palette, PostProcessing};
+use crate::registry::Registry;
+use crate::seed::{Seed, Streams};
+use crate::Error;
+use image::{imageops, DynamicImage, GrayImage, ImageFormat, RgbImage, RgbaImage};
+use rand::Rng;
+use rand_chacha::rand_core::SeedableRng;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// The file formats a wallpaper can be saved as
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    Png,
+    Jpeg,
+    Bmp,
+    Tiff,
+    WebP,
+}
+
+impl From<OutputFormat> for ImageFormat {
+    fn from(format: OutputFormat) -> Self {
+        match format {
+            OutputFormat::Png => ImageFormat::Png,
+            OutputFormat::Jpeg => ImageFormat::Jpeg,
+            OutputFormat::Bmp => ImageFormat::Bmp,
+            OutputFormat::Tiff => ImageFormat::Tiff,
+            OutputFormat::WebP => ImageFormat::WebP,
+        }
+    }
+}
+
+fn default_width() -> u32 {
+    1920
+}
+
+fn default_height() -> u32 {
+    1080
+}
+
+/// Deserialize post-processing steps, which are validated on loading like the other recipe values
+fn deserialize_post_processing<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<PostProcessing>, D::Error> {
+    let steps = Vec::<PostProcessing>::deserialize(deserializer)?;
+    for step in &steps {
+        step.validate().map_err(serde::de::Error::custom)?;
+    }
+    Ok(steps)
+}
+
+fn is_zero(value: &u32) -> bool {
+    *value == 0
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+fn default_opacity() -> f64 {
+    1.0
+}
+
+fn is_opaque(value: &f64) -> bool {
+    *value == 1.0
+}
+
+fn is_normal(mode: &MixMode) -> bool {
+    *mode == MixMode::Normal
+}
+
+/// A layer that is composited over the wallpaper, see [`Recipe::layers`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LayerRecipe {
+    /// The name of the algorithm in the registry that renders the layer
+    pub mode: String,
+    /// A pattern file that is drawn by the pattern modes instead of their built-in pattern
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<PathBuf>,
+    /// The name of a pre-defined pattern that is drawn by the pattern modes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pattern_name: Option<String>,
+    /// The way the layer is mixed with the layers below
+    #[serde(default, skip_serializing_if = "is_normal")]
+    pub mix_mode: MixMode,
+    /// The opacity of the layer in [0, 1]
+    #[serde(default = "default_opacity", skip_serializing_if = "is_opaque")]
+    pub opacity: f64,
+    /// If true, the algorithm renders a transparent background, so that only its foreground is
+    /// drawn over the layers below
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub transparent: bool,
+    /// Parameters of the algorithm, see [`Algorithm::parameters`]
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub params: BTreeMap<String, ParameterValue>,
+    /// A grayscale mask that limits where the layer is applied
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mask: Option<MaskRecipe>,
+}
+
+impl LayerRecipe {
+    pub fn new(mode: &str) -> Self {
+        LayerRecipe {
+            mode: mode.to_string(),
+            pattern: None,
+            pattern_name: None,
+            mix_mode: MixMode::Normal,
+            opacity: 1.0,
+            transparent: false,
+            params: BTreeMap::new(),
+            mask: None,
+        }
+    }
+}
+
+fn default_mask_frequency() -> f64 {
+    0.004
+}
+
+fn default_mask_octaves() -> u32 {
+    4
+}
+
+fn default_mask_center() -> (f64, f64) {
+    (0.5, 0.5)
+}
+
+fn default_mask_radius() -> f64 {
+    1.0
+}
+
+/// A mask of a layer. Black parts of the mask hide the layer, white parts show it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "MaskRecipeFields")]
+pub struct MaskRecipe {
+    /// What the mask is created from
+    #[serde(flatten)]
+    pub source: MaskSource,
+    /// If true, black parts of the mask show the layer and white parts hide it
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub invert: bool,
+}
+
+/// A mask as written in a recipe. Serde cannot deny unknown fields next to a flattened field, so
+/// all keys are collected and checked against the keys of the source.
+#[derive(Deserialize)]
+struct MaskRecipeFields {
+    #[serde(flatten)]
+    source: MaskSource,
+    #[serde(default)]
+    invert: bool,
+    #[serde(flatten)]
+    keys: BTreeMap<String, serde::de::IgnoredAny>,
+}
+
+impl TryFrom<MaskRecipeFields> for MaskRecipe {
+    type Error = Error;
+
+    fn try_from(fields: MaskRecipeFields) -> Result<Self, Self::Error> {
+        let known = fields.source.keys();
+        match fields
+            .keys
+            .keys()
+            .find(|key| *key != "type" && !known.contains(&key.as_str()))
+        {
+            Some(key) => Err(Error::InvalidParameter {
+                name: key.clone(),
+                reason: format!(
+                    "Unknown key of a mask, expected one of type, invert, {}",
+                    known.join(", ")
+                ),
+            }),
+            None => Ok(MaskRecipe {
+                source: fields.source,
+                invert: fields.invert,
+            }),
+        }
+    }
+}
+
+/// What a layer mask is created from, see [`mask`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum MaskSource {
+    /// The luminance of the output of the algorithm with the given name in the registry
+    Algorithm {
+        mode: String,
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        params: BTreeMap<String, ParameterValue>,
+    },
+    /// A pattern file or a pre-defined pattern, which shows the layer where the pattern is drawn.
+    /// The parameters configure the tiling of the pattern.
+    Pattern {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pattern: Option<PathBuf>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pattern_name: Option<String>,
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        params: BTreeMap<String, ParameterValue>,
+    },
+    /// Perlin fractal noise with the given base frequency in virtual units
+    Noise {
+        #[serde(default = "default_mask_frequency")]
+        frequency: f64,
+        #[serde(default = "default_mask_octaves")]
+        octaves: u32,
+    },
+    /// A gradient across the image in the direction of the given angle in degrees
+    Linear {
+        #[serde(default)]
+        angle: f64,
+    },
+    /// A gradient from the given center, relative to the image size, to the given radius,
+    /// relative to half the image diagonal
+    Radial {
+        #[serde(default = "default_mask_center")]
+        center: (f64, f64),
+        #[serde(default = "default_mask_radius")]
+        radius: f64,
+    },
+}
+
+impl MaskSource {
+    /// Get the keys of this source in a recipe, besides its type
+    fn keys(&self) -> &'static [&'static str] {
+        match self {
+            MaskSource::Algorithm { .. } => &["mode", "params"],
+            MaskSource::Pattern { .. } => &["pattern", "pattern_name", "params"],
+            MaskSource::Noise { .. } => &["frequency", "octaves"],
+            MaskSource::Linear { .. } => &["angle"],
+            MaskSource::Radial { .. } => &["center", "radius"],
+        }
+    }
+}
+
+impl MaskRecipe {
+    pub fn new(source: MaskSource) -> Self {
+        MaskRecipe {
+            source,
+            invert: false,
+        }
+    }
+    /// Create the mask in the given size
+    pub fn render<R: Rng>(
+        &self,
+        registry: &Registry<R>,
+        rng: &mut R,
+        (width, height): (u32, u32),
+        tileable: bool,
+    ) -> Result<GrayImage, Error> {
+        let mut mask_img = match &self.source {
+            MaskSource::Algorithm { mode, params } => {
+                let mut algorithm =
+                    create_configured(registry, mode, (None, None), (tileable, false), params)?;
+                let mut img = RgbImage::new(width, height);
+                algorithm.build(rng, &mut img)?;
+                mask::luminance(&img)
+            },
+            MaskSource::Pattern {
+                pattern,
+                pattern_name,
+                params,
+            } => {
+                let mut pattern = match (pattern, pattern_name) {
+                    (Some(path), None) => Pattern::load(path)?,
+                    (None, Some(name)) => Patterns::by_name(name)?,
+                    _ => {
+                        return Err(Error::InvalidParameter {
+                            name: "pattern".to_string(),
+                            reason: "A pattern mask needs either a pattern file or a pattern name"
+                                .to_string(),
+                        })
+                    },
+                };
+                for (name, value) in params {
+                    Algorithm::<R>::configure(&mut pattern, name, value.clone())?;
+                }
+                pattern.mask(width, height)
+            },
+            MaskSource::Noise { frequency, octaves } => {
+                // The noise grid grows with the frequency of the highest octave, so both are
+                // limited like the octaves of clouds, whose frequency is at most 0.005
+                let limits = [
+                    ("frequency", *frequency, (0.0001, 0.01)),
+                    ("octaves", *octaves as f64, (1.0, 8.0)),
+                ];
+                for (name, value, (min, max)) in limits {
+                    if !(min..=max).contains(&value) {
+                        return Err(Error::InvalidParameter {
+                            name: name.to_string(),
+                            reason: format!(
+                                "The value {} is not in the range [{},{}]",
+                                value, min, max
+                            ),
+                        });
+                    }
+                }
+                mask::noise(rng, (width, height), (*frequency, *octaves), tileable)?
+            },
+            MaskSource::Linear { angle } => mask::linear_gradient((width, height), *angle),
+            MaskSource::Radial { center, radius } => {
+                mask::radial_gradient((width, height), *center, *radius)
+            },
+        };
+        if self.invert {
+            imageops::invert(&mut mask_img);
+        }
+        Ok(mask_img)
+    }
+}
+
+/// Create the algorithm with the given name and configure it
+fn create_configured<R: Rng>(
+    registry: &Registry<R>,
+    mode: &str,
+    (pattern, pattern_name): (Option<&Path>, Option<&str>),
+    (tileable, transparent): (bool, bool),
+    params: &BTreeMap<String, ParameterValue>,
+) -> Result<Box<dyn Algorithm<R>>, Error> {
+    let mut algorithm = registry.create(mode)?;
+    let pattern = match (pattern, pattern_name) {
+        (Some(_), Some(_)) => {
+            return Err(Error::InvalidParameter {
+                name: "pattern".to_string(),
+                reason: "Either a pattern file or a pattern name can be given, not both"
+                    .to_string(),
+            })
+        },
+        (Some(path), None) => Some(Pattern::load(path)?),
+        (None, Some(name)) => Some(Patterns::by_name(name)?),
+        (None, None) => None,
+    };
+    if let Some(pattern) = pattern {
+        if !algorithm.supports_patterns() {
+            return Err(Error::InvalidParameter {
+                name: "pattern".to_string(),
+                reason: format!("Mode {} does not draw patterns", mode),
+            });
+        }
+        algorithm.set_pattern(pattern);
+    }
+    if tileable {
+        if !algorithm.supports_tiling() {
+            return Err(Error::InvalidParameter {
+                name: "tileable".to_string(),
+                reason: format!("Mode {} cannot render tileable wallpapers", mode),
+            });
+        }
+        algorithm.set_tileable(true);
+    }
+    if transparent {
+        if !algorithm.supports_transparency() {
+            return Err(Error::InvalidParameter {
+                name: "transparent".to_string(),
+                reason: format!("Mode {} cannot render a transparent background", mode),
+            });
+        }
+        algorithm.set_transparent(true);
+    }
+    for (name, value) in params {
+        algorithm.configure(name, value.clone())?;
+    }
+    Ok(algorithm)
+}
+
+/// A complete description of a wallpaper generation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Recipe {
+    /// The name of the algorithm in the registry
+    pub mode: String,
+    /// The width of the wallpaper in pixels
+    #[serde(default = "default_width")]
+    pub width: u32,
+    /// The height of the wallpaper in pixels
+    #[serde(default = "default_height")]
+    pub height: u32,
+    /// A monitor layout the wallpaper spans. If a layout is given, the wallpaper is rendered on a
+    /// canvas that contains all monitors, and width and height are ignored.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub monitors: Option<Layout>,
+    /// The gap in pixels between adjacent monitors of the layout
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub bezel: u32,
+    /// If true, the wallpaper is rendered seamlessly tileable, i.e. its right edge continues at
+    /// the left edge and its bottom edge continues at the top edge
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub tileable: bool,
+    /// If true, the algorithm renders a transparent background and the wallpaper is saved with an
+    /// alpha channel
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub transparent: bool,
+    /// A pattern file (ASCII art or image) that is drawn by the pattern modes instead of their
+    /// built-in pattern, see [`Pattern::load`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<PathBuf>,
+    /// The name of a pre-defined pattern that is drawn by the pattern modes instead of their
+    /// built-in pattern, see [`Patterns::by_name`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pattern_name: Option<String>,
+    /// The seed of the random number generator. If no seed is given, a random seed is used.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<Seed>,
+    /// The file the wallpaper is saved to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<PathBuf>,
+    /// The file format of the output. If no format is given, it is guessed from the file name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<OutputFormat>,
+    /// A palette the generated wallpaper is recolored with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub palette: Option<Palette>,
+    /// Parameters of the algorithm, see [`Algorithm::parameters`]
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub params: BTreeMap<String, ParameterValue>,
+    /// Layers that are composited over the output of the algorithm in order, from the bottom to
+    /// the top
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub layers: Vec<LayerRecipe>,
+    /// Operations that are applied to the wallpaper in order, after the palette
+    #[serde(
+        default,
+        skip_serializing_if = "Vec::is_empty",
+        deserialize_with = "deserialize_post_processing"
+    )]
+    pub post_processing: Vec<PostProcessing>,
+}
+
+impl Recipe {
+    pub fn new(mode: &str, width: u32, height: u32) -> Self {
+        Recipe {
+            mode: mode.to_string(),
+            width,
+            height,
+            monitors: None,
+            bezel: 0,
+            tileable: false,
+            transparent: false,
+            pattern: None,
+            pattern_name: None,
+            seed: None,
+            output: None,
+            format: None,
+            palette: None,
+            params: BTreeMap::new(),
+            layers: vec![],
+            post_processing: vec![],
+        }
+    }
+    /// Create the algorithm of this recipe and set all parameters
+    pub fn create_algorithm<R: Rng>(
+        &self,
+        registry: &Registry<R>,
+    ) -> Result<Box<dyn Algorithm<R>>, Error> {
+        create_configured(
+            registry,
+            &self.mode,
+            (self.pattern.as_deref(), self.pattern_name.as_deref()),
+            (self.tileable, self.transparent),
+            &self.params,
+        )
+    }
+    /// Create the random number generator of the given stage of the rendering. Each layer and
+    /// mask gets its own stream, so that adding a layer does not change the layers below.
+    fn stage_rng<R: SeedableRng>(&self, stage: &str) -> R {
+        match self.seed {
+            Some(seed) => {
+                R::seed_from_u64(Streams::new(seed).substreams("layers").seed(stage).value())
+            },
+            None => R::from_entropy(),
+        }
+    }
+    /// Render all layers of this recipe in the given size and stack them
+    fn create_layer_stack<R: Rng + SeedableRng>(
+        &self,
+        registry: &Registry<R>,
+        (width, height): (u32, u32),
+    ) -> Result<LayerStack, Error> {
+        let mut stack = LayerStack::new();
+        for (index, layer) in self.layers.iter().enumerate() {
+            // Layers clamp their opacity, which would silently accept wrong values and keep NaN
+            if !(0.0..=1.0).contains(&layer.opacity) {
+                return Err(Error::InvalidParameter {
+                    name: "opacity".to_string(),
+                    reason: format!("The value {} is not in the range [0,1]", layer.opacity),
+                });
+            }
+            let mut algorithm = create_configured(
+                registry,
+                &layer.mode,
+                (layer.pattern.as_deref(), layer.pattern_name.as_deref()),
+                (self.tileable, layer.transparent),
+                &layer.params,
+            )?;
+            let mut rng = self.stage_rng(&format!("layer{}", index));
+            let mut image_layer = ImageLayer::render(&mut *algorithm, &mut rng, width, height)?;
+            image_layer.set_mix_mode(layer.mix_mode);
+            image_layer.set_opacity(layer.opacity);
+            if let Some(mask) = &layer.mask {
+                let mut rng = self.stage_rng(&format!("mask{}", index));
+                let mask_img = mask.render(registry, &mut rng, (width, height), self.tileable)?;
+                image_layer.set_mask(Some(mask_img));
+            }
+            stack.push(Box::new(image_layer));
+        }
+        Ok(stack)
+    }
+    /// Get the size of the rendered image, which contains all monitors if a layout is given
+    pub fn canvas_size(&self) -> Result<(u32, u32), Error> {
+        match &self.monitors {
+            Some(layout) => layout.canvas_size(self.bezel),
+            None => Ok((self.width, self.height)),
+        }
+    }
+    /// Render the wallpaper described by this recipe. The wallpaper is opaque unless the recipe
+    /// is transparent.
+    pub fn render<R: Rng + SeedableRng>(&self, registry: &Registry<R>) -> Result<RgbaImage, Error> {
+        let mut algorithm = self.create_algorithm(registry)?;
+        let mut rng = match self.seed {
+            Some(seed) => R::seed_from_u64(seed.value()),
+            None => R::from_entropy(),
+        };
+        let (width, height) = self.canvas_size()?;
+        for step in &self.post_processing {
+            step.validate()?;
+        }
+        let mut img = RgbaImage::new(width, height);
+        algorithm.build_rgba(&mut rng, &mut img)?;
+        self.create_layer_stack(registry, (width, height))?
+            .draw(&mut img)?;
+        if let Some(palette) = &self.palette {
+            palette.apply(&mut img);
+        }
+        for step in &self.post_processing {
+            step.apply(&mut img);
+        }
+        Ok(img)
+    }
+    /// Get the format a wallpaper is saved as at the given path, which is the output format of
+    /// this recipe or the format given by the file extension
+    pub fn format_of(&self, path: &Path) -> Result<ImageFormat, Error> {
+        match self.format {
+            Some(format) => Ok(format.into()),
+            None => Ok(ImageFormat::from_path(path)?),
+        }
+    }
+    /// Save the given image to the given path, using the output format of this recipe.
+    /// The alpha channel is only saved if the recipe is transparent.
+    /// The recipe is embedded into the file, see [`metadata`](crate::metadata).
+    pub fn save(&self, img: &RgbaImage, path: &Path) -> Result<(), Error> {
+        let format = self.format_of(path)?;
+        let img = DynamicImage::ImageRgba8(img.clone());
+        if !self.transparent {
+            return metadata::save(&img.to_rgb8().into(), path, format, self);
+        }
+        let supports_alpha = matches!(
+            format,
+            ImageFormat::Png | ImageFormat::WebP | ImageFormat::Tiff | ImageFormat::Bmp
+        );
+        if !supports_alpha {
+            return Err(Error::InvalidParameter {
+                name: "transparent".to_string(),
+                reason: format!("{:?} files cannot store transparent wallpapers", format),
+            });
+        }
+        metadata::save(&img, path, format, self)
+    }
+    /// Load the recipe that was embedded into the given wallpaper by [`Recipe::save`]
+    pub fn from_image(path: &Path) -> Result<Self, Error> {
+        metadata::read_recipe(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ChaCha8Rng;
+
+    #[test]
+    fn rendering_a_seeded_recipe_is_reproducible() {
+        let registry: Registry<ChaCha8Rng> = Registry::builtin();
+        let mut recipe = Recipe::new("flow", 64, 48);
+        recipe.seed = Some(Seed::from(123456));
+        recipe
+            .params
+            .insert("num_particles".to_string(), ParameterValue::Integer(100));
+        recipe.palette = Some(Palette::new(vec![[0, 0, 64], [255, 200, 0]]).unwrap());
+        recipe.post_processing = vec![PostProcessing::Invert];
+        assert_eq!(
+            recipe.render(&registry).unwrap(),
+            recipe.render(&registry).unwrap()
+        );
+    }
+
+    #[test]
+    fn same_seed_gives_same_composition_at_any_size() {
+        let registry: Registry<ChaCha8Rng> = Registry::builtin();
+        let mut small = Recipe::new("nearestpoint", 96, 54);
+        small.seed = Some(Seed::from(123456));
+        let large = Recipe {
+            width: 192,
+            height: 108,
+            ..small.clone()
+        };
+        let small = small.render(&registry).unwrap();
+        let large = large.render(&registry).unwrap();
+        let matching = small
+            .enumerate_pixels()
+            .filter(|(x, y, pixel)| large.get_pixel(2 * x + 1, 2 * y + 1) == *pixel)
+            .count();
+        // Pixels at the borders between cells may differ, because they are sampled at different
+        // positions
+        assert!(matching as f64 > 0.9 * (96 * 54) as f64);
+    }
+
+    /// Get the mean difference of the color channels of two pixel lines of the given image
+    fn line_difference(img: &RgbaImage, first: &[(u32, u32)], second: &[(u32, u32)]) -> f64 {
+        let total: u32 = first
+            .iter()
+            .zip(second)
+            .flat_map(|(&(x1, y1), &(x2, y2))| {
+                let (a, b) = (img.get_pixel(x1, y1).0, img.get_pixel(x2, y2).0);
+                (0..3).map(move |c| a[c].abs_diff(b[c]) as u32)
+            })
+            .sum();
+        total as f64 / (3 * first.len()) as f64
+    }
+
+    #[test]
+    fn tileable_wallpapers_continue_at_the_borders() {
+        let registry: Registry<ChaCha8Rng> = Registry::builtin();
+        for mode in ["clouds", "nearestpoint", "squares", "flow"] {
+            let mut recipe = Recipe::new(mode, 120, 80);
+            recipe.seed = Some(Seed::from(42));
+            recipe.tileable = true;
+            let img = recipe.render(&registry).unwrap();
+            let column = |x: u32| (0..80).map(|y| (x, y)).collect::<Vec<_>>();
+            let row = |y: u32| (0..120).map(|x| (x, y)).collect::<Vec<_>>();
+            // The seam between the last and the first line should not stand out from the
+            // differences between adjacent lines within the image
+            let columns = (0..119)
+                .map(|x| line_difference(&img, &column(x), &column(x + 1)))
+                .sum::<f64>()
+                / 119.0;
+            let rows = (0..79)
+                .map(|y| line_difference(&img, &row(y), &row(y + 1)))
+                .sum::<f64>()
+                / 79.0;
+            let seam_x = line_difference(&img, &column(119), &column(0));
+            let seam_y = line_difference(&img, &row(79), &row(0));
+            assert!(seam_x <= 2.0 * columns + 1.0, "{}", mode);
+            assert!(seam_y <= 2.0 * rows + 1.0, "{}", mode);
+        }
+        let mut recipe = Recipe::new("tangles", 120, 80);
+        recipe.tileable = true;
+        assert!(matches!(
+            recipe.render(&registry),
+            Err(Error::InvalidParameter { .. })
+        ));
+    }
+
+    #[test]
+    fn layers_are_composited_over_the_wallpaper() {
+        let registry: Registry<ChaCha8Rng> = Registry::builtin();
+        let mut base = Recipe::new("clouds", 64, 48);
+        base.seed = Some(Seed::from(7));
+        let mut lightning = LayerRecipe::new("lightning");
+        lightning.mix_mode = MixMode::Screen;
+        let mut recipe = base.clone();
+        recipe.layers = vec![lightning.clone()];
+        let background = base.render(&registry).unwrap();
+        let composite = recipe.render(&registry).unwrap();
+        // Screen only lightens the image
+        assert!(background
+            .pixels()
+            .zip(composite.pixels())
+            .all(|(below, above)| (0..3).all(|c| above.0[c] >= below.0[c])));
+        assert_ne!(background, composite);
+        // Adding a layer on top keeps the layers below
+        let mut islands = LayerRecipe::new("islands");
+        islands.opacity = 0.0;
+        recipe.layers.push(islands);
+        assert_eq!(recipe.render(&registry).unwrap(), composite);
+        // A mask fades the layer between the image below and the fully applied layer
+        lightning.mask = Some(MaskRecipe {
+            source: MaskSource::Algorithm {
+                mode: "clouds".to_string(),
+                params: BTreeMap::new(),
+            },
+            invert: true,
+        });
+        recipe.layers = vec![lightning];
+        let masked = recipe.render(&registry).unwrap();
+        assert_ne!(masked, composite);
+        assert!(masked.enumerate_pixels().all(|(x, y, pixel)| {
+            let (below, above) = (background.get_pixel(x, y).0, composite.get_pixel(x, y).0);
+            (0..3).all(|c| (below[c]..=above[c]).contains(&pixel.0[c]))
+        }));
+        let json = serde_json::to_string(&recipe).unwrap();
+        assert_eq!(serde_json::from_str::<Recipe>(&json).unwrap(), recipe);
+    }
+
+    #[test]
+    fn masks_are_created_from_their_sources() {
+        let registry: Registry<ChaCha8Rng> = Registry::builtin();
+        let json = r#"{"mode": "clouds", "width": 64, "height": 48, "seed": 7, "layers": [
+            {"mode": "squares", "mask": {"type": "algorithm", "mode": "flow"}},
+            {"mode": "squares", "mask": {"type": "noise", "octaves": 2, "invert": true}},
+            {"mode": "squares", "mask": {"type": "radial", "center": [0.25, 0.5]}},
+            {"mode": "squares", "mask": {"type": "pattern", "pattern_name": "checker",
+                "params": {"tiling": "brick"}}},
+            {"mode": "squares", "mask": {"type": "linear", "angle": 0.0}}
+        ]}"#;
+        let recipe: Recipe = serde_json::from_str(json).unwrap();
+        let noise = recipe.layers[1].mask.as_ref().unwrap();
+        assert!(noise.invert);
+        assert_eq!(
+            noise.source,
+            MaskSource::Noise {
+                frequency: default_mask_frequency(),
+                octaves: 2
+            }
+        );
+        let json = serde_json::to_string(&recipe).unwrap();
+        assert_eq!(serde_json::from_str::<Recipe>(&json).unwrap(), recipe);
+        assert!(recipe.render(&registry).is_ok());
+        // A gradient fades from the image below on the left to the full layer on the right
+        let mut base = recipe.clone();
+        base.layers.clear();
+        let mut unmasked = recipe.clone();
+        unmasked.layers = vec![LayerRecipe::new("squares")];
+        let mut masked = unmasked.clone();
+        masked.layers[0].mask = recipe.layers[4].mask.clone();
+        let (base, unmasked) = (
+            base.render(&registry).unwrap(),
+            unmasked.render(&registry).unwrap(),
+        );
+        let masked = masked.render(&registry).unwrap();
+        for y in 0..48 {
+            assert_eq!(masked.get_pixel(0, y), base.get_pixel(0, y));
+            assert_eq!(masked.get_pixel(63, y), unmasked.get_pixel(63, y));
+        }
+        // Pattern masks need a pattern
+        let empty = MaskRecipe::new(MaskSource::Pattern {
+            pattern: None,
+            pattern_name: None,
+            params: BTreeMap::new(),
+        });
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        assert!(empty.render(&registry, &mut rng, (64, 48), false).is_err());
+        // Noise masks are limited to noise grids of a sensible size
+        for (frequency, octaves) in [(1.0, 4), (0.004, 12), (0.0, 4), (0.004, 0)] {
+            let noise = MaskRecipe::new(MaskSource::Noise { frequency, octaves });
+            assert!(noise.render(&registry, &mut rng, (64, 48), false).is_err());
+        }
+    }
+
+    #[test]
+    fn misspelled_keys_and_invalid_opacities_are_rejected() {
+        let parse = |layer: &str| {
+            let json = format!(r#"{{"mode": "clouds", "layers": [{}]}}"#, layer);
+            serde_json::from_str::<Recipe>(&json).map_err(|err| err.to_string())
+        };
+        assert!(parse(r#"{"mode": "flow", "opacity": 0.5, "mix_mode": "multiply"}"#).is_ok());
+        assert!(parse(r#"{"mode": "flow", "mask": {"type": "noise", "invert": true}}"#).is_ok());
+        for layer in [
+            r#"{"mode": "flow", "opactiy": 0.5}"#,
+            r#"{"mode": "flow", "mixmode": "multiply"}"#,
+            r#"{"mode": "flow", "mask": {"type": "noise", "inverted": true}}"#,
+            r#"{"mode": "flow", "mask": {"type": "linear", "frequency": 0.01}}"#,
+        ] {
+            let err = parse(layer).unwrap_err();
+            assert!(
+                ["opactiy", "mixmode", "inverted", "frequency"]
+                    .iter()
+                    .any(|key| err.contains(key)),
+                "{}",
+                err
+            );
+        }
+        let registry: Registry<ChaCha8Rng> = Registry::builtin();
+        let mut recipe = Recipe::new("clouds", 32, 24);
+        recipe.layers = vec![LayerRecipe::new("flow")];
+        for opacity in [-0.1, 1.5, f64::NAN, f64::INFINITY] {
+            recipe.layers[0].opacity = opacity;
+            let result = recipe.render(&registry);
+            assert!(
+                matches!(result, Err(Error::InvalidParameter { name, .. }) if name == "opacity")
+            );
+        }
+    }
+
+    #[test]
+    fn transparent_backgrounds_are_kept() {
+        let registry: Registry<ChaCha8Rng> = Registry::builtin();
+        let mut recipe = Recipe::new("lightning", 64, 48);
+        recipe.seed = Some(Seed::from(7));
+        recipe.transparent = true;
+        let img = recipe.render(&registry).unwrap();
+        assert!(img.pixels().any(|pixel| pixel.0[3] == 0));
+        assert!(img.pixels().any(|pixel| pixel.0[3] == 255));
+        let path = std::env::temp_dir().join(format!(
+            "procedural_wallpapers_transparent_{}.png",
+            std::process::id()
+        ));
+        recipe.save(&img, &path).unwrap();
+        let loaded = image::open(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap().to_rgba8(), img);
+        assert!(recipe.save(&img, &path.with_extension("jpg")).is_err());
+        // The transparent background of a layer shows the layers below
+        let mut layered = Recipe::new("clouds", 64, 48);
+        layered.seed = Some(Seed::from(7));
+        let background = layered.render(&registry).unwrap();
+        let mut lightning = LayerRecipe::new("lightning");
+        lightning.transparent = true;
+        layered.layers = vec![lightning];
+        let composite = layered.render(&registry).unwrap();
+        let changed = background
+            .pixels()
+            .zip(composite.pixels())
+            .filter(|(below, above)| below != above)
+            .count();
+        assert!(changed > 0 && changed < 64 * 48 / 2);
+        recipe.mode = "clouds".to_string();
+        assert!(matches!(
+            recipe.render(&registry),
+            Err(Error::InvalidParameter { .. })
+        ));
+    }
+
+    #[test]
+    fn invalid_parameters_are_rejected() {
+        let registry: Registry<ChaCha8Rng> = Registry::builtin();
+        let mut recipe = Recipe::new("flow", 64, 48);
+        recipe
+            .params
+            .insert("num_particles".to_string(), ParameterValue::Boolean(true));
+        assert!(matches!(
+            recipe.render(&registry),
+            Err(Error::InvalidParameter { .. })
+        ));
+    }
+}
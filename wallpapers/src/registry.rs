@@ -0,0 +1,259 @@
+//! A registry of all available algorithms, mapping stable names to constructors and metadata.
+//!
+//! Applications can enumerate the built-in algorithms with [`Registry::builtin`] and add their
+//! own [`Algorithm`] implementations with [`Registry::register`].
+use crate::algorithms::*;
+use crate::patterns::pattern::Patterns;
+use crate::Error;
+use rand::Rng;
+
+const BAGYONI: &str = "Attila Bagyoni, 2018 (ported to Rust by Lukas Kirschner)";
+const KIRSCHNER: &str = "Lukas Kirschner";
+
+/// A registered algorithm
+pub struct RegistryEntry<R: Rng> {
+    /// The stable name that is used to select the algorithm, e.g. on the command line
+    pub name: &'static str,
+    /// A one-line description of the algorithm
+    pub description: &'static str,
+    /// The author of the algorithm
+    pub author: &'static str,
+    constructor: fn() -> Box<dyn Algorithm<R>>,
+}
+
+impl<R: Rng> RegistryEntry<R> {
+    pub fn new(
+        name: &'static str,
+        description: &'static str,
+        author: &'static str,
+        constructor: fn() -> Box<dyn Algorithm<R>>,
+    ) -> Self {
+        RegistryEntry {
+            name,
+            description,
+            author,
+            constructor,
+        }
+    }
+    /// Create a new instance of the algorithm with its default parameters
+    pub fn create(&self) -> Box<dyn Algorithm<R>> {
+        (self.constructor)()
+    }
+    /// Get all parameters that can be used to tune the algorithm
+    pub fn parameters(&self) -> Vec<Parameter> {
+        self.create().parameters()
+    }
+}
+
+/// A collection of algorithms that can be looked up by name
+pub struct Registry<R: Rng> {
+    entries: Vec<RegistryEntry<R>>,
+}
+
+impl<R: Rng> Default for Registry<R> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<R: Rng> Registry<R> {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Registry { entries: vec![] }
+    }
+    /// Create a registry containing all algorithms of this crate
+    pub fn builtin() -> Self {
+        let mut registry = Self::new();
+        for entry in [
+            RegistryEntry::new(
+                "clouds",
+                "Perlin noise fed into a sigmoid function",
+                BAGYONI,
+                || Box::new(Clouds::default()),
+            ),
+            RegistryEntry::new("flow", "Perlin flow field", BAGYONI, || {
+                Box::new(Flow::default())
+            }),
+            RegistryEntry::new(
+                "islands",
+                "Perlin noise fed into a cutoff function",
+                BAGYONI,
+                || Box::new(Islands::default()),
+            ),
+            RegistryEntry::new(
+                "lightning",
+                "Similar to a Brownian tree but faster to generate",
+                BAGYONI,
+                || Box::new(Lightning::default()),
+            ),
+            RegistryEntry::new(
+                "nearestpoint",
+                "Each pixel gets the color of its nearest point neighbor",
+                KIRSCHNER,
+                || Box::new(NearestPoint::default()),
+            ),
+            RegistryEntry::new("tangles", "Rec-tangles", BAGYONI, || {
+                Box::new(Tangles::default())
+            }),
+            RegistryEntry::new(
+                "cellularone",
+                "A cellular automaton that averages the colors of neighboring pixels",
+                KIRSCHNER,
+                || Box::new(CellularOne::default()),
+            ),
+            RegistryEntry::new(
+                "squares",
+                "Squares colored with the average of their neighbors plus a random offset",
+                KIRSCHNER,
+                || Box::new(SquaresOneDirection::new_nodir()),
+            ),
+            RegistryEntry::new(
+                "squareshor",
+                "The squares algorithm, prioritizing horizontal neighbors",
+                KIRSCHNER,
+                || Box::new(SquaresOneDirection::new_horiz()),
+            ),
+            RegistryEntry::new(
+                "squaresver",
+                "The squares algorithm, prioritizing vertical neighbors",
+                KIRSCHNER,
+                || Box::new(SquaresOneDirection::new_vert()),
+            ),
+            RegistryEntry::new(
+                "squaresdiag",
+                "The squares algorithm, prioritizing diagonal neighbors",
+                KIRSCHNER,
+                || Box::new(SquaresOneDirection::new_diag()),
+            ),
+            RegistryEntry::new(
+                "squares2",
+                "The squares algorithm with additional randomly colored squares",
+                KIRSCHNER,
+                || Box::new(SquaresOneDirection::new_nodir_randomized()),
+            ),
+            RegistryEntry::new(
+                "squares2h",
+                "The horizontal squares algorithm with additional randomly colored squares",
+                KIRSCHNER,
+                || Box::new(SquaresOneDirection::new_horiz_randomized()),
+            ),
+            RegistryEntry::new(
+                "squares2v",
+                "The vertical squares algorithm with additional randomly colored squares",
+                KIRSCHNER,
+                || Box::new(SquaresOneDirection::new_vert_randomized()),
+            ),
+            RegistryEntry::new(
+                "hexagons",
+                "The squares algorithm on a grid of hexagons",
+                KIRSCHNER,
+                || Box::new(Mosaic::new_hexagons()),
+            ),
+            RegistryEntry::new(
+                "triangles",
+                "The squares algorithm on a grid of triangles",
+                KIRSCHNER,
+                || Box::new(Mosaic::new_triangles()),
+            ),
+            RegistryEntry::new(
+                "rhombi",
+                "The squares algorithm on a grid of rhombi",
+                KIRSCHNER,
+                || Box::new(Mosaic::new_rhombi()),
+            ),
+            RegistryEntry::new(
+                "nearestgradient",
+                "Each pixel gets colored with a gradient to its nearest point neighbors",
+                KIRSCHNER,
+                || Box::new(NearestPoint::new_soft()),
+            ),
+            RegistryEntry::new(
+                "pattern",
+                "A fixed pattern is repeated over the complete image",
+                KIRSCHNER,
+                || Box::new(Patterns::diamond()),
+            ),
+            RegistryEntry::new(
+                "randompatterns",
+                "Patterns stamped at random positions, sizes, rotations and colors",
+                KIRSCHNER,
+                || Box::new(RandomPatterns::default()),
+            ),
+        ] {
+            registry.register(entry);
+        }
+        registry
+    }
+    /// Add an algorithm to the registry.
+    /// If an algorithm with the same name was registered before, it is replaced and returned.
+    pub fn register(&mut self, entry: RegistryEntry<R>) -> Option<RegistryEntry<R>> {
+        match self.entries.iter_mut().find(|e| e.name == entry.name) {
+            Some(existing) => Some(std::mem::replace(existing, entry)),
+            None => {
+                self.entries.push(entry);
+                None
+            },
+        }
+    }
+    /// Get the algorithm with the given name
+    pub fn get(&self, name: &str) -> Option<&RegistryEntry<R>> {
+        self.entries.iter().find(|e| e.name == name)
+    }
+    /// Create a new instance of the algorithm with the given name
+    pub fn create(&self, name: &str) -> Result<Box<dyn Algorithm<R>>, Error> {
+        self.get(name)
+            .map(RegistryEntry::create)
+            .ok_or_else(|| Error::UnknownAlgorithm(name.to_string()))
+    }
+    /// Iterate over all registered algorithms in the order they were registered
+    pub fn entries(&self) -> impl Iterator<Item = &RegistryEntry<R>> {
+        self.entries.iter()
+    }
+    /// Iterate over the names of all registered algorithms
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.entries.iter().map(|e| e.name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ChaCha8Rng, RgbImage, SeedableRng};
+    use std::collections::HashSet;
+
+    #[test]
+    fn builtin_names_are_unique() {
+        let registry: Registry<ChaCha8Rng> = Registry::builtin();
+        let names: HashSet<&str> = registry.names().collect();
+        assert_eq!(names.len(), registry.entries().count());
+    }
+
+    #[test]
+    fn builtin_algorithms_build_small_images() {
+        let registry: Registry<ChaCha8Rng> = Registry::builtin();
+        for entry in registry.entries() {
+            let mut img = RgbImage::new(64, 48);
+            let mut rng = ChaCha8Rng::seed_from_u64(123456);
+            entry
+                .create()
+                .build(&mut rng, &mut img)
+                .unwrap_or_else(|err| panic!("{} failed: {}", entry.name, err));
+        }
+    }
+
+    #[test]
+    fn register_replaces_existing_entries() {
+        let mut registry: Registry<ChaCha8Rng> = Registry::builtin();
+        let count = registry.entries().count();
+        let old = registry.register(RegistryEntry::new("flow", "Custom flow", "Me", || {
+            Box::new(Flow::default())
+        }));
+        assert!(old.is_some());
+        assert_eq!(registry.entries().count(), count);
+        assert_eq!(registry.get("flow").unwrap().description, "Custom flow");
+        assert!(matches!(
+            registry.create("nonexistent"),
+            Err(Error::UnknownAlgorithm(_))
+        ));
+    }
+}
@@ -0,0 +1,191 @@
+//! Seeds for the random number generator.
+//!
+//! A seed is a 64-bit number. It can be given as decimal number (`123456`), as hex number
+//! (`0x1e240`) or as arbitrary string (`monday-standup`), which is hashed to a number with 64-bit
+//! FNV-1a. The hash is part of the format and will not change between versions.
+//!
+//! Algorithms derive independent [`Streams`] from their random number generator for each stage,
+//! so that changing one stage (e.g. the number of particles) does not change the random choices
+//! of all other stages.
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::convert::Infallible;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// A seed for the random number generator
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Seed(u64);
+
+impl Seed {
+    /// Pick a random seed from the entropy source of the operating system
+    pub fn random() -> Self {
+        Seed(rand::random())
+    }
+    /// Hash the given string to a seed
+    pub fn from_text(text: &str) -> Self {
+        Seed(fnv1a(text.as_bytes()))
+    }
+    pub fn value(&self) -> u64 {
+        self.0
+    }
+}
+
+/// The 64-bit FNV-1a hash of the given bytes
+fn fnv1a(bytes: &[u8]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const PRIME: u64 = 0x100000001b3;
+    bytes.iter().fold(OFFSET_BASIS, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(PRIME)
+    })
+}
+
+/// The SplitMix64 finalizer, which spreads small differences of the input over all bits
+fn mix(mut value: u64) -> u64 {
+    value = (value ^ (value >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94d049bb133111eb);
+    value ^ (value >> 31)
+}
+
+/// Named random number sub-streams derived from a single master seed.
+/// Each name gives an independent stream, no matter how many values other streams consume.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Streams {
+    base: u64,
+}
+
+impl Streams {
+    pub fn new(seed: Seed) -> Self {
+        Streams { base: seed.0 }
+    }
+    /// Derive the streams from the given random number generator, which consumes exactly one
+    /// value of it
+    pub fn from_rng(rng: &mut impl Rng) -> Self {
+        Streams { base: rng.gen() }
+    }
+    /// Get the seed of the stream with the given name
+    pub fn seed(&self, name: &str) -> Seed {
+        Seed(mix(self.base ^ fnv1a(name.as_bytes())))
+    }
+    /// Get a random number generator for the stream with the given name
+    pub fn rng(&self, name: &str) -> ChaCha8Rng {
+        ChaCha8Rng::seed_from_u64(self.seed(name).0)
+    }
+    /// Get the streams of a nested stage with the given name, e.g. a layer of a composition
+    pub fn substreams(&self, name: &str) -> Streams {
+        Streams::new(self.seed(name))
+    }
+}
+
+impl From<u64> for Seed {
+    fn from(value: u64) -> Self {
+        Seed(value)
+    }
+}
+
+impl FromStr for Seed {
+    type Err = Infallible;
+
+    /// Parse a decimal or hex (`0x` prefix) number. Any other string is hashed.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X"));
+        Ok(
+            match (s.parse::<u64>(), hex.map(|h| u64::from_str_radix(h, 16))) {
+                (Ok(value), _) | (_, Some(Ok(value))) => Seed(value),
+                _ => Seed::from_text(s),
+            },
+        )
+    }
+}
+
+impl Display for Seed {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Serialize for Seed {
+    /// Seeds that do not fit into a signed 64-bit integer are written as hex string,
+    /// because formats like TOML do not support larger integers
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match i64::try_from(self.0) {
+            Ok(value) => serializer.serialize_i64(value),
+            Err(_) => serializer.serialize_str(&format!("{:#x}", self.0)),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Seed {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        /// All representations of seeds in serialized data
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Number(u64),
+            Text(String),
+        }
+        Ok(match Repr::deserialize(deserializer)? {
+            Repr::Number(value) => Seed(value),
+            Repr::Text(text) => text.parse().unwrap_or_else(|never| match never {}),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_numbers_and_strings() {
+        assert_eq!("0".parse::<Seed>().unwrap().value(), 0);
+        assert_eq!(
+            "18446744073709551615".parse::<Seed>().unwrap().value(),
+            u64::MAX
+        );
+        assert_eq!("0x1e240".parse::<Seed>().unwrap().value(), 123456);
+        assert_eq!("".parse::<Seed>().unwrap().value(), 0xcbf29ce484222325);
+        assert_eq!("a".parse::<Seed>().unwrap().value(), 0xaf63dc4c8601ec8c);
+        assert_eq!(
+            "monday-standup".parse::<Seed>().unwrap(),
+            Seed::from_text("monday-standup")
+        );
+        assert_ne!("-1".parse::<Seed>().unwrap().value(), u64::MAX);
+    }
+
+    #[test]
+    fn streams_are_independent() {
+        let streams = Streams::new(Seed(123456));
+        assert_eq!(streams.seed("points"), streams.seed("points"));
+        assert_ne!(streams.seed("points"), streams.seed("palette"));
+        assert_ne!(
+            streams.seed("points"),
+            Streams::new(Seed(123457)).seed("points")
+        );
+        let mut points = streams.rng("points");
+        let first: u64 = points.gen();
+        let _: u64 = streams.rng("palette").gen();
+        assert_eq!(first, streams.rng("points").gen::<u64>());
+        assert_ne!(
+            streams.substreams("layer").seed("points"),
+            streams.seed("points")
+        );
+    }
+
+    #[test]
+    fn large_seeds_are_serialized_as_hex() {
+        #[derive(Serialize, Deserialize)]
+        struct Wrapper {
+            seed: Seed,
+        }
+        let text = serde_json::to_string(&Wrapper {
+            seed: Seed(u64::MAX),
+        })
+        .unwrap();
+        assert_eq!(text, r#"{"seed":"0xffffffffffffffff"}"#);
+        for seed in [Seed(42), Seed(u64::MAX)] {
+            let text = serde_json::to_string(&Wrapper { seed }).unwrap();
+            assert_eq!(serde_json::from_str::<Wrapper>(&text).unwrap().seed, seed);
+        }
+    }
+}
@@ -0,0 +1,177 @@
+//! Conversions between sRGB and color spaces with separate hue, colorfulness and lightness.
+//!
+//! [`Oklch`] is the polar form of the perceptual OKLab color space by Björn Ottosson, where equal
+//! changes of lightness or chroma look about equally large for all hues. [`Hsv`] is the simpler
+//! hue, saturation and value model of sRGB.
+
+/// Convert an sRGB channel to linear light
+fn to_linear(channel: u8) -> f64 {
+    let c = channel as f64 / 255.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Convert a channel in linear light to sRGB
+fn from_linear(channel: f64) -> u8 {
+    let c = if channel <= 0.003_130_8 {
+        12.92 * channel
+    } else {
+        1.055 * channel.powf(1.0 / 2.4) - 0.055
+    };
+    (c * 255.0).round().clamp(0.0, 255.0) as u8
+}
+
+/// A color given as lightness, chroma and hue in the OKLab color space
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Oklch {
+    /// The perceived lightness from 0 (black) to 1 (white)
+    pub lightness: f64,
+    /// The colorfulness from 0 (grey), where the most colorful sRGB colors have a chroma of ~0.32
+    pub chroma: f64,
+    /// The hue angle in degrees
+    pub hue: f64,
+}
+
+impl Oklch {
+    pub fn from_rgb(color: [u8; 3]) -> Self {
+        let [r, g, b] = color.map(to_linear);
+        let l = (0.412_221_470_8 * r + 0.536_332_536_3 * g + 0.051_445_992_9 * b).cbrt();
+        let m = (0.211_903_498_2 * r + 0.680_699_545_1 * g + 0.107_396_956_6 * b).cbrt();
+        let s = (0.088_302_461_9 * r + 0.281_718_837_6 * g + 0.629_978_700_5 * b).cbrt();
+        let lightness = 0.210_454_255_3 * l + 0.793_617_785 * m - 0.004_072_046_8 * s;
+        let a = 1.977_998_495_1 * l - 2.428_592_205 * m + 0.450_593_709_9 * s;
+        let b = 0.025_904_037_1 * l + 0.782_771_766_2 * m - 0.808_675_766 * s;
+        Oklch {
+            lightness,
+            chroma: a.hypot(b),
+            hue: b.atan2(a).to_degrees().rem_euclid(360.0),
+        }
+    }
+    /// Convert the color to linear sRGB, whose channels are outside of 0..=1 if the color cannot
+    /// be displayed
+    fn to_linear_rgb(self) -> [f64; 3] {
+        let (a, b) = (
+            self.chroma * self.hue.to_radians().cos(),
+            self.chroma * self.hue.to_radians().sin(),
+        );
+        let l = (self.lightness + 0.396_337_777_4 * a + 0.215_803_757_3 * b).powi(3);
+        let m = (self.lightness - 0.105_561_345_8 * a - 0.063_854_172_8 * b).powi(3);
+        let s = (self.lightness - 0.089_484_177_5 * a - 1.291_485_548 * b).powi(3);
+        [
+            4.076_741_662_1 * l - 3.307_711_591_3 * m + 0.230_969_929_2 * s,
+            -1.268_438_004_6 * l + 2.609_757_401_1 * m - 0.341_319_396_5 * s,
+            -0.004_196_086_3 * l - 0.703_418_614_7 * m + 1.707_614_701 * s,
+        ]
+    }
+    /// Convert the color to sRGB. Colors that cannot be displayed lose chroma until they can,
+    /// which keeps their lightness and hue
+    pub fn to_rgb(&self) -> [u8; 3] {
+        let in_gamut = |color: Oklch| {
+            color
+                .to_linear_rgb()
+                .iter()
+                .all(|c| (-1e-4..=1.0001).contains(c))
+        };
+        let mut color = Oklch {
+            lightness: self.lightness.clamp(0.0, 1.0),
+            chroma: self.chroma.max(0.0),
+            hue: self.hue,
+        };
+        if !in_gamut(color) {
+            let (mut low, mut high) = (0.0, color.chroma);
+            for _ in 0..20 {
+                let chroma = (low + high) / 2.0;
+                if in_gamut(Oklch { chroma, ..color }) {
+                    low = chroma;
+                } else {
+                    high = chroma;
+                }
+            }
+            color.chroma = low;
+        }
+        color.to_linear_rgb().map(from_linear)
+    }
+}
+
+/// A color given as hue, saturation and value of the sRGB color model
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Hsv {
+    /// The hue angle in degrees
+    pub hue: f64,
+    /// The colorfulness from 0 (grey) to 1 (fully saturated)
+    pub saturation: f64,
+    /// The brightness from 0 (black) to 1 (the brightest color of the hue)
+    pub value: f64,
+}
+
+impl Hsv {
+    pub fn from_rgb(color: [u8; 3]) -> Self {
+        let [r, g, b] = color.map(|c| c as f64 / 255.0);
+        let max = r.max(g).max(b);
+        let delta = max - r.min(g).min(b);
+        let hue = if delta == 0.0 {
+            0.0
+        } else if max == r {
+            60.0 * ((g - b) / delta)
+        } else if max == g {
+            60.0 * ((b - r) / delta + 2.0)
+        } else {
+            60.0 * ((r - g) / delta + 4.0)
+        };
+        Hsv {
+            hue: hue.rem_euclid(360.0),
+            saturation: if max == 0.0 { 0.0 } else { delta / max },
+            value: max,
+        }
+    }
+    pub fn to_rgb(&self) -> [u8; 3] {
+        let (saturation, value) = (self.saturation.clamp(0.0, 1.0), self.value.clamp(0.0, 1.0));
+        let sector = self.hue.rem_euclid(360.0) / 60.0;
+        let chroma = value * saturation;
+        let x = chroma * (1.0 - (sector % 2.0 - 1.0).abs());
+        let (r, g, b) = match sector as u32 {
+            0 => (chroma, x, 0.0),
+            1 => (x, chroma, 0.0),
+            2 => (0.0, chroma, x),
+            3 => (0.0, x, chroma),
+            4 => (x, 0.0, chroma),
+            _ => (chroma, 0.0, x),
+        };
+        let min = value - chroma;
+        [r, g, b].map(|c| ((c + min) * 255.0).round().clamp(0.0, 255.0) as u8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colors_survive_the_round_trip() {
+        for color in [
+            [0, 0, 0],
+            [255, 255, 255],
+            [255, 0, 0],
+            [30, 144, 255],
+            [128, 64, 200],
+        ] {
+            assert_eq!(Oklch::from_rgb(color).to_rgb(), color);
+            assert_eq!(Hsv::from_rgb(color).to_rgb(), color);
+        }
+        let white = Oklch::from_rgb([255, 255, 255]);
+        assert!((white.lightness - 1.0).abs() < 1e-3 && white.chroma < 1e-3);
+        assert_eq!(Hsv::from_rgb([0, 255, 0]).hue, 120.0);
+        // A chroma that sRGB cannot show is reduced, keeping the lightness
+        let vivid = Oklch {
+            lightness: 0.5,
+            chroma: 1.0,
+            hue: 200.0,
+        };
+        let reduced = Oklch::from_rgb(vivid.to_rgb());
+        assert!((reduced.lightness - 0.5).abs() < 0.01);
+        assert!(reduced.chroma < 0.2);
+    }
+}
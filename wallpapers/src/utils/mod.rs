@@ -1,2 +1,4 @@
+pub mod color;
 /// Utilities and helper functions for drawing and noise
 pub mod perlin;
+pub mod scale;
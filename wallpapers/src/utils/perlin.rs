@@ -1,6 +1,17 @@
 /// Perlin Noise Implementation in Rust
+use crate::Error;
 use rand::Rng;
 
+/// Hash a grid position with the given seed, using the SplitMix64 finalizer
+fn hash(seed: u64, x: usize, y: usize) -> u64 {
+    let mut value = seed
+        ^ (x as u64).wrapping_mul(0x9e3779b97f4a7c15)
+        ^ (y as u64).wrapping_mul(0xc2b2ae3d27d4eb4f);
+    value = (value ^ (value >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94d049bb133111eb);
+    value ^ (value >> 31)
+}
+
 pub struct Perlin {
     /// Width of the perlin noise
     width: usize,
@@ -8,6 +19,8 @@ pub struct Perlin {
     height: usize,
     /// Noise Gradient
     gradient: Vec<Vec<(f64, f64)>>,
+    /// The width and height after which the fractal noise repeats itself, if it is periodic
+    period: Option<(f64, f64)>,
 }
 
 impl Perlin {
@@ -16,56 +29,150 @@ impl Perlin {
             width,
             height,
             gradient: vec![vec![(0.0, 0.0); height]; width],
+            period: None,
         }
     }
+    /// Get the dot product of the given offset to a grid point and the gradient at that grid point
     fn distance_along_gradient(
         &self,
-        x: f64,
-        y: f64,
+        dx: f64,
+        dy: f64,
         gridx: usize,
         gridy: usize,
-    ) -> Result<f64, String> {
+    ) -> Result<f64, Error> {
         if gridx >= self.width || gridy >= self.height {
-            Err(format!(
-                "Coordinates ({},{}) out of bounds for image of size ({},{})!",
-                gridx, gridy, self.width, self.height
-            ))
+            Err(Error::NoiseOutOfBounds {
+                x: gridx,
+                y: gridy,
+                width: self.width,
+                height: self.height,
+            })
         } else {
-            Ok((x - gridx as f64) * self.gradient[gridx][gridy].0
-                + (y - gridy as f64) * self.gradient[gridx][gridy].1)
+            Ok(dx * self.gradient[gridx][gridy].0 + dy * self.gradient[gridx][gridy].1)
         }
     }
     fn inter(x: f64, y: f64, weight: f64) -> f64 {
         let yweight: f64 = weight * weight * (2.0 - weight);
         x * (1.0 - yweight) + y * yweight
     }
-    /// Regenerate the noise
+    /// Create noise that is large enough to compute the fractal noise of the given depth and
+    /// base frequency for all coordinates up to the given width and height
+    pub fn for_fractal(width: f64, height: f64, freq: f64, depth: u32) -> Self {
+        let max_freq = freq * 2f64.powi(depth.saturating_sub(1) as i32);
+        Perlin::new(
+            (width * max_freq).ceil() as usize + 2,
+            (height * max_freq).ceil() as usize + 2,
+        )
+    }
+    /// Regenerate the noise.
+    /// The gradient of each grid point only depends on the random seed and its position, so noise
+    /// of different sizes that was generated from the same seed matches where it overlaps.
     pub fn regenerate_noise(&mut self, rng: &mut impl Rng) {
+        let seed: u64 = rng.gen();
         for x in 0..self.width {
             for y in 0..self.height {
-                let val: f64 = (rng.gen::<u32>() & 0xfff) as f64;
+                let val: f64 = (hash(seed, x, y) & 0xfff) as f64;
                 self.gradient[x][y].0 = val.sin();
                 self.gradient[x][y].1 = val.cos();
             }
         }
     }
+    /// Make the fractal noise repeat itself after the given width and height, so that images
+    /// drawn from it can be tiled seamlessly.
+    /// The frequency of each octave is adjusted slightly, so that a whole number of grid cells fits
+    /// into the period.
+    pub fn set_period(&mut self, width: f64, height: f64) {
+        self.period = Some((width, height));
+    }
+    /// Interpolate between the gradients of the grid cell whose upper left corner is (x0, y0).
+    /// The gradients of the corners are looked up at the given grid columns and rows.
+    fn interpolate(
+        &self,
+        (x, y): (f64, f64),
+        (x0, y0): (f64, f64),
+        (gridx0, gridx1): (usize, usize),
+        (gridy0, gridy1): (usize, usize),
+    ) -> Result<f64, Error> {
+        let d1: f64 = self.distance_along_gradient(x - x0, y - y0, gridx0, gridy0)?;
+        let d2: f64 = self.distance_along_gradient(x - (x0 + 1.0), y - y0, gridx1, gridy0)?;
+        let d3: f64 = self.distance_along_gradient(x - x0, y - (y0 + 1.0), gridx0, gridy1)?;
+        let d4: f64 =
+            self.distance_along_gradient(x - (x0 + 1.0), y - (y0 + 1.0), gridx1, gridy1)?;
+        let i1: f64 = Perlin::inter(d1, d2, x - x0);
+        let i2: f64 = Perlin::inter(d3, d4, x - x0);
+        Ok(Perlin::inter(i1, i2, y - y0))
+    }
     /// Get the value of the perlin noise at the given coordinate
-    pub fn perlin(&self, x: f64, y: f64) -> Result<f64, String> {
-        let d1: f64 = self.distance_along_gradient(x, y, x as usize, y as usize)?;
-        let d2: f64 = self.distance_along_gradient(x, y, x as usize + 1, y as usize)?;
-        let d3: f64 = self.distance_along_gradient(x, y, x as usize, (y as usize) + 1)?;
-        let d4: f64 = self.distance_along_gradient(x, y, x as usize + 1, (y as usize) + 1)?;
-        let i1: f64 = Perlin::inter(d1, d2, x - x.floor());
-        let i2: f64 = Perlin::inter(d3, d4, x - x.floor());
-        Ok(Perlin::inter(i1, i2, y - y.floor()))
+    pub fn perlin(&self, x: f64, y: f64) -> Result<f64, Error> {
+        let (gridx, gridy) = (x as usize, y as usize);
+        self.interpolate(
+            (x, y),
+            (gridx as f64, gridy as f64),
+            (gridx, gridx + 1),
+            (gridy, gridy + 1),
+        )
+    }
+    /// Get the value of the perlin noise at the given coordinate, wrapping around after the given
+    /// number of grid cells
+    fn periodic_perlin(
+        &self,
+        x: f64,
+        y: f64,
+        cells_x: usize,
+        cells_y: usize,
+    ) -> Result<f64, Error> {
+        let (x0, y0) = (x.floor(), y.floor());
+        let gridx = (x0 as i64).rem_euclid(cells_x as i64) as usize;
+        let gridy = (y0 as i64).rem_euclid(cells_y as i64) as usize;
+        self.interpolate(
+            (x, y),
+            (x0, y0),
+            (gridx, (gridx + 1) % cells_x),
+            (gridy, (gridy + 1) % cells_y),
+        )
+    }
+    /// Get the value of a single octave of the fractal noise
+    fn octave(&self, x: f64, y: f64, freq: f64) -> Result<f64, Error> {
+        match self.period {
+            None => self.perlin(x * freq, y * freq),
+            Some((width, height)) => {
+                let cells_x = (width * freq).round().max(1.0);
+                let cells_y = (height * freq).round().max(1.0);
+                self.periodic_perlin(
+                    x * cells_x / width,
+                    y * cells_y / height,
+                    cells_x as usize,
+                    cells_y as usize,
+                )
+            },
+        }
     }
     /// Recursive Fractal Implementation
-    pub fn fractal(&self, x: f64, y: f64, freq: f64, depth: u32) -> Result<f64, String> {
+    pub fn fractal(&self, x: f64, y: f64, freq: f64, depth: u32) -> Result<f64, Error> {
         match depth {
             0 => Ok(0.0),
-            d => {
-                Ok(self.perlin(x * freq, y * freq)? + self.fractal(x, y, freq * 2.0, d - 1)? / 2.0)
-            },
+            d => Ok(self.octave(x, y, freq)? + self.fractal(x, y, freq * 2.0, d - 1)? / 2.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ChaCha8Rng, SeedableRng};
+
+    #[test]
+    fn periodic_noise_repeats() {
+        let (width, height) = (300.0, 170.0);
+        let mut perlin = Perlin::for_fractal(width, height, 0.013, 4);
+        perlin.regenerate_noise(&mut ChaCha8Rng::seed_from_u64(3));
+        perlin.set_period(width, height);
+        for (x, y) in [(0.0, 0.0), (12.5, 80.25), (299.0, 3.0), (150.0, 169.5)] {
+            let value = perlin.fractal(x, y, 0.013, 4).unwrap();
+            let wrapped_x = perlin.fractal(x + width, y, 0.013, 4).unwrap();
+            let wrapped_y = perlin.fractal(x, y + height, 0.013, 4).unwrap();
+            assert!((value - wrapped_x).abs() < 1e-9);
+            assert!((value - wrapped_y).abs() < 1e-9);
         }
     }
 }
@@ -0,0 +1,83 @@
+//! Resolution-independent coordinates.
+//!
+//! Algorithms place their features in virtual units, where the shorter side of the image is always
+//! [`VIRTUAL_SIZE`] units long. Rendering the same seed at a different resolution with the same
+//! aspect ratio therefore gives the same picture at a different level of detail, and a 1920x1080
+//! image uses exactly one unit per pixel.
+use image::RgbImage;
+
+/// The length of the shorter image side in virtual units
+pub const VIRTUAL_SIZE: f64 = 1080.0;
+
+/// The mapping between pixels and virtual units of an image
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Scale {
+    /// The number of virtual units per pixel
+    units_per_pixel: f64,
+    /// The width of the image in pixels
+    width: u32,
+    /// The height of the image in pixels
+    height: u32,
+}
+
+impl Scale {
+    pub fn new(width: u32, height: u32) -> Self {
+        Scale {
+            units_per_pixel: VIRTUAL_SIZE / width.min(height).max(1) as f64,
+            width,
+            height,
+        }
+    }
+    /// Get the scale of the given image
+    pub fn of(img: &RgbImage) -> Self {
+        Scale::new(img.width(), img.height())
+    }
+    /// Get the number of virtual units per pixel
+    pub fn units_per_pixel(&self) -> f64 {
+        self.units_per_pixel
+    }
+    /// Get the width of the image in virtual units
+    pub fn width(&self) -> f64 {
+        self.width as f64 * self.units_per_pixel
+    }
+    /// Get the height of the image in virtual units
+    pub fn height(&self) -> f64 {
+        self.height as f64 * self.units_per_pixel
+    }
+    /// Get the area of the image in square virtual units
+    pub fn area(&self) -> f64 {
+        self.width() * self.height()
+    }
+    /// Convert a pixel coordinate or length to virtual units
+    pub fn to_units(&self, pixels: f64) -> f64 {
+        pixels * self.units_per_pixel
+    }
+    /// Convert a coordinate or length in virtual units to pixels
+    pub fn to_pixels(&self, units: f64) -> f64 {
+        units / self.units_per_pixel
+    }
+    /// Get the pixel that contains the given virtual coordinate, clamped to the image
+    pub fn pixel_at(&self, x: f64, y: f64) -> (u32, u32) {
+        let clamp = |pixels: f64, size: u32| (pixels.max(0.0) as u32).min(size.saturating_sub(1));
+        (
+            clamp(self.to_pixels(x), self.width),
+            clamp(self.to_pixels(y), self.height),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shorter_side_has_virtual_size() {
+        assert_eq!(Scale::new(1920, 1080).units_per_pixel(), 1.0);
+        assert_eq!(Scale::new(3840, 2160).width(), 1920.0);
+        assert_eq!(Scale::new(1080, 2400).height(), 2400.0);
+        assert_eq!(
+            Scale::new(3840, 2160).pixel_at(1919.9, 1080.0),
+            (3839, 2159)
+        );
+    }
+}